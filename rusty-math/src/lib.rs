@@ -2,6 +2,7 @@ mod errors;
 mod matrix2;
 mod matrix3;
 mod matrix4;
+mod quaternion;
 mod tuple2;
 mod tuple3;
 mod tuple4;
@@ -11,6 +12,7 @@ pub use errors::*;
 pub use matrix2::Matrix2;
 pub use matrix3::Matrix3;
 pub use matrix4::Matrix4;
+pub use quaternion::Quaternion;
 pub use tuple2::Tuple2;
 pub use tuple3::Tuple3;
 pub use tuple4::Tuple4;
\ No newline at end of file
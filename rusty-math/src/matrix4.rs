@@ -1,5 +1,6 @@
 use std::ops::Mul;
 use crate::Matrix3;
+use crate::Quaternion;
 use crate::Tuple4;
 use crate::errors::{MatrixInversionError, SubmatrixIndexError};
 
@@ -196,6 +197,43 @@ impl Matrix4 {
   }
 }
 
+impl Matrix4 {
+  /// Splits an affine transform into `translation * rotation * scale`, where
+  /// `scale` is symmetric and may carry shear (polar decomposition).
+  pub fn decompose(&self) -> Result<(Tuple4, Quaternion, Matrix4), MatrixInversionError> {
+    let translation = Tuple4::vector(self.c3.x(), self.c3.y(), self.c3.z());
+    let linear = Matrix4::new(self.c0, self.c1, self.c2, Tuple4::new(0.0, 0.0, 0.0, 1.0));
+
+    // average the matrix with its inverse transpose until it is orthonormal
+    let mut rotation = linear;
+    for _ in 0..100 {
+      let inverse_transpose = rotation.inverse()?.transposed();
+      let next = Matrix4::new(
+        (rotation.c0 + inverse_transpose.c0) * 0.5,
+        (rotation.c1 + inverse_transpose.c1) * 0.5,
+        (rotation.c2 + inverse_transpose.c2) * 0.5,
+        Tuple4::new(0.0, 0.0, 0.0, 1.0)
+      );
+      let change = (next.c0 - rotation.c0).length() + (next.c1 - rotation.c1).length() + (next.c2 - rotation.c2).length();
+      rotation = next;
+      if change < 0.00001 {
+        break;
+      }
+    }
+    // fold reflections into the scale so that the rotation is proper
+    if rotation.determinant() < 0.0 {
+      rotation = Matrix4::new(rotation.c0 * -1.0, rotation.c1 * -1.0, rotation.c2 * -1.0, Tuple4::new(0.0, 0.0, 0.0, 1.0));
+    }
+    let scale = rotation.inverse()? * linear;
+    Ok((translation, Quaternion::from_matrix(&rotation), scale))
+  }
+
+  /// Inverse of `decompose`.
+  pub fn compose(translation: Tuple4, rotation: Quaternion, scale: Matrix4) -> Self {
+    Matrix4::translation(translation.x(), translation.y(), translation.z()) * rotation.to_matrix() * scale
+  }
+}
+
 impl Mul<Matrix4> for Matrix4 {
   type Output = Self;
 
@@ -598,4 +636,23 @@ mod tests {
     let point = Tuple4::point(1.0, 0.0, 1.0);
     assert!(cmp_tuple4(Tuple4::point(15.0, 0.0, 7.0), transform * point));
   }
+
+  #[test]
+  fn implements_decompose() {
+    let close = |a: Matrix4, b: Matrix4| {
+      [(a.c0, b.c0), (a.c1, b.c1), (a.c2, b.c2), (a.c3, b.c3)].iter().all(|(x, y)| (*x - *y).length() < 0.0001)
+    };
+    let m = Matrix4::scaling(2.0, 0.5, 3.0).rotate_y(0.7).rotate_x(-0.2).translate(1.0, -2.0, 5.0);
+    let (translation, rotation, scale) = m.decompose().unwrap();
+    assert_eq!(Tuple4::vector(1.0, -2.0, 5.0), translation);
+    assert!(close(Matrix4::rotation_x(-0.2) * Matrix4::rotation_y(0.7), rotation.to_matrix()));
+    assert!(close(Matrix4::scaling(2.0, 0.5, 3.0), scale));
+    assert!(close(m, Matrix4::compose(translation, rotation, scale)));
+
+    // mirroring ends up in the scale
+    let m = Matrix4::scaling(-1.0, 1.0, 1.0).rotate_z(0.5);
+    let (_, rotation, scale) = m.decompose().unwrap();
+    assert!(close(m, Matrix4::compose(Tuple4::vector(0.0, 0.0, 0.0), rotation, scale)));
+    assert!(Matrix4::scaling(0.0, 1.0, 1.0).decompose().is_err());
+  }
 }
\ No newline at end of file
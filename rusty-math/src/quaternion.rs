@@ -0,0 +1,189 @@
+use std::ops::{Add, Mul, Neg};
+use crate::Matrix4;
+use crate::Tuple4;
+
+/// Unit quaternion representing a rotation, with vector part (x, y, z) and
+/// scalar part w.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+  pub x: f32,
+  pub y: f32,
+  pub z: f32,
+  pub w: f32
+}
+
+impl Quaternion {
+  pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+    Self { x, y, z, w }
+  }
+
+  pub fn identity() -> Self {
+    Self::new(0.0, 0.0, 0.0, 1.0)
+  }
+
+  /// Rotation by `angle` radians around `axis`.
+  pub fn from_axis_angle(axis: Tuple4, angle: f32) -> Self {
+    let axis = axis.normalized();
+    let (sin, cos) = (angle / 2.0).sin_cos();
+    Self::new(axis.x() * sin, axis.y() * sin, axis.z() * sin, cos)
+  }
+
+  /// Rotation part of `m`, whose upper 3x3 block must be orthonormal.
+  pub fn from_matrix(m: &Matrix4) -> Self {
+    let element = |row: usize, column: usize| -> f32 {
+      let c = match column {
+        0 => m.c0,
+        1 => m.c1,
+        _ => m.c2
+      };
+      match row {
+        0 => c.x(),
+        1 => c.y(),
+        _ => c.z()
+      }
+    };
+    let trace = element(0, 0) + element(1, 1) + element(2, 2);
+    let q = if trace > 0.0 {
+      let s = (trace + 1.0).sqrt() * 2.0;
+      Self::new(
+        (element(2, 1) - element(1, 2)) / s,
+        (element(0, 2) - element(2, 0)) / s,
+        (element(1, 0) - element(0, 1)) / s,
+        0.25 * s
+      )
+    } else if element(0, 0) > element(1, 1) && element(0, 0) > element(2, 2) {
+      let s = (1.0 + element(0, 0) - element(1, 1) - element(2, 2)).sqrt() * 2.0;
+      Self::new(
+        0.25 * s,
+        (element(0, 1) + element(1, 0)) / s,
+        (element(0, 2) + element(2, 0)) / s,
+        (element(2, 1) - element(1, 2)) / s
+      )
+    } else if element(1, 1) > element(2, 2) {
+      let s = (1.0 + element(1, 1) - element(0, 0) - element(2, 2)).sqrt() * 2.0;
+      Self::new(
+        (element(0, 1) + element(1, 0)) / s,
+        0.25 * s,
+        (element(1, 2) + element(2, 1)) / s,
+        (element(0, 2) - element(2, 0)) / s
+      )
+    } else {
+      let s = (1.0 + element(2, 2) - element(0, 0) - element(1, 1)).sqrt() * 2.0;
+      Self::new(
+        (element(0, 2) + element(2, 0)) / s,
+        (element(1, 2) + element(2, 1)) / s,
+        0.25 * s,
+        (element(1, 0) - element(0, 1)) / s
+      )
+    };
+    q.normalized()
+  }
+
+  pub fn to_matrix(&self) -> Matrix4 {
+    let Self { x, y, z, w } = *self;
+    Matrix4::new(
+      Tuple4::new(1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + z * w), 2.0 * (x * z - y * w), 0.0),
+      Tuple4::new(2.0 * (x * y - z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + x * w), 0.0),
+      Tuple4::new(2.0 * (x * z + y * w), 2.0 * (y * z - x * w), 1.0 - 2.0 * (x * x + y * y), 0.0),
+      Tuple4::new(0.0, 0.0, 0.0, 1.0)
+    )
+  }
+
+  pub fn dot(lhs: Self, rhs: Self) -> f32 {
+    lhs.x * rhs.x + lhs.y * rhs.y + lhs.z * rhs.z + lhs.w * rhs.w
+  }
+
+  pub fn length(&self) -> f32 {
+    Self::dot(*self, *self).sqrt()
+  }
+
+  pub fn normalized(&self) -> Self {
+    *self * (1.0 / self.length())
+  }
+
+  /// Spherical linear interpolation along the shorter arc from `a` (t = 0) to `b` (t = 1).
+  pub fn slerp(a: Self, b: Self, t: f32) -> Self {
+    let mut cos_theta = Self::dot(a, b);
+    let b = if cos_theta < 0.0 {
+      cos_theta = -cos_theta;
+      -b
+    } else {
+      b
+    };
+    if cos_theta > 0.9995 {
+      // nearly parallel: fall back to normalized linear interpolation
+      return (a * (1.0 - t) + b * t).normalized();
+    }
+    let theta = cos_theta.clamp(-1.0, 1.0).acos();
+    let sin_theta = theta.sin();
+    a * (((1.0 - t) * theta).sin() / sin_theta) + b * ((t * theta).sin() / sin_theta)
+  }
+}
+
+impl Add for Quaternion {
+  type Output = Self;
+
+  fn add(self, rhs: Self) -> Self::Output {
+    Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z, self.w + rhs.w)
+  }
+}
+
+impl Mul<f32> for Quaternion {
+  type Output = Self;
+
+  fn mul(self, rhs: f32) -> Self::Output {
+    Self::new(self.x * rhs, self.y * rhs, self.z * rhs, self.w * rhs)
+  }
+}
+
+impl Neg for Quaternion {
+  type Output = Self;
+
+  fn neg(self) -> Self::Output {
+    Self::new(-self.x, -self.y, -self.z, -self.w)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Matrix4, Quaternion, Tuple4};
+  use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+  fn cmp_matrix(a: Matrix4, b: Matrix4) -> bool {
+    [(a.c0, b.c0), (a.c1, b.c1), (a.c2, b.c2), (a.c3, b.c3)].iter().all(|(x, y)| (*x - *y).length() < 0.0001)
+  }
+
+  #[test]
+  fn implements_to_matrix() {
+    assert!(cmp_matrix(Matrix4::identity(), Quaternion::identity().to_matrix()));
+    let q = Quaternion::from_axis_angle(Tuple4::vector(0.0, 1.0, 0.0), FRAC_PI_2);
+    assert!(cmp_matrix(Matrix4::rotation_y(FRAC_PI_2), q.to_matrix()));
+    let q = Quaternion::from_axis_angle(Tuple4::vector(1.0, 0.0, 0.0), 0.3);
+    assert!(cmp_matrix(Matrix4::rotation_x(0.3), q.to_matrix()));
+  }
+
+  #[test]
+  fn implements_from_matrix() {
+    let rotations = [
+      Matrix4::identity(),
+      Matrix4::rotation_x(2.5),
+      Matrix4::rotation_y(-1.0),
+      Matrix4::rotation_z(PI),
+      Matrix4::rotation_x(0.4).rotate_y(3.0).rotate_z(-2.0)
+    ];
+    for m in rotations {
+      assert!(cmp_matrix(m, Quaternion::from_matrix(&m).to_matrix()), "{:?}", m);
+    }
+  }
+
+  #[test]
+  fn implements_slerp() {
+    let a = Quaternion::identity();
+    let b = Quaternion::from_axis_angle(Tuple4::vector(0.0, 0.0, 1.0), FRAC_PI_2);
+    assert!(cmp_matrix(Matrix4::rotation_z(FRAC_PI_4), Quaternion::slerp(a, b, 0.5).to_matrix()));
+    assert!(cmp_matrix(a.to_matrix(), Quaternion::slerp(a, b, 0.0).to_matrix()));
+    assert!(cmp_matrix(b.to_matrix(), Quaternion::slerp(a, b, 1.0).to_matrix()));
+    // the shorter arc is taken for quaternions on opposite hemispheres
+    assert!(cmp_matrix(Matrix4::rotation_z(FRAC_PI_4), Quaternion::slerp(a, -b, 0.5).to_matrix()));
+  }
+}
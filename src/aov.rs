@@ -0,0 +1,258 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::{Color, BLACK};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render::{RenderSettings, BAND_ROWS};
+use crate::rng::Rng;
+use crate::world::World;
+
+use std::sync::Mutex;
+use std::thread;
+
+/// Auxiliary passes rendered next to the beauty image, for compositing,
+/// debugging and denoising. They hold raw values, so normals and positions may
+/// be negative; pixels that see nothing stay black.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aovs {
+  /// Camera-space depth of the hit, in all three channels.
+  pub depth: Canvas,
+  /// World-space normal facing the camera, as used for shading.
+  pub normal: Canvas,
+  /// Surface color before lighting.
+  pub albedo: Canvas,
+  /// A distinct color per object, see `id_color`.
+  pub object_id: Canvas,
+  /// A distinct color per material, numbered in order of first use.
+  pub material_id: Canvas,
+  /// Texture coordinates in red and green.
+  pub uv: Canvas,
+  /// World-space hit position.
+  pub position: Canvas
+}
+
+impl Aovs {
+  /// Every pass with its name, in a fixed order.
+  pub fn passes(&self) -> [(&'static str, &Canvas); 7] {
+    [
+      ("depth", &self.depth),
+      ("normal", &self.normal),
+      ("albedo", &self.albedo),
+      ("object-id", &self.object_id),
+      ("material-id", &self.material_id),
+      ("uv", &self.uv),
+      ("position", &self.position)
+    ]
+  }
+}
+
+/// Renders the AOVs of the first hit along camera rays. Depth, normal, albedo,
+/// UV and position average the pixel's samples, taken at the same positions
+/// and times as the beauty image's, without lens blur; misses count as black.
+/// IDs cannot be blended, so they come from the ray through the pixel center.
+pub fn render_aovs(camera: &Camera, world: &World, settings: &RenderSettings) -> Aovs {
+  let (width, height) = (camera.hsize, camera.vsize);
+  let materials = material_ids(world);
+  let mut pixels = vec![Pixel::BLANK; width * height];
+  let bands = Mutex::new(pixels.chunks_mut((width * BAND_ROWS).max(1)).enumerate());
+  thread::scope(|scope| {
+    for _ in 0..settings.thread_count().min(height.div_ceil(BAND_ROWS)) {
+      scope.spawn(|| loop {
+        let next = bands.lock().unwrap().next();
+        let (band, chunk) = match next {
+          Some(next) => next,
+          None => break
+        };
+        for (i, pixel) in chunk.iter_mut().enumerate() {
+          let (x, y) = (i % width, band * BAND_ROWS + i / width);
+          *pixel = render_pixel(camera, world, settings, &materials, x, y);
+        }
+      });
+    }
+  });
+
+  let pass = |channel: fn(&Pixel) -> Color| {
+    let mut canvas = Canvas::new(width, height);
+    for (i, pixel) in pixels.iter().enumerate() {
+      canvas.write(i % width, i / width, &channel(pixel));
+    }
+    canvas
+  };
+  Aovs {
+    depth: pass(|p| p.depth),
+    normal: pass(|p| p.normal),
+    albedo: pass(|p| p.albedo),
+    object_id: pass(|p| p.object_id),
+    material_id: pass(|p| p.material_id),
+    uv: pass(|p| p.uv),
+    position: pass(|p| p.position)
+  }
+}
+
+/// Color standing for `id` in ID passes; neighbouring IDs get unrelated colors.
+pub fn id_color(id: usize) -> Color {
+  let mut rng = Rng::new(id as u64);
+  // keep IDs clear of black, which marks background
+  let mut channel = || 0.2 + 0.8 * rng.next_f32();
+  Color::new(channel(), channel(), channel())
+}
+
+/// Material ID of every object: the index of its material among the distinct
+/// materials in object order.
+fn material_ids(world: &World) -> Vec<usize> {
+  let mut materials: Vec<Material> = Vec::new();
+  world.objects.iter().map(|object| {
+    let material = object.get_material();
+    match materials.iter().position(|m| *m == material) {
+      Some(id) => id,
+      None => {
+        materials.push(material);
+        materials.len() - 1
+      }
+    }
+  }).collect()
+}
+
+/// The AOVs of one pixel, laid out like `Aovs`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pixel {
+  depth: Color,
+  normal: Color,
+  albedo: Color,
+  object_id: Color,
+  material_id: Color,
+  uv: Color,
+  position: Color
+}
+
+impl Pixel {
+  const BLANK: Pixel = Pixel {
+    depth: BLACK,
+    normal: BLACK,
+    albedo: BLACK,
+    object_id: BLACK,
+    material_id: BLACK,
+    uv: BLACK,
+    position: BLACK
+  };
+}
+
+fn render_pixel(
+  camera: &Camera,
+  world: &World,
+  settings: &RenderSettings,
+  materials: &[usize],
+  x: usize,
+  y: usize
+) -> Pixel {
+  let mut pixel = Pixel::BLANK;
+  if let Some(surface) = camera.ray_for_pixel(x as f32 + 0.5, y as f32 + 0.5).and_then(|r| world.hit(r)) {
+    pixel.object_id = id_color(surface.object);
+    pixel.material_id = id_color(materials[surface.object]);
+  }
+
+  let count = settings.samples_per_pixel.max(1);
+  let mut pixel_rng = Rng::for_pixel(settings.seed, x, y);
+  let rotation = (pixel_rng.next_f32(), pixel_rng.next_f32());
+  let weight = 1.0 / count as f32;
+  for index in 0..count {
+    let mut rng = Rng::for_sample(settings.seed, x, y, index);
+    let (u, v) = settings.pattern.sample(index, count, rotation, &mut rng);
+    let time = if camera.shutter_close > camera.shutter_open {
+      camera.shutter_time(rng.next_f32())
+    } else {
+      camera.shutter_open
+    };
+    let surface = match camera.ray_for_pixel(x as f32 + u, y as f32 + v).and_then(|r| world.hit(Ray { time, ..r })) {
+      Some(surface) => surface,
+      None => continue
+    };
+    let depth = camera.depth(surface.point);
+    let (n, p) = (surface.normal, surface.point);
+    let (tu, tv) = world.objects[surface.object].uv_at(p, time);
+    pixel.depth = pixel.depth + Color::new(depth, depth, depth) * weight;
+    pixel.normal = pixel.normal + Color::new(n.x(), n.y(), n.z()) * weight;
+    pixel.albedo = pixel.albedo + surface.material.color * weight;
+    pixel.uv = pixel.uv + Color::new(tu, tv, 0.0) * weight;
+    pixel.position = pixel.position + Color::new(p.x(), p.y(), p.z()) * weight;
+  }
+  pixel
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{id_color, render_aovs};
+  use crate::camera::Camera;
+  use crate::color::{Color, BLACK};
+  use crate::material::Material;
+  use crate::point_light::PointLight;
+  use crate::render::RenderSettings;
+  use crate::sampler::SamplePattern;
+  use crate::sphere::Sphere;
+  use crate::world::World;
+  use rm::{Matrix4, Tuple4};
+  use std::f32::consts::FRAC_PI_2;
+
+  fn scene() -> (Camera, World) {
+    let red = Material { color: Color::new(1.0, 0.0, 0.0), ..Material::default() };
+    // camera-space +x is to the left of the image
+    let mut left = Sphere::unit();
+    left.set_transform(Matrix4::translation(1.5, 0.0, 0.0));
+    left.set_material(red);
+    let mut right = Sphere::unit();
+    right.set_transform(Matrix4::translation(-1.5, 0.0, 0.0));
+    right.set_material(red);
+    let mut back = Sphere::unit();
+    back.set_transform(Matrix4::translation(0.0, 0.0, -20.0) * Matrix4::scaling(8.0, 8.0, 8.0));
+    let light = PointLight::new(Tuple4::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+    let mut camera = Camera::new(20, 10, FRAC_PI_2);
+    camera.set_transform(Matrix4::translation(0.0, 0.0, -4.0)).unwrap();
+    (camera, World::new(vec![left, right, back], vec![Box::new(light)]))
+  }
+
+  #[test]
+  fn implements_aovs() {
+    let (camera, world) = scene();
+    let aovs = render_aovs(&camera, &world, &RenderSettings::default());
+    // (15, 4) looks at the right sphere head on, (10, 4) between the spheres at the back one
+    let near = camera.ray_for_pixel(15.5, 4.5).and_then(|r| world.hit(r)).unwrap();
+    assert_eq!(1, near.object);
+    let depth = aovs.depth.pixel_at(15, 4);
+    assert!((depth.r() - camera.depth(near.point)).abs() < 0.0001 && depth.r() == depth.b());
+    assert!(aovs.depth.pixel_at(10, 4).r() > depth.r());
+
+    let n = near.normal;
+    assert_eq!(Color::new(n.x(), n.y(), n.z()), aovs.normal.pixel_at(15, 4));
+    let p = near.point;
+    assert_eq!(Color::new(p.x(), p.y(), p.z()), aovs.position.pixel_at(15, 4));
+    assert_eq!(Color::new(1.0, 0.0, 0.0), aovs.albedo.pixel_at(15, 4));
+    assert_eq!(Material::default().color, aovs.albedo.pixel_at(10, 4));
+
+    // objects differ, the two red spheres share a material
+    assert_eq!(id_color(1), aovs.object_id.pixel_at(15, 4));
+    assert_eq!(id_color(0), aovs.object_id.pixel_at(4, 4));
+    assert_eq!(id_color(0), aovs.material_id.pixel_at(15, 4));
+    assert_eq!(id_color(0), aovs.material_id.pixel_at(4, 4));
+    assert_eq!(id_color(1), aovs.material_id.pixel_at(10, 4));
+    assert_ne!(id_color(0), id_color(1));
+
+    let (u, v) = world.objects[1].uv_at(p, 0.0);
+    assert_eq!(Color::new(u, v, 0.0), aovs.uv.pixel_at(15, 4));
+    assert_eq!(7, aovs.passes().len());
+  }
+
+  #[test]
+  fn implements_aov_sampling() {
+    let (mut camera, mut world) = scene();
+    world.objects.truncate(2);
+    camera.set_size(21, 10);
+    let settings = RenderSettings { samples_per_pixel: 16, pattern: SamplePattern::Jittered, threads: 2, ..RenderSettings::default() };
+    let aovs = render_aovs(&camera, &world, &settings);
+    assert_eq!(aovs, render_aovs(&camera, &world, &RenderSettings { threads: 1, ..settings }));
+    // silhouettes blend with the empty background, IDs stay crisp
+    let edge = (0..21).find(|&x| (0.01..0.99).contains(&aovs.albedo.pixel_at(x, 5).r())).unwrap();
+    let id = aovs.object_id.pixel_at(edge, 5);
+    assert!(id == BLACK || id == id_color(0) || id == id_color(1));
+    assert_eq!(BLACK, aovs.depth.pixel_at(10, 5));
+  }
+}
@@ -0,0 +1,250 @@
+extern crate rusty_math as rm;
+
+use crate::color::Color;
+use crate::light::{Light, LightSample, RadianceSample};
+use crate::ray::Ray;
+use crate::rng::Rng;
+use rm::Tuple4;
+
+/// Rectangular light spanned by two edges from `corner`, sampled on a
+/// `usteps` x `vsteps` grid of cells. Monte Carlo integrators treat it as a
+/// two-sided emitter of radiance `intensity`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaLight {
+  pub corner: Tuple4,
+  pub uvec: Tuple4,
+  pub usteps: usize,
+  pub vvec: Tuple4,
+  pub vsteps: usize,
+  pub samples: usize,
+  pub position: Tuple4,
+  pub intensity: Color,
+  /// Sample a random position inside each cell rather than its center.
+  pub jitter: bool
+}
+
+impl AreaLight {
+  pub fn new(corner: Tuple4, full_uvec: Tuple4, usteps: usize, full_vvec: Tuple4, vsteps: usize, jitter: bool, intensity: Color) -> Self {
+    Self {
+      corner,
+      uvec: full_uvec / usteps as f32,
+      usteps,
+      vvec: full_vvec / vsteps as f32,
+      vsteps,
+      samples: usteps * vsteps,
+      position: corner + full_uvec * 0.5 + full_vvec * 0.5,
+      intensity,
+      jitter
+    }
+  }
+
+  pub fn point_on_light(&self, u: usize, v: usize, rng: &mut Rng) -> Tuple4 {
+    let (du, dv) = if self.jitter { (rng.next_f32(), rng.next_f32()) } else { (0.5, 0.5) };
+    self.corner + self.uvec * (u as f32 + du) + self.vvec * (v as f32 + dv)
+  }
+
+  pub fn sample_points(&self, rng: &mut Rng) -> Vec<Tuple4> {
+    let mut points = Vec::with_capacity(self.samples);
+    for v in 0..self.vsteps {
+      for u in 0..self.usteps {
+        points.push(self.point_on_light(u, v, rng));
+      }
+    }
+    points
+  }
+
+  pub fn area(&self) -> f32 {
+    self.full_normal().length()
+  }
+
+  fn full_normal(&self) -> Tuple4 {
+    let full_uvec = self.uvec * self.usteps as f32;
+    let full_vvec = self.vvec * self.vsteps as f32;
+    Tuple4::cross(full_uvec, full_vvec).unwrap()
+  }
+
+  /// Distance along `r` to the light's rectangle, if it is hit.
+  pub fn intersect(&self, r: Ray) -> Option<f32> {
+    let normal = self.full_normal();
+    let denominator = Tuple4::dot(normal, r.direction);
+    if denominator.abs() < f32::EPSILON {
+      return None;
+    }
+    let t = Tuple4::dot(normal, self.corner - r.origin) / denominator;
+    if t <= 0.0 {
+      return None;
+    }
+    let offset = r.point_at(t) - self.corner;
+    let (full_uvec, full_vvec) = (self.uvec * self.usteps as f32, self.vvec * self.vsteps as f32);
+    let u = Tuple4::dot(offset, full_uvec) / full_uvec.length_squared();
+    let v = Tuple4::dot(offset, full_vvec) / full_vvec.length_squared();
+    if (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v) { Some(t) } else { None }
+  }
+
+  /// Converts an area density at a point `distance` away, seen along
+  /// `direction`, into a solid angle density.
+  fn solid_angle_pdf(&self, direction: Tuple4, distance: f32) -> f32 {
+    let cos_light = Tuple4::dot(self.full_normal().normalized(), direction).abs();
+    if cos_light <= f32::EPSILON {
+      return 0.0;
+    }
+    distance * distance / (self.area() * cos_light)
+  }
+}
+
+impl Light for AreaLight {
+  fn samples(&self, point: Tuple4, rng: &mut Rng) -> Vec<LightSample> {
+    self.sample_points(rng).into_iter().map(|light_point| {
+      let v = light_point - point;
+      LightSample { direction: v.normalized(), distance: v.length(), intensity: self.intensity }
+    }).collect()
+  }
+
+  fn intensity(&self) -> Color {
+    self.intensity
+  }
+
+  fn sample_radiance(&self, point: Tuple4, rng: &mut Rng) -> Option<RadianceSample> {
+    if self.area() <= 0.0 {
+      return None;
+    }
+    let light_point = self.corner +
+      self.uvec * (self.usteps as f32 * rng.next_f32()) +
+      self.vvec * (self.vsteps as f32 * rng.next_f32());
+    let v = light_point - point;
+    let distance = v.length();
+    let direction = v.normalized();
+    let pdf = self.solid_angle_pdf(direction, distance);
+    if pdf <= 0.0 {
+      return None;
+    }
+    Some(RadianceSample { direction, distance, radiance: self.intensity, pdf: Some(pdf) })
+  }
+
+  fn pdf(&self, point: Tuple4, direction: Tuple4) -> f32 {
+    match self.intersect(Ray::new(point, direction)) {
+      Some(t) if self.area() > 0.0 => self.solid_angle_pdf(direction, t),
+      _ => 0.0
+    }
+  }
+
+  fn emitted(&self, r: Ray) -> Option<(f32, Color)> {
+    self.intersect(r).map(|t| (t, self.intensity))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{AreaLight, Color, Light, Ray, Rng, Tuple4};
+  use crate::test_utils::default_world;
+
+  #[test]
+  fn implements_constructor() {
+    let light = AreaLight::new(
+      Tuple4::point(0.0, 0.0, 0.0),
+      Tuple4::vector(2.0, 0.0, 0.0), 4,
+      Tuple4::vector(0.0, 0.0, 1.0), 2,
+      false, Color::new(1.0, 1.0, 1.0)
+    );
+    assert_eq!(Tuple4::vector(0.5, 0.0, 0.0), light.uvec);
+    assert_eq!(Tuple4::vector(0.0, 0.0, 0.5), light.vvec);
+    assert_eq!(8, light.samples);
+    assert_eq!(Tuple4::point(1.0, 0.0, 0.5), light.position);
+  }
+
+  #[test]
+  fn implements_samples() {
+    let light = AreaLight::new(
+      Tuple4::point(-1.0, 0.0, 0.0),
+      Tuple4::vector(2.0, 0.0, 0.0), 2,
+      Tuple4::vector(0.0, 0.0, 1.0), 1,
+      false, Color::new(1.0, 1.0, 1.0)
+    );
+    let samples = light.samples(Tuple4::point(-0.5, -1.0, 0.5), &mut Rng::new(0));
+    assert_eq!(2, samples.len());
+    assert_eq!(Tuple4::vector(0.0, 1.0, 0.0), samples[0].direction);
+    assert_eq!(1.0, samples[0].distance);
+    assert!((samples[1].distance - f32::sqrt(2.0)).abs() < 0.0001);
+  }
+
+  #[test]
+  fn implements_point_on_light() {
+    let light = AreaLight::new(
+      Tuple4::point(0.0, 0.0, 0.0),
+      Tuple4::vector(2.0, 0.0, 0.0), 4,
+      Tuple4::vector(0.0, 0.0, 1.0), 2,
+      false, Color::new(1.0, 1.0, 1.0)
+    );
+    let mut rng = Rng::new(0);
+    assert_eq!(Tuple4::point(0.25, 0.0, 0.25), light.point_on_light(0, 0, &mut rng));
+    assert_eq!(Tuple4::point(0.75, 0.0, 0.25), light.point_on_light(1, 0, &mut rng));
+    assert_eq!(Tuple4::point(0.25, 0.0, 0.75), light.point_on_light(0, 1, &mut rng));
+    assert_eq!(Tuple4::point(1.25, 0.0, 0.25), light.point_on_light(2, 0, &mut rng));
+    assert_eq!(Tuple4::point(1.75, 0.0, 0.75), light.point_on_light(3, 1, &mut rng));
+
+    let light = AreaLight { jitter: true, ..light };
+    for _ in 0..32 {
+      let p = light.point_on_light(3, 1, &mut rng);
+      assert!(p.x() >= 1.5 && p.x() <= 2.0 && p.z() >= 0.5 && p.z() <= 1.0);
+    }
+  }
+
+  #[test]
+  fn implements_partial_occlusion() {
+    let w = default_world();
+    let light = AreaLight::new(
+      Tuple4::point(-0.5, -0.5, -5.0),
+      Tuple4::vector(1.0, 0.0, 0.0), 2,
+      Tuple4::vector(0.0, 1.0, 0.0), 2,
+      false, Color::new(1.0, 1.0, 1.0)
+    );
+    let mut rng = Rng::new(0);
+    let mut visible = |point: Tuple4| {
+      light.samples(point, &mut rng).iter().filter(|s| !w.is_occluded(point, s.direction, s.distance, 0.0)).count()
+    };
+    assert_eq!(0, visible(Tuple4::point(0.0, 0.0, 2.0)));
+    assert_eq!(1, visible(Tuple4::point(1.0, -1.0, 2.0)));
+    assert_eq!(2, visible(Tuple4::point(1.5, 0.0, 2.0)));
+    assert_eq!(3, visible(Tuple4::point(1.25, 1.25, 3.0)));
+    assert_eq!(4, visible(Tuple4::point(0.0, 0.0, -2.0)));
+  }
+
+  fn panel() -> AreaLight {
+    AreaLight::new(
+      Tuple4::point(-1.0, 2.0, -0.5),
+      Tuple4::vector(2.0, 0.0, 0.0), 2,
+      Tuple4::vector(0.0, 0.0, 1.0), 2,
+      true, Color::new(3.0, 3.0, 3.0)
+    )
+  }
+
+  #[test]
+  fn implements_intersect() {
+    let light = panel();
+    assert_eq!(2.0, light.area());
+    let up = Tuple4::vector(0.0, 1.0, 0.0);
+    assert_eq!(Some(2.0), light.intersect(Ray::new(Tuple4::point(0.0, 0.0, 0.0), up)));
+    assert_eq!(Some(1.0), light.intersect(Ray::new(Tuple4::point(0.9, 3.0, 0.4), -up)));
+    assert_eq!(None, light.intersect(Ray::new(Tuple4::point(1.1, 0.0, 0.0), up)));
+    assert_eq!(None, light.intersect(Ray::new(Tuple4::point(0.0, 0.0, 0.0), -up)));
+    assert_eq!(None, light.intersect(Ray::new(Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(1.0, 0.0, 0.0))));
+    assert_eq!(Some((2.0, Color::new(3.0, 3.0, 3.0))), light.emitted(Ray::new(Tuple4::point(0.0, 0.0, 0.0), up)));
+  }
+
+  #[test]
+  fn implements_sample_radiance_and_pdf() {
+    let light = panel();
+    let point = Tuple4::point(0.3, 0.0, 0.1);
+    let mut rng = Rng::new(4);
+    for _ in 0..64 {
+      let sample = light.sample_radiance(point, &mut rng).unwrap();
+      let pdf = sample.pdf.unwrap();
+      assert_eq!(Color::new(3.0, 3.0, 3.0), sample.radiance);
+      assert!((pdf - light.pdf(point, sample.direction)).abs() / pdf < 0.001);
+      assert!((light.intersect(Ray::new(point, sample.direction)).unwrap() - sample.distance).abs() < 0.001);
+    }
+    // directly below the center: d^2 / (area * cos)
+    assert!((light.pdf(Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 1.0, 0.0)) - 2.0).abs() < 0.0001);
+    assert_eq!(0.0, light.pdf(point, Tuple4::vector(0.0, -1.0, 0.0)));
+  }
+}
@@ -0,0 +1,451 @@
+extern crate rusty_math as rm;
+
+use crate::ray::Ray;
+use rm::{Matrix4, MatrixInversionError, Tuple4};
+
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+/// Most pixels an image may have, enough for 8192 x 8192.
+pub const MAX_PIXELS: usize = 1 << 26;
+
+/// Outline of the lens opening, which shapes out-of-focus highlights (bokeh).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApertureShape {
+  Disk,
+  /// Regular polygon formed by `blades` diaphragm blades, turned by `rotation` radians.
+  Polygon { blades: usize, rotation: f32 }
+}
+
+impl ApertureShape {
+  /// Point on the aperture scaled to unit radius, uniformly distributed over its
+  /// area for uniform `u`, `v`.
+  pub fn sample(&self, u: f32, v: f32) -> (f32, f32) {
+    match *self {
+      ApertureShape::Polygon { blades, rotation } if blades >= 3 => {
+        // pick one of the equal triangles fanning out from the center
+        let scaled = u * blades as f32;
+        let blade = (scaled as usize).min(blades - 1);
+        let (a, b) = (blade_vertex(blade, blades, rotation), blade_vertex(blade + 1, blades, rotation));
+        let su = (scaled - blade as f32).sqrt();
+        (su * ((1.0 - v) * a.0 + v * b.0), su * ((1.0 - v) * a.1 + v * b.1))
+      },
+      _ => concentric_sample_disk(u, v)
+    }
+  }
+}
+
+fn blade_vertex(index: usize, blades: usize, rotation: f32) -> (f32, f32) {
+  let angle = rotation + 2.0 * PI * index as f32 / blades as f32;
+  (angle.cos(), angle.sin())
+}
+
+/// Shirley-Chiu mapping of the unit square onto the unit disk.
+fn concentric_sample_disk(u: f32, v: f32) -> (f32, f32) {
+  let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+  if a == 0.0 && b == 0.0 {
+    return (0.0, 0.0);
+  }
+  let (r, theta) = if a.abs() > b.abs() {
+    (a, FRAC_PI_4 * (b / a))
+  } else {
+    (b, 2.0 * FRAC_PI_4 - FRAC_PI_4 * (a / b))
+  };
+  (r * theta.cos(), r * theta.sin())
+}
+
+/// How points on the film map to ray directions. The camera looks down -z with
+/// +y up; every projection is positioned by the camera transform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+  /// Pinhole or thin lens, covering the camera's `field_of_view`.
+  Perspective,
+  /// Parallel rays from a film `view_width` world units wide.
+  Orthographic { view_width: f32 },
+  /// Equidistant fisheye: the angle off the view axis grows linearly with the
+  /// distance from the image center, reaching `field_of_view / 2` on the circle
+  /// inscribed in the image. Film outside that circle sees nothing.
+  Fisheye { field_of_view: f32 },
+  /// Full 360x180 degree latitude-longitude panorama, centered on the view axis.
+  Equirectangular
+}
+
+impl Projection {
+  /// Whether the projection models a thin lens, making `aperture` meaningful.
+  pub fn has_lens(&self) -> bool {
+    matches!(self, Projection::Perspective | Projection::Orthographic { .. })
+  }
+}
+
+/// Camera generating rays through `projection`, perspective by default. For
+/// projections with a lens, a non-zero `aperture` models a thin lens of that
+/// radius, keeping objects at `focal_distance` from the camera sharp. The
+/// shutter stays open from `shutter_open` to `shutter_close`, in the time units
+/// of `Ray::time`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+  pub hsize: usize,
+  pub vsize: usize,
+  pub field_of_view: f32,
+  pub projection: Projection,
+  pub aperture: f32,
+  pub focal_distance: f32,
+  pub aperture_shape: ApertureShape,
+  pub shutter_open: f32,
+  pub shutter_close: f32,
+  transform: Matrix4,
+  inverse_transform: Matrix4,
+  half_width: f32,
+  half_height: f32,
+  pixel_size: f32
+}
+
+impl Camera {
+  pub fn new(hsize: usize, vsize: usize, field_of_view: f32) -> Self {
+    let mut camera = Self {
+      hsize,
+      vsize,
+      field_of_view,
+      projection: Projection::Perspective,
+      aperture: 0.0,
+      focal_distance: 1.0,
+      aperture_shape: ApertureShape::Disk,
+      shutter_open: 0.0,
+      shutter_close: 0.0,
+      transform: Matrix4::identity(),
+      inverse_transform: Matrix4::identity(),
+      half_width: 0.0,
+      half_height: 0.0,
+      pixel_size: 0.0
+    };
+    camera.set_size(hsize, vsize);
+    camera
+  }
+
+  pub fn orthographic(hsize: usize, vsize: usize, view_width: f32) -> Self {
+    Self { projection: Projection::Orthographic { view_width }, ..Self::new(hsize, vsize, FRAC_PI_2) }
+  }
+
+  pub fn fisheye(hsize: usize, vsize: usize, field_of_view: f32) -> Self {
+    Self { projection: Projection::Fisheye { field_of_view }, ..Self::new(hsize, vsize, FRAC_PI_2) }
+  }
+
+  pub fn equirectangular(hsize: usize, vsize: usize) -> Self {
+    Self { projection: Projection::Equirectangular, ..Self::new(hsize, vsize, FRAC_PI_2) }
+  }
+
+  /// Changes the image resolution, keeping the field of view across the wider side.
+  pub fn set_size(&mut self, hsize: usize, vsize: usize) {
+    let half_view = (self.field_of_view / 2.0).tan();
+    let aspect = hsize as f32 / vsize as f32;
+    let (half_width, half_height) = if aspect >= 1.0 {
+      (half_view, half_view / aspect)
+    } else {
+      (half_view * aspect, half_view)
+    };
+    self.hsize = hsize;
+    self.vsize = vsize;
+    self.half_width = half_width;
+    self.half_height = half_height;
+    self.pixel_size = half_width * 2.0 / hsize as f32;
+  }
+
+  /// Places the camera by its view transform. A singular `t` is rejected and
+  /// leaves the camera where it was.
+  pub fn set_transform(&mut self, t: Matrix4) -> Result<(), MatrixInversionError> {
+    self.inverse_transform = t.inverse()?;
+    self.transform = t;
+    Ok(())
+  }
+
+  pub fn get_transform(&self) -> Matrix4 {
+    self.transform
+  }
+
+  pub fn pixel_size(&self) -> f32 {
+    self.pixel_size
+  }
+
+  /// Instant within the shutter interval for uniform `u`.
+  pub fn shutter_time(&self, u: f32) -> f32 {
+    self.shutter_open + (self.shutter_close - self.shutter_open) * u
+  }
+
+  /// Distance of world point `p` in front of the camera, along its viewing axis.
+  pub fn depth(&self, p: Tuple4) -> f32 {
+    -(self.transform * p).z()
+  }
+
+  /// Whether rays leave from across the aperture rather than a single point.
+  pub fn has_lens(&self) -> bool {
+    self.aperture > 0.0 && self.projection.has_lens()
+  }
+
+  /// Ray through continuous film position (`px`, `py`), measured in pixels from
+  /// the top left corner; the center of pixel (x, y) is (x + 0.5, y + 0.5).
+  /// `None` where the film sees nothing, outside a fisheye's image circle.
+  pub fn ray_for_pixel(&self, px: f32, py: f32) -> Option<Ray> {
+    self.ray_from_lens_point(px, py, (0.0, 0.0))
+  }
+
+  /// Like `ray_for_pixel`, but leaving from the lens position given by uniform
+  /// `u`, `v` over the aperture.
+  pub fn ray_through_lens(&self, px: f32, py: f32, u: f32, v: f32) -> Option<Ray> {
+    let lens = if self.has_lens() { self.aperture_shape.sample(u, v) } else { (0.0, 0.0) };
+    self.ray_from_lens_point(px, py, lens)
+  }
+
+  fn ray_from_lens_point(&self, px: f32, py: f32, (lens_x, lens_y): (f32, f32)) -> Option<Ray> {
+    let (hsize, vsize) = (self.hsize as f32, self.vsize as f32);
+    let (origin, direction) = match self.projection {
+      Projection::Perspective => {
+        let world_x = self.half_width - px * self.pixel_size;
+        let world_y = self.half_height - py * self.pixel_size;
+        let lens = Tuple4::point(lens_x * self.aperture, lens_y * self.aperture, 0.0);
+        // the image plane sits at z = -1, so scaling by the focal distance lands on the plane of focus
+        let focus = Tuple4::point(world_x * self.focal_distance, world_y * self.focal_distance, -self.focal_distance);
+        (lens, focus - lens)
+      },
+      Projection::Orthographic { view_width } => {
+        let pixel_size = view_width / hsize;
+        let film = Tuple4::point(view_width / 2.0 - px * pixel_size, vsize * pixel_size / 2.0 - py * pixel_size, 0.0);
+        let lens = film + Tuple4::vector(lens_x * self.aperture, lens_y * self.aperture, 0.0);
+        let focus = film + Tuple4::vector(0.0, 0.0, -self.focal_distance);
+        (lens, focus - lens)
+      },
+      Projection::Fisheye { field_of_view } => {
+        let radius = hsize.min(vsize) / 2.0;
+        let (x, y) = ((hsize / 2.0 - px) / radius, (vsize / 2.0 - py) / radius);
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+          return None;
+        }
+        let (sin_theta, cos_theta) = (r * field_of_view / 2.0).sin_cos();
+        let (sin_phi, cos_phi) = if r > 0.0 { (y / r, x / r) } else { (0.0, 1.0) };
+        (Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(sin_theta * cos_phi, sin_theta * sin_phi, -cos_theta))
+      },
+      Projection::Equirectangular => {
+        // longitude grows to the left (+x), latitude upwards
+        let longitude = (0.5 - px / hsize) * 2.0 * PI;
+        let latitude = (0.5 - py / vsize) * PI;
+        let (sin_lon, cos_lon) = longitude.sin_cos();
+        let (sin_lat, cos_lat) = latitude.sin_cos();
+        (Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(cos_lat * sin_lon, sin_lat, -cos_lat * cos_lon))
+      }
+    };
+
+    let origin = self.inverse_transform * origin;
+    let direction = (self.inverse_transform * direction).normalized();
+    Some(Ray::new(origin, direction))
+  }
+}
+
+pub fn view_transform(from: Tuple4, to: Tuple4, up: Tuple4) -> Matrix4 {
+  let forward = (to - from).normalized();
+  let left = Tuple4::cross(forward, up.normalized()).unwrap();
+  let true_up = Tuple4::cross(left, forward).unwrap();
+  let orientation = Matrix4::new(
+    Tuple4::new(left.x(), true_up.x(), -forward.x(), 0.0),
+    Tuple4::new(left.y(), true_up.y(), -forward.y(), 0.0),
+    Tuple4::new(left.z(), true_up.z(), -forward.z(), 0.0),
+    Tuple4::new(0.0, 0.0, 0.0, 1.0)
+  );
+  orientation * Matrix4::translation(-from.x(), -from.y(), -from.z())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{view_transform, ApertureShape, Camera, Matrix4, Projection, Tuple4};
+  use crate::rng::Rng;
+  use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4, PI};
+
+  fn cmp_tuple4(a: Tuple4, b: Tuple4) -> bool {
+    (a - b).length() < 0.0001
+  }
+
+  #[test]
+  fn implements_constructor() {
+    let c = Camera::new(160, 120, FRAC_PI_2);
+    assert_eq!(160, c.hsize);
+    assert_eq!(120, c.vsize);
+    assert_eq!(FRAC_PI_2, c.field_of_view);
+    assert_eq!(Projection::Perspective, c.projection);
+    assert_eq!(Matrix4::identity(), c.get_transform());
+  }
+
+  #[test]
+  fn implements_pixel_size() {
+    assert!((Camera::new(200, 125, FRAC_PI_2).pixel_size() - 0.01).abs() < 0.00001);
+    assert!((Camera::new(125, 200, FRAC_PI_2).pixel_size() - 0.01).abs() < 0.00001);
+  }
+
+  #[test]
+  fn implements_set_size() {
+    let mut c = Camera::new(10, 10, FRAC_PI_2);
+    c.set_transform(Matrix4::translation(0.0, 1.0, 0.0)).unwrap();
+    c.set_size(200, 125);
+    assert_eq!((200, 125), (c.hsize, c.vsize));
+    assert_eq!(Camera::new(200, 125, FRAC_PI_2).pixel_size(), c.pixel_size());
+    assert_eq!(Matrix4::translation(0.0, 1.0, 0.0), c.get_transform());
+  }
+
+  #[test]
+  fn rejects_singular_transforms() {
+    let mut c = Camera::new(10, 10, FRAC_PI_2);
+    c.set_transform(Matrix4::translation(0.0, 1.0, 0.0)).unwrap();
+    assert!(c.set_transform(Matrix4::scaling(1.0, 0.0, 1.0)).is_err());
+    assert_eq!(Matrix4::translation(0.0, 1.0, 0.0), c.get_transform());
+    assert!(cmp_tuple4(Tuple4::point(0.0, -1.0, 0.0), c.ray_for_pixel(5.0, 5.0).unwrap().origin));
+  }
+
+  #[test]
+  fn implements_ray_for_pixel() {
+    let mut c = Camera::new(201, 101, FRAC_PI_2);
+    let r = c.ray_for_pixel(100.5, 50.5).unwrap();
+    assert!(cmp_tuple4(Tuple4::point(0.0, 0.0, 0.0), r.origin));
+    assert!(cmp_tuple4(Tuple4::vector(0.0, 0.0, -1.0), r.direction));
+
+    let r = c.ray_for_pixel(0.5, 0.5).unwrap();
+    assert!(cmp_tuple4(Tuple4::vector(0.66519, 0.33259, -0.66851), r.direction));
+
+    c.set_transform(Matrix4::rotation_y(FRAC_PI_4) * Matrix4::translation(0.0, -2.0, 5.0)).unwrap();
+    let r = c.ray_for_pixel(100.5, 50.5).unwrap();
+    assert!(cmp_tuple4(Tuple4::point(0.0, 2.0, -5.0), r.origin));
+    assert!(cmp_tuple4(Tuple4::vector(FRAC_1_SQRT_2, 0.0, -FRAC_1_SQRT_2), r.direction));
+  }
+
+  #[test]
+  fn implements_aperture_shapes() {
+    let mut rng = Rng::new(1);
+    let hexagon = ApertureShape::Polygon { blades: 6, rotation: 0.0 };
+    let (mut disk_mean, mut hexagon_mean) = ((0.0, 0.0), (0.0, 0.0));
+    for _ in 0..2000 {
+      let (x, y) = ApertureShape::Disk.sample(rng.next_f32(), rng.next_f32());
+      assert!(x * x + y * y <= 1.0 + 1e-5);
+      disk_mean = (disk_mean.0 + x / 2000.0, disk_mean.1 + y / 2000.0);
+
+      let (x, y) = hexagon.sample(rng.next_f32(), rng.next_f32());
+      // inside every edge of a hexagon with vertices on the unit circle
+      for i in 0..6 {
+        let angle = FRAC_PI_2 / 1.5 * (i as f32 + 0.5);
+        assert!(x * angle.cos() + y * angle.sin() <= (FRAC_PI_2 / 3.0).cos() + 1e-5);
+      }
+      hexagon_mean = (hexagon_mean.0 + x / 2000.0, hexagon_mean.1 + y / 2000.0);
+    }
+    assert!(disk_mean.0.abs() < 0.05 && disk_mean.1.abs() < 0.05);
+    assert!(hexagon_mean.0.abs() < 0.05 && hexagon_mean.1.abs() < 0.05);
+    assert_eq!((0.0, 0.0), ApertureShape::Disk.sample(0.5, 0.5));
+  }
+
+  #[test]
+  fn implements_ray_through_lens() {
+    let mut c = Camera::new(201, 101, FRAC_PI_2);
+    c.set_transform(view_transform(Tuple4::point(0.0, 0.0, -5.0), Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 1.0, 0.0))).unwrap();
+    c.aperture = 0.2;
+    c.focal_distance = 5.0;
+    c.aperture_shape = ApertureShape::Polygon { blades: 5, rotation: 0.3 };
+    let pinhole = c.ray_for_pixel(30.5, 70.5).unwrap();
+    // every lens ray meets the pinhole ray on the plane of focus
+    let focus = pinhole.point_at(5.0 / Tuple4::dot(pinhole.direction, Tuple4::vector(0.0, 0.0, 1.0)));
+    for (u, v) in [(0.1, 0.9), (0.7, 0.2), (0.99, 0.5)] {
+      let r = c.ray_through_lens(30.5, 70.5, u, v).unwrap();
+      assert!((r.origin - Tuple4::point(0.0, 0.0, -5.0)).length() <= 0.2 + 1e-5);
+      assert!(r.origin.z() == -5.0 && r.origin != Tuple4::point(0.0, 0.0, -5.0));
+      let t = (focus.z() - r.origin.z()) / r.direction.z();
+      assert!(cmp_tuple4(focus, r.point_at(t)));
+    }
+
+    // without an aperture every ray leaves from the center
+    c.aperture = 0.0;
+    assert_eq!(Some(pinhole), c.ray_through_lens(30.5, 70.5, 0.1, 0.9));
+  }
+
+  #[test]
+  fn implements_orthographic_projection() {
+    let mut c = Camera::orthographic(200, 100, 4.0);
+    let center = c.ray_for_pixel(100.0, 50.0).unwrap();
+    assert!(cmp_tuple4(Tuple4::point(0.0, 0.0, 0.0), center.origin));
+    assert!(cmp_tuple4(Tuple4::vector(0.0, 0.0, -1.0), center.direction));
+    // parallel rays from across a 4 x 2 film
+    let corner = c.ray_for_pixel(0.0, 0.0).unwrap();
+    assert!(cmp_tuple4(Tuple4::point(2.0, 1.0, 0.0), corner.origin));
+    assert!(cmp_tuple4(center.direction, corner.direction));
+
+    c.set_transform(view_transform(Tuple4::point(5.0, 0.0, 0.0), Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 1.0, 0.0))).unwrap();
+    let r = c.ray_for_pixel(0.0, 100.0).unwrap();
+    assert!(cmp_tuple4(Tuple4::point(5.0, -1.0, -2.0), r.origin));
+    assert!(cmp_tuple4(Tuple4::vector(-1.0, 0.0, 0.0), r.direction));
+    assert!(!c.has_lens());
+    c.aperture = 0.1;
+    assert!(c.has_lens());
+  }
+
+  #[test]
+  fn implements_fisheye_projection() {
+    let c = Camera::fisheye(200, 100, PI);
+    assert!(cmp_tuple4(Tuple4::vector(0.0, 0.0, -1.0), c.ray_for_pixel(100.0, 50.0).unwrap().direction));
+    // the edge of the image circle is field_of_view / 2 off axis
+    assert!(cmp_tuple4(Tuple4::vector(0.0, 1.0, 0.0), c.ray_for_pixel(100.0, 0.0).unwrap().direction));
+    assert!(cmp_tuple4(Tuple4::vector(-1.0, 0.0, 0.0), c.ray_for_pixel(150.0, 50.0).unwrap().direction));
+    // angles grow linearly with the distance from the center
+    let r = c.ray_for_pixel(125.0, 50.0).unwrap();
+    assert!(cmp_tuple4(Tuple4::vector(-FRAC_1_SQRT_2, 0.0, -FRAC_1_SQRT_2), r.direction));
+    assert_eq!(None, c.ray_for_pixel(160.0, 50.0));
+    assert_eq!(None, c.ray_for_pixel(0.5, 0.5));
+
+    // there is no lens to sample
+    let mut c = Camera::fisheye(100, 100, FRAC_PI_2);
+    c.aperture = 0.5;
+    assert!(!c.has_lens());
+    assert_eq!(c.ray_for_pixel(20.0, 30.0), c.ray_through_lens(20.0, 30.0, 0.9, 0.1));
+  }
+
+  #[test]
+  fn implements_equirectangular_projection() {
+    let mut c = Camera::equirectangular(360, 180);
+    assert!(cmp_tuple4(Tuple4::vector(0.0, 0.0, -1.0), c.ray_for_pixel(180.0, 90.0).unwrap().direction));
+    assert!(cmp_tuple4(Tuple4::vector(0.0, 1.0, 0.0), c.ray_for_pixel(180.0, 0.0).unwrap().direction));
+    assert!(cmp_tuple4(Tuple4::vector(0.0, -1.0, 0.0), c.ray_for_pixel(180.0, 180.0).unwrap().direction));
+    assert!(cmp_tuple4(Tuple4::vector(1.0, 0.0, 0.0), c.ray_for_pixel(90.0, 90.0).unwrap().direction));
+    assert!(cmp_tuple4(Tuple4::vector(-1.0, 0.0, 0.0), c.ray_for_pixel(270.0, 90.0).unwrap().direction));
+    // both side edges look backwards
+    assert!(cmp_tuple4(Tuple4::vector(0.0, 0.0, 1.0), c.ray_for_pixel(0.0, 90.0).unwrap().direction));
+    assert!(cmp_tuple4(Tuple4::vector(0.0, 0.0, 1.0), c.ray_for_pixel(360.0, 90.0).unwrap().direction));
+
+    c.set_transform(Matrix4::translation(0.0, -2.0, 0.0)).unwrap();
+    let r = c.ray_for_pixel(180.0, 45.0).unwrap();
+    assert!(cmp_tuple4(Tuple4::point(0.0, 2.0, 0.0), r.origin));
+    assert!(cmp_tuple4(Tuple4::vector(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2), r.direction));
+  }
+
+  #[test]
+  fn implements_depth() {
+    let mut c = Camera::new(10, 10, FRAC_PI_2);
+    c.set_transform(view_transform(Tuple4::point(0.0, 0.0, -5.0), Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 1.0, 0.0))).unwrap();
+    assert!((c.depth(Tuple4::point(0.0, 0.0, 0.0)) - 5.0).abs() < 0.0001);
+    // depth ignores the offset from the viewing axis
+    assert!((c.depth(Tuple4::point(3.0, -2.0, 1.0)) - 6.0).abs() < 0.0001);
+    assert!(c.depth(Tuple4::point(0.0, 0.0, -6.0)) < 0.0);
+  }
+
+  #[test]
+  fn implements_shutter_time() {
+    let mut c = Camera::new(10, 10, FRAC_PI_2);
+    assert_eq!(0.0, c.shutter_time(0.7));
+    c.shutter_open = 0.25;
+    c.shutter_close = 0.75;
+    assert_eq!(0.25, c.shutter_time(0.0));
+    assert_eq!(0.5, c.shutter_time(0.5));
+  }
+
+  #[test]
+  fn implements_view_transform() {
+    let from = Tuple4::point(0.0, 0.0, 0.0);
+    let up = Tuple4::vector(0.0, 1.0, 0.0);
+    assert_eq!(Matrix4::identity(), view_transform(from, Tuple4::point(0.0, 0.0, -1.0), up));
+    assert_eq!(Matrix4::scaling(-1.0, 1.0, -1.0), view_transform(from, Tuple4::point(0.0, 0.0, 1.0), up));
+    assert_eq!(
+      Matrix4::translation(0.0, 0.0, -8.0),
+      view_transform(Tuple4::point(0.0, 0.0, 8.0), Tuple4::point(0.0, 0.0, 0.0), up)
+    );
+  }
+}
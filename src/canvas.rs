@@ -1,7 +1,9 @@
 use std::fs;
-use crate::color::Color;
+use std::path::Path;
+use crate::color::{Color, Transfer, BLACK};
+use crate::errors::ImageError;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Canvas {
   canvas: Vec<Color>,
   height: usize,
@@ -12,23 +14,291 @@ impl Canvas {
   pub fn new(width: usize, height: usize) -> Self {
     Self {
       canvas: vec![Color::new(0.0, 0.0, 0.0); width * height],
-      height: height,
-      width: width
+      height,
+      width
     }
   }
 
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
   pub fn write(&mut self, x: usize, y: usize, color: &Color) {
     self.canvas[y * self.width + x] = *color;
   }
 
-  fn as_ppm(&self) -> String {
+  pub fn pixel_at(&self, x: usize, y: usize) -> Color {
+    self.canvas[y * self.width + x]
+  }
+
+  /// Rows from top to bottom, each `width` pixels long.
+  pub fn rows(&self) -> impl Iterator<Item = &[Color]> + '_ {
+    self.canvas.chunks(self.width.max(1))
+  }
+
+  /// Every pixel with its position, row by row.
+  pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, Color)> + '_ {
+    let width = self.width;
+    self.canvas.iter().enumerate().map(move |(i, c)| (i % width, i / width, *c))
+  }
+
+  /// Color at continuous position (`px`, `py`) in pixels, interpolated between
+  /// the nearest pixel centers and clamped at the borders. An empty canvas
+  /// reads as black.
+  pub fn bilinear_at(&self, px: f32, py: f32) -> Color {
+    if self.width == 0 || self.height == 0 {
+      return BLACK;
+    }
+    let x = (px - 0.5).clamp(0.0, self.width.saturating_sub(1) as f32);
+    let y = (py - 0.5).clamp(0.0, self.height.saturating_sub(1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let top = self.pixel_at(x0, y0) * (1.0 - fx) + self.pixel_at(x1, y0) * fx;
+    let bottom = self.pixel_at(x0, y1) * (1.0 - fx) + self.pixel_at(x1, y1) * fx;
+    top * (1.0 - fy) + bottom * fy
+  }
+
+  pub fn fill(&mut self, color: &Color) {
+    self.canvas.fill(*color);
+  }
+
+  /// Canvas of the same size with `f` applied to every pixel.
+  pub fn map<F: FnMut(Color) -> Color>(&self, mut f: F) -> Canvas {
+    Canvas { canvas: self.canvas.iter().map(|c| f(*c)).collect(), ..*self }
+  }
+
+  /// The `width` x `height` region with its top left corner at (`x`, `y`),
+  /// cut short where it extends past the canvas.
+  pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Canvas {
+    if x >= self.width || y >= self.height {
+      return Canvas::new(0, 0);
+    }
+    let width = width.min(self.width.saturating_sub(x));
+    let height = height.min(self.height.saturating_sub(y));
+    let mut cropped = Canvas::new(width, height);
+    for row in 0..height {
+      let start = (y + row) * self.width + x;
+      cropped.canvas[row * width..(row + 1) * width].copy_from_slice(&self.canvas[start..start + width]);
+    }
+    cropped
+  }
+
+  /// Copies `source` over this canvas with its top left corner at (`x`, `y`).
+  /// Parts falling outside the canvas are dropped.
+  pub fn blit(&mut self, source: &Canvas, x: isize, y: isize) {
+    self.composite(source, x, y, |_, _, _, src| src);
+  }
+
+  /// Blends `source` over this canvas with its top left corner at (`x`, `y`),
+  /// weighting each channel by the matching pixel and channel of `alpha`,
+  /// which must have the size of `source`: 0 keeps this canvas, 1 takes `source`.
+  pub fn paste(&mut self, source: &Canvas, alpha: &Canvas, x: isize, y: isize) {
+    assert!(
+      alpha.width == source.width && alpha.height == source.height,
+      "cannot paste a {}x{} image with a {}x{} alpha mask", source.width, source.height, alpha.width, alpha.height
+    );
+    self.composite(source, x, y, |sx, sy, dst, src| {
+      let a = alpha.pixel_at(sx, sy);
+      dst + (src - dst) * a
+    });
+  }
+
+  fn composite<F: Fn(usize, usize, Color, Color) -> Color>(&mut self, source: &Canvas, x: isize, y: isize, blend: F) {
+    for (sx, sy, src) in source.pixels() {
+      let (dx, dy) = (x + sx as isize, y + sy as isize);
+      if dx < 0 || dy < 0 || dx >= self.width as isize || dy >= self.height as isize {
+        continue;
+      }
+      let i = dy as usize * self.width + dx as usize;
+      self.canvas[i] = blend(sx, sy, self.canvas[i], src);
+    }
+  }
+
+  pub fn flip_horizontal(&mut self) {
+    let width = self.width.max(1);
+    for row in self.canvas.chunks_mut(width) {
+      row.reverse();
+    }
+  }
+
+  pub fn flip_vertical(&mut self) {
+    let width = self.width;
+    for y in 0..self.height / 2 {
+      let (top, bottom) = self.canvas.split_at_mut((self.height - 1 - y) * width);
+      top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[..width]);
+    }
+  }
+
+  /// Resamples the canvas to `width` x `height`. When shrinking, the filter
+  /// widens with the scale so that every source pixel contributes.
+  pub fn resize(&self, width: usize, height: usize, filter: ResizeFilter) -> Canvas {
+    if self.width == 0 || self.height == 0 {
+      return Canvas::new(width, height);
+    }
+    let horizontal = resample_taps(self.width, width, filter);
+    let vertical = resample_taps(self.height, height, filter);
+    let mut wide = Canvas::new(width, self.height);
+    for y in 0..self.height {
+      for (x, taps) in horizontal.iter().enumerate() {
+        let color = taps.iter().fold(BLACK, |sum, (sx, w)| sum + self.pixel_at(*sx, y) * *w);
+        wide.write(x, y, &color);
+      }
+    }
+    let mut resized = Canvas::new(width, height);
+    for (y, taps) in vertical.iter().enumerate() {
+      for x in 0..width {
+        let color = taps.iter().fold(BLACK, |sum, (sy, w)| sum + wide.pixel_at(x, *sy) * *w);
+        resized.write(x, y, &color);
+      }
+    }
+    resized
+  }
+
+  /// Decodes a P3 (ASCII) or P6 (binary) PPM image, converting its channels
+  /// back to linear values through `transfer`.
+  pub fn from_ppm(data: &[u8], transfer: Transfer) -> Result<Self, ImageError> {
+    let mut reader = PpmReader { data, pos: 0 };
+    let magic = reader.token()?;
+    let binary = match magic.as_str() {
+      "P3" => false,
+      "P6" => true,
+      _ => return Err(ImageError::Format(format!("unsupported PPM magic number '{}'", magic)))
+    };
+    let width = reader.number()?;
+    let height = reader.number()?;
+    let max_value = reader.number()?;
+    if max_value == 0 || max_value > 65535 {
+      return Err(ImageError::Format(format!("invalid PPM maximum value {}", max_value)));
+    }
+
+    if binary {
+      // a single whitespace character separates the header from the raster
+      reader.pos += 1;
+    }
+    // every pixel takes at least three bytes, so check the size before allocating it
+    let pixels = match width.checked_mul(height) {
+      Some(pixels) if pixels <= reader.data.len().saturating_sub(reader.pos) / 3 => pixels,
+      _ => return Err(ImageError::Format(format!("PPM size {}x{} exceeds the image data", width, height)))
+    };
+    let mut canvas = Self::new(width, height);
+    let mut channels = [0.0; 3];
+    for i in 0..pixels {
+      for channel in channels.iter_mut() {
+        let value = if binary { reader.binary_sample(max_value)? } else { reader.number()? };
+        if value > max_value {
+          return Err(ImageError::Format(format!("sample {} exceeds maximum value {}", value, max_value)));
+        }
+        *channel = transfer.decode(value as f32 / max_value as f32);
+      }
+      canvas.canvas[i] = Color::new(channels[0], channels[1], channels[2]);
+    }
+    Ok(canvas)
+  }
+
+  pub fn import_ppm(file: &str, transfer: Transfer) -> Result<Self, ImageError> {
+    Self::from_ppm(&fs::read(file)?, transfer)
+  }
+
+  /// Decodes a Radiance RGBE (.hdr) image, flat or run-length encoded. HDR
+  /// files store linear radiance, so no transfer function is applied.
+  pub fn from_hdr(data: &[u8]) -> Result<Self, ImageError> {
+    let mut pos = 0;
+    let next_line = |pos: &mut usize| -> Result<String, ImageError> {
+      let start = *pos;
+      while *pos < data.len() && data[*pos] != b'\n' {
+        *pos += 1;
+      }
+      if *pos >= data.len() {
+        return Err(ImageError::Format(String::from("unexpected end of HDR header")));
+      }
+      *pos += 1;
+      Ok(String::from_utf8_lossy(&data[start..*pos - 1]).into_owned())
+    };
+    let magic = next_line(&mut pos)?;
+    if !magic.starts_with("#?") {
+      return Err(ImageError::Format(format!("unsupported HDR magic '{}'", magic)));
+    }
+    loop {
+      let line = next_line(&mut pos)?;
+      if line.is_empty() {
+        break;
+      }
+      if let Some(format) = line.strip_prefix("FORMAT=") {
+        if format != "32-bit_rle_rgbe" {
+          return Err(ImageError::Format(format!("unsupported HDR format '{}'", format)));
+        }
+      }
+    }
+    let resolution = next_line(&mut pos)?;
+    let (width, height): (usize, usize) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+      ["-Y", height, "+X", width] => match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err(ImageError::Format(format!("invalid HDR resolution '{}'", resolution)))
+      },
+      _ => return Err(ImageError::Format(format!("unsupported HDR orientation '{}'", resolution)))
+    };
+
+    // run-length encoding packs at most 127 pixels of a channel into two bytes,
+    // so the scanline data cannot hold more than 16 pixels per byte
+    let pixels = match width.checked_mul(height) {
+      Some(pixels) if pixels <= (data.len() - pos).saturating_mul(16) => pixels,
+      _ => return Err(ImageError::Format(format!("HDR size {}x{} exceeds the image data", width, height)))
+    };
+    let mut canvas = Self::new(width, height);
+    // an image without rows reads no scanlines
+    let mut scanline = vec![[0u8; 4]; width.min(pixels)];
+    for y in 0..height {
+      pos = read_hdr_scanline(data, pos, &mut scanline)?;
+      for (x, rgbe) in scanline.iter().enumerate() {
+        canvas.canvas[y * width + x] = rgbe_to_color(*rgbe);
+      }
+    }
+    Ok(canvas)
+  }
+
+  pub fn import_hdr(file: &str) -> Result<Self, ImageError> {
+    Self::from_hdr(&fs::read(file)?)
+  }
+
+  /// Encodes the canvas as an uncompressed Radiance RGBE image, keeping the
+  /// linear high dynamic range values.
+  pub fn as_hdr(&self) -> Vec<u8> {
+    let mut hdr = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.height, self.width).into_bytes();
+    for color in &self.canvas {
+      hdr.extend_from_slice(&color_to_rgbe(color));
+    }
+    hdr
+  }
+
+  pub fn export_hdr(&self, file: &str) -> Result<(), ImageError> {
+    Ok(fs::write(file, self.as_hdr())?)
+  }
+
+  /// Writes the canvas in the format given by the file extension: `.ppm` is
+  /// sRGB encoded, `.hdr` keeps linear radiance.
+  pub fn save(&self, file: &str) -> Result<(), ImageError> {
+    let extension = Path::new(file).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+      "ppm" => Ok(fs::write(file, self.as_ppm(Transfer::Srgb))?),
+      "hdr" => self.export_hdr(file),
+      "" => Err(ImageError::Format(format!("cannot infer an image format for '{}'; use .ppm or .hdr", file))),
+      other => Err(ImageError::Format(format!("unsupported image format '.{}'; use .ppm or .hdr", other)))
+    }
+  }
+
+  fn as_ppm(&self, transfer: Transfer) -> String {
     let mut ppm = format!("P3\n{} {}\n255\n", self.width, self.height);
-    if self.canvas.len() == 0 { return ppm };
+    if self.canvas.is_empty() { return ppm };
 
-    let mut line: String = color_to_channels(self.canvas.first().unwrap()).join(" ");
+    let mut line: String = color_to_channels(self.canvas.first().unwrap(), transfer).join(" ");
     let mut count = 1;
     for color in self.canvas.iter().skip(1) {
-      for channel in color_to_channels(color) {
+      for channel in color_to_channels(color, transfer) {
         if line.len() + channel.len() >= 70 || count >= self.width {
           if count >= self.width {
             count = 0;
@@ -48,32 +318,246 @@ impl Canvas {
     ppm
   }
 
+  /// Writes the canvas as an sRGB encoded PPM.
   pub fn export_ppm(&self, file: &String) {
-    fs::write(file, self.as_ppm()).expect("Unable to write file");
+    self.export_ppm_with(file, Transfer::Srgb);
+  }
+
+  pub fn export_ppm_with(&self, file: &String, transfer: Transfer) {
+    fs::write(file, self.as_ppm(transfer)).expect("Unable to write file");
   }
 }
 
-fn color_to_channels(color: &Color) -> Vec<String> {
-  let r = f32::min(255.0, f32::max(0.0, (color.r() * 255.0).round())) as u8;
-  let g = f32::min(255.0, f32::max(0.0, (color.g() * 255.0).round())) as u8;
-  let b = f32::min(255.0, f32::max(0.0, (color.b() * 255.0).round())) as u8;
+/// Reconstruction filter used by `Canvas::resize`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResizeFilter {
+  /// Linear interpolation between neighbouring pixels.
+  Bilinear,
+  /// Windowed sinc over three lobes; sharper, but may ring at hard edges.
+  Lanczos3
+}
+
+impl ResizeFilter {
+  fn radius(&self) -> f32 {
+    match self {
+      ResizeFilter::Bilinear => 1.0,
+      ResizeFilter::Lanczos3 => 3.0
+    }
+  }
+
+  fn evaluate(&self, x: f32) -> f32 {
+    let x = x.abs();
+    match self {
+      ResizeFilter::Bilinear => (1.0 - x).max(0.0),
+      ResizeFilter::Lanczos3 if x < f32::EPSILON => 1.0,
+      ResizeFilter::Lanczos3 if x < 3.0 => {
+        let pi_x = std::f32::consts::PI * x;
+        3.0 * pi_x.sin() * (pi_x / 3.0).sin() / (pi_x * pi_x)
+      },
+      ResizeFilter::Lanczos3 => 0.0
+    }
+  }
+}
+
+/// Source pixels and normalised weights making up each of `to` pixels when
+/// resampling a line of `from` pixels.
+fn resample_taps(from: usize, to: usize, filter: ResizeFilter) -> Vec<Vec<(usize, f32)>> {
+  if from == to {
+    return (0..to).map(|i| vec![(i, 1.0)]).collect();
+  }
+  let scale = from as f32 / to as f32;
+  let stretch = scale.max(1.0);
+  let support = filter.radius() * stretch;
+  (0..to).map(|i| {
+    let center = (i as f32 + 0.5) * scale - 0.5;
+    let first = (center - support).ceil() as isize;
+    let last = (center + support).floor() as isize;
+    let mut taps: Vec<(usize, f32)> = (first..=last)
+      .map(|j| (j.clamp(0, from as isize - 1) as usize, filter.evaluate((j as f32 - center) / stretch)))
+      .filter(|(_, w)| *w != 0.0)
+      .collect();
+    let total: f32 = taps.iter().map(|(_, w)| w).sum();
+    for tap in taps.iter_mut() {
+      tap.1 /= total;
+    }
+    taps
+  }).collect()
+}
+
+fn color_to_channels(color: &Color, transfer: Transfer) -> Vec<String> {
+  let encoded = color.encoded(transfer);
+  let r = (encoded.r() * 255.0).round().clamp(0.0, 255.0) as u8;
+  let g = (encoded.g() * 255.0).round().clamp(0.0, 255.0) as u8;
+  let b = (encoded.b() * 255.0).round().clamp(0.0, 255.0) as u8;
   vec![r.to_string(), g.to_string(), b.to_string()]
 }
 
+fn color_to_rgbe(color: &Color) -> [u8; 4] {
+  let v = color.r().max(color.g()).max(color.b());
+  if v.is_nan() || v < 1e-32 {
+    return [0, 0, 0, 0];
+  }
+  // v = m * 2^e with m in [0.5, 1)
+  let mut e = v.log2().floor() as i32 + 1;
+  if v / 2f32.powi(e) >= 1.0 {
+    e += 1;
+  }
+  let scale = 256.0 / 2f32.powi(e);
+  let channel = |c: f32| (c.max(0.0) * scale).min(255.0) as u8;
+  [channel(color.r()), channel(color.g()), channel(color.b()), (e + 128).clamp(0, 255) as u8]
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+  if rgbe[3] == 0 {
+    return Color::new(0.0, 0.0, 0.0);
+  }
+  let f = 2f32.powi(rgbe[3] as i32 - 136);
+  Color::new(rgbe[0] as f32 * f, rgbe[1] as f32 * f, rgbe[2] as f32 * f)
+}
+
+/// Reads one scanline of RGBE pixels starting at `pos`, returning the position
+/// after it.
+fn read_hdr_scanline(data: &[u8], mut pos: usize, scanline: &mut [[u8; 4]]) -> Result<usize, ImageError> {
+  let eof = || ImageError::Format(String::from("unexpected end of HDR data"));
+  let width = scanline.len();
+  let header = data.get(pos..pos + 4).ok_or_else(eof)?;
+  let run_length_encoded = (8..0x8000).contains(&width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0;
+  if !run_length_encoded {
+    for pixel in scanline.iter_mut() {
+      pixel.copy_from_slice(data.get(pos..pos + 4).ok_or_else(eof)?);
+      pos += 4;
+    }
+    return Ok(pos);
+  }
+  if ((header[2] as usize) << 8 | header[3] as usize) != width {
+    return Err(ImageError::Format(String::from("HDR scanline width mismatch")));
+  }
+  pos += 4;
+  // each channel is stored separately as runs and literal spans
+  for channel in 0..4 {
+    let mut x = 0;
+    while x < width {
+      let count = *data.get(pos).ok_or_else(eof)? as usize;
+      pos += 1;
+      if count > 128 {
+        let count = count - 128;
+        let value = *data.get(pos).ok_or_else(eof)?;
+        pos += 1;
+        if x + count > width {
+          return Err(ImageError::Format(String::from("HDR run exceeds scanline")));
+        }
+        for pixel in scanline[x..x + count].iter_mut() {
+          pixel[channel] = value;
+        }
+        x += count;
+      } else {
+        if count == 0 || x + count > width {
+          return Err(ImageError::Format(String::from("invalid HDR literal span")));
+        }
+        let values = data.get(pos..pos + count).ok_or_else(eof)?;
+        for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+          pixel[channel] = *value;
+        }
+        pos += count;
+        x += count;
+      }
+    }
+  }
+  Ok(pos)
+}
+
+struct PpmReader<'a> {
+  data: &'a [u8],
+  pos: usize
+}
+
+impl<'a> PpmReader<'a> {
+  fn skip_whitespace_and_comments(&mut self) {
+    while self.pos < self.data.len() {
+      match self.data[self.pos] {
+        b'#' => {
+          while self.pos < self.data.len() && self.data[self.pos] != b'\n' {
+            self.pos += 1;
+          }
+        },
+        c if c.is_ascii_whitespace() => self.pos += 1,
+        _ => break
+      }
+    }
+  }
+
+  fn token(&mut self) -> Result<String, ImageError> {
+    self.skip_whitespace_and_comments();
+    let start = self.pos;
+    while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() && self.data[self.pos] != b'#' {
+      self.pos += 1;
+    }
+    if start == self.pos {
+      return Err(ImageError::Format(String::from("unexpected end of file")));
+    }
+    Ok(String::from_utf8_lossy(&self.data[start..self.pos]).into_owned())
+  }
+
+  fn number(&mut self) -> Result<usize, ImageError> {
+    let token = self.token()?;
+    token.parse().map_err(|_| ImageError::Format(format!("expected a number, found '{}'", token)))
+  }
+
+  fn binary_sample(&mut self, max_value: usize) -> Result<usize, ImageError> {
+    let size = if max_value < 256 { 1 } else { 2 };
+    if self.pos + size > self.data.len() {
+      return Err(ImageError::Format(String::from("unexpected end of file")));
+    }
+    let value = if size == 1 {
+      self.data[self.pos] as usize
+    } else {
+      (self.data[self.pos] as usize) << 8 | self.data[self.pos + 1] as usize
+    };
+    self.pos += size;
+    Ok(value)
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::Color;
+  use super::BLACK;
   use super::Canvas;
+  use super::Transfer;
   use super::color_to_channels;
+  use super::ImageError;
+  use super::ResizeFilter;
+  use crate::test_utils::cmp_color;
+
+  fn gradient(width: usize, height: usize) -> Canvas {
+    let mut canvas = Canvas::new(width, height);
+    for y in 0..height {
+      for x in 0..width {
+        canvas.write(x, y, &Color::new(x as f32, y as f32, 1.0));
+      }
+    }
+    canvas
+  }
 
   #[test]
   fn implements_color_to_channels() {
     let c1 = Color::new(1.5, 0.0, 0.0);
     let c2 = Color::new(0.0, 0.5, 0.0);
     let c3 = Color::new(-0.5, 0.0, 1.0);
-    assert_eq!("255 0 0".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c1));
-    assert_eq!("0 128 0".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c2));
-    assert_eq!("0 0 255".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c3));
+    assert_eq!("255 0 0".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c1, Transfer::Linear));
+    assert_eq!("0 128 0".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c2, Transfer::Linear));
+    assert_eq!("0 0 255".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c3, Transfer::Linear));
+  }
+
+  #[test]
+  fn implements_color_to_channels_srgb() {
+    let c1 = Color::new(1.5, 0.0, 0.0);
+    let c2 = Color::new(0.0, 0.25, 0.0);
+    let c3 = Color::new(0.216, 0.0, 1.0);
+    assert_eq!("255 0 0".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c1, Transfer::Srgb));
+    assert_eq!("0 137 0".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c2, Transfer::Srgb));
+    assert_eq!("128 0 255".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c3, Transfer::Srgb));
+    assert_eq!("0 136 0".split_whitespace().collect::<Vec<_>>(), color_to_channels(&c2, Transfer::Gamma(2.2)));
   }
 
   #[test]
@@ -82,8 +566,8 @@ mod tests {
     let height = 20;
     let expected = Canvas {
       canvas: vec![Color::new(0.0, 0.0, 0.0); width * height],
-      height: height,
-      width: width
+      height,
+      width
     };
     assert_eq!(expected, Canvas::new(width, height));
   }
@@ -96,13 +580,16 @@ mod tests {
     let y = 3;
     c.write(x, y, &red);
     assert_eq!(c.canvas[y * c.width + x], red);
+    assert_eq!(red, c.pixel_at(x, y));
+    assert_eq!(10, c.width());
+    assert_eq!(20, c.height());
   }
 
   #[test]
   fn implements_as_ppm_header() {
     assert_eq!(
       String::from("P3\n5 3\n255"),
-      Canvas::new(5, 3).as_ppm().lines().take(3).collect::<Vec<_>>().join("\n")
+      Canvas::new(5, 3).as_ppm(Transfer::Linear).lines().take(3).collect::<Vec<_>>().join("\n")
     );
   }
 
@@ -120,7 +607,7 @@ mod tests {
       "0 0 0 0 0 0 0 128 0 0 0 0 0 0 0",
       "0 0 0 0 0 0 0 0 0 0 0 0 0 0 255"
     ].join("\n");
-    assert_eq!(body, c.as_ppm().lines().skip(3).collect::<Vec<_>>().join("\n"));
+    assert_eq!(body, c.as_ppm(Transfer::Linear).lines().skip(3).collect::<Vec<_>>().join("\n"));
   }
 
   #[test]
@@ -133,11 +620,247 @@ mod tests {
       "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204",
       "153 255 204 153 255 204 153 255 204 153 255 204 153",
     ].join("\n");
-    assert_eq!(body, canvas.as_ppm().lines().skip(3).collect::<Vec<_>>().join("\n"));
+    assert_eq!(body, canvas.as_ppm(Transfer::Linear).lines().skip(3).collect::<Vec<_>>().join("\n"));
   }
 
   #[test]
   fn implements_as_ppm_endline() {
-    assert_eq!('\n', Canvas::new(5, 3).as_ppm().pop().unwrap());
+    assert_eq!('\n', Canvas::new(5, 3).as_ppm(Transfer::Linear).pop().unwrap());
+  }
+
+  #[test]
+  fn implements_from_ppm_ascii() {
+    let ppm = "P3\n# a comment\n2 1\n255\n255 0 0  # trailing comment\n0 188 51\n";
+    let c = Canvas::from_ppm(ppm.as_bytes(), Transfer::Linear).unwrap();
+    assert_eq!(2, c.width);
+    assert_eq!(1, c.height);
+    assert_eq!(Color::new(1.0, 0.0, 0.0), c.canvas[0]);
+    assert_eq!(Color::new(0.0, 188.0 / 255.0, 0.2), c.canvas[1]);
+
+    let c = Canvas::from_ppm(ppm.as_bytes(), Transfer::Srgb).unwrap();
+    assert!((c.canvas[1].g() - 0.5).abs() < 0.005);
+  }
+
+  #[test]
+  fn implements_from_ppm_binary() {
+    let mut ppm = b"P6 1 2 255\n".to_vec();
+    ppm.extend_from_slice(&[255, 0, 51, 0, 255, 0]);
+    let c = Canvas::from_ppm(&ppm, Transfer::Linear).unwrap();
+    assert_eq!(Color::new(1.0, 0.0, 0.2), c.canvas[0]);
+    assert_eq!(Color::new(0.0, 1.0, 0.0), c.canvas[1]);
+
+    let mut ppm = b"P6 1 1 65535\n".to_vec();
+    ppm.extend_from_slice(&[255, 255, 0, 0, 128, 0]);
+    let c = Canvas::from_ppm(&ppm, Transfer::Linear).unwrap();
+    assert_eq!(Color::new(1.0, 0.0, 32768.0 / 65535.0), c.canvas[0]);
+  }
+
+  #[test]
+  fn implements_ppm_roundtrip() {
+    let mut c = Canvas::new(4, 2);
+    c.write(1, 0, &Color::new(0.5, 0.25, 0.0));
+    c.write(3, 1, &Color::new(0.05, 1.0, 0.75));
+    let ppm = c.as_ppm(Transfer::Srgb);
+    let decoded = Canvas::from_ppm(ppm.as_bytes(), Transfer::Srgb).unwrap();
+    for (expected, actual) in c.canvas.iter().zip(decoded.canvas.iter()) {
+      assert!((expected.r() - actual.r()).abs() < 0.005);
+      assert!((expected.g() - actual.g()).abs() < 0.005);
+      assert!((expected.b() - actual.b()).abs() < 0.005);
+    }
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn rejects_malformed_ppm() {
+    assert!(Canvas::from_ppm(b"P5 1 1 255 0", Transfer::Linear).is_err());
+    assert!(Canvas::from_ppm(b"P3 1 1 255 0 0", Transfer::Linear).is_err());
+    assert!(Canvas::from_ppm(b"P3 1 1 255 0 300 0", Transfer::Linear).is_err());
+    assert!(Canvas::from_ppm(b"P3 1 x 255", Transfer::Linear).is_err());
+    assert!(Canvas::from_ppm(b"P6 1 1 255\n\x01\x02", Transfer::Linear).is_err());
+    assert!(matches!(Canvas::from_ppm(b"P6 99999 99999 255\n", Transfer::Linear), Err(ImageError::Format(_))));
+    let overflowing = b"P3 18446744073709551615 2 255 0 0 0";
+    assert!(matches!(Canvas::from_ppm(overflowing, Transfer::Linear), Err(ImageError::Format(_))));
+  }
+
+  #[test]
+  fn implements_from_hdr_flat() {
+    let mut hdr = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n-Y 2 +X 1\n".to_vec();
+    hdr.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+    let c = Canvas::from_hdr(&hdr).unwrap();
+    assert_eq!(1, c.width);
+    assert_eq!(2, c.height);
+    assert_eq!(Color::new(1.0, 0.5, 0.0), c.canvas[0]);
+    assert_eq!(Color::new(0.0, 0.0, 0.0), c.canvas[1]);
+  }
+
+  #[test]
+  fn implements_from_hdr_run_length_encoded() {
+    let mut hdr = b"#?RGBE\n\n-Y 1 +X 8\n".to_vec();
+    hdr.extend_from_slice(&[2, 2, 0, 8]);
+    hdr.extend_from_slice(&[136, 128]); // red: run of 8
+    hdr.extend_from_slice(&[4, 0, 32, 64, 128, 132, 0]); // green: literals then a run
+    hdr.extend_from_slice(&[136, 0]); // blue
+    hdr.extend_from_slice(&[136, 130]); // exponent: values scaled by 4 / 256
+    let c = Canvas::from_hdr(&hdr).unwrap();
+    assert_eq!(Color::new(2.0, 0.0, 0.0), c.canvas[0]);
+    assert_eq!(Color::new(2.0, 2.0, 0.0), c.canvas[3]);
+    assert_eq!(Color::new(2.0, 0.0, 0.0), c.canvas[7]);
+  }
+
+  #[test]
+  fn implements_hdr_roundtrip() {
+    let mut c = Canvas::new(2, 2);
+    c.write(0, 0, &Color::new(1.0, 0.5, 0.0));
+    c.write(1, 0, &Color::new(1000.0, 3.0, 0.25));
+    c.write(0, 1, &Color::new(0.001, 0.002, 0.004));
+    let decoded = Canvas::from_hdr(&c.as_hdr()).unwrap();
+    assert_eq!((2, 2), (decoded.width, decoded.height));
+    assert_eq!(Color::new(1.0, 0.5, 0.0), decoded.canvas[0]);
+    assert_eq!(Color::new(0.0, 0.0, 0.0), decoded.canvas[3]);
+    for (a, b) in c.canvas.iter().zip(&decoded.canvas) {
+      // 8 bit mantissas relative to the brightest channel
+      let tolerance = a.r().max(a.g()).max(a.b()) / 128.0;
+      assert!((a.r() - b.r()).abs() <= tolerance && (a.g() - b.g()).abs() <= tolerance && (a.b() - b.b()).abs() <= tolerance);
+    }
+  }
+
+  #[test]
+  fn rejects_unknown_formats() {
+    let c = Canvas::new(1, 1);
+    assert!(matches!(c.save("image.png"), Err(ImageError::Format(_))));
+    assert!(matches!(c.save("image"), Err(ImageError::Format(_))));
+  }
+
+  #[test]
+  fn rejects_malformed_hdr() {
+    assert!(Canvas::from_hdr(b"P3 1 1 255 0 0 0").is_err());
+    assert!(Canvas::from_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\x00\x00\x00\x00").is_err());
+    assert!(Canvas::from_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\x00\x00\x00\x00").is_err());
+    assert!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 1 +X 2\n\x00\x00\x00\x00").is_err());
+    assert!(matches!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 99999 +X 99999\n\x00\x00\x00\x00"), Err(ImageError::Format(_))));
+    assert!(matches!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 2 +X 18446744073709551615\n"), Err(ImageError::Format(_))));
+    assert!(matches!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 0 +X 18446744073709551615\n"), Ok(c) if c.canvas.is_empty()));
+  }
+
+  #[test]
+  fn implements_iterators() {
+    let canvas = gradient(3, 2);
+    let rows: Vec<&[Color]> = canvas.rows().collect();
+    assert_eq!(2, rows.len());
+    assert_eq!(Color::new(2.0, 1.0, 1.0), rows[1][2]);
+    let pixels: Vec<(usize, usize, Color)> = canvas.pixels().collect();
+    assert_eq!(6, pixels.len());
+    assert_eq!((1, 1, Color::new(1.0, 1.0, 1.0)), pixels[4]);
+    assert!(canvas.pixels().all(|(x, y, c)| c == canvas.pixel_at(x, y)));
+    assert_eq!(0, Canvas::new(0, 0).rows().count());
+  }
+
+  #[test]
+  fn implements_fill_and_map() {
+    let mut canvas = Canvas::new(4, 3);
+    canvas.fill(&Color::new(0.5, 0.25, 1.0));
+    assert_eq!(Color::new(0.5, 0.25, 1.0), canvas.pixel_at(3, 2));
+    let doubled = canvas.map(|c| c * 2.0);
+    assert_eq!((4, 3), (doubled.width(), doubled.height()));
+    assert_eq!(Color::new(1.0, 0.5, 2.0), doubled.pixel_at(1, 1));
+  }
+
+  #[test]
+  fn implements_crop_and_blit() {
+    let canvas = gradient(5, 4);
+    let cropped = canvas.crop(1, 2, 3, 2);
+    assert_eq!((3, 2), (cropped.width(), cropped.height()));
+    assert_eq!(Color::new(1.0, 2.0, 1.0), cropped.pixel_at(0, 0));
+    assert_eq!(Color::new(3.0, 3.0, 1.0), cropped.pixel_at(2, 1));
+    // regions past the border are cut short
+    let corner = canvas.crop(3, 3, 10, 10);
+    assert_eq!((2, 1), (corner.width(), corner.height()));
+    let outside = canvas.crop(10, 0, 5, 5);
+    assert_eq!((0, 0), (outside.width(), outside.height()));
+    let below = canvas.crop(0, 4, 5, 5);
+    assert_eq!((0, 0), (below.width(), below.height()));
+
+    let mut target = Canvas::new(4, 4);
+    target.blit(&cropped, 2, -1);
+    assert_eq!(Color::new(1.0, 3.0, 1.0), target.pixel_at(2, 0));
+    assert_eq!(Color::new(2.0, 3.0, 1.0), target.pixel_at(3, 0));
+    assert_eq!(Color::new(0.0, 0.0, 0.0), target.pixel_at(2, 1));
+    assert_eq!(Color::new(0.0, 0.0, 0.0), target.pixel_at(1, 0));
+  }
+
+  #[test]
+  fn implements_paste_with_alpha() {
+    let mut target = Canvas::new(3, 1);
+    target.fill(&Color::new(1.0, 1.0, 1.0));
+    let mut source = Canvas::new(2, 1);
+    source.fill(&Color::new(0.0, 0.0, 0.0));
+    let mut alpha = Canvas::new(2, 1);
+    alpha.write(0, 0, &Color::new(0.25, 0.25, 0.25));
+    alpha.write(1, 0, &Color::new(1.0, 0.0, 0.5));
+    target.paste(&source, &alpha, 1, 0);
+    assert_eq!(Color::new(1.0, 1.0, 1.0), target.pixel_at(0, 0));
+    assert_eq!(Color::new(0.75, 0.75, 0.75), target.pixel_at(1, 0));
+    assert_eq!(Color::new(0.0, 1.0, 0.5), target.pixel_at(2, 0));
+  }
+
+  #[test]
+  #[should_panic(expected = "cannot paste a 2x2 image with a 1x2 alpha mask")]
+  fn rejects_mismatched_alpha() {
+    Canvas::new(3, 3).paste(&Canvas::new(2, 2), &Canvas::new(1, 2), 0, 0);
+  }
+
+  #[test]
+  fn implements_flip() {
+    let mut canvas = gradient(3, 3);
+    canvas.flip_horizontal();
+    assert_eq!(Color::new(2.0, 0.0, 1.0), canvas.pixel_at(0, 0));
+    assert_eq!(Color::new(1.0, 2.0, 1.0), canvas.pixel_at(1, 2));
+    canvas.flip_vertical();
+    assert_eq!(Color::new(2.0, 2.0, 1.0), canvas.pixel_at(0, 0));
+    assert_eq!(Color::new(0.0, 0.0, 1.0), canvas.pixel_at(2, 2));
+    assert_eq!(Color::new(1.0, 1.0, 1.0), canvas.pixel_at(1, 1));
+    canvas.flip_vertical();
+    canvas.flip_horizontal();
+    assert_eq!(gradient(3, 3), canvas);
+  }
+
+  #[test]
+  fn implements_resize() {
+    for filter in [ResizeFilter::Bilinear, ResizeFilter::Lanczos3] {
+      let mut flat = Canvas::new(7, 5);
+      flat.fill(&Color::new(0.2, 0.4, 0.6));
+      for (width, height) in [(7, 5), (16, 3), (2, 9)] {
+        let resized = flat.resize(width, height, filter);
+        assert_eq!((width, height), (resized.width(), resized.height()));
+        assert!(resized.pixels().all(|(_, _, c)| cmp_color(Color::new(0.2, 0.4, 0.6), c)));
+      }
+      assert_eq!(gradient(6, 4), gradient(6, 4).resize(6, 4, filter));
+    }
+
+    // doubling interpolates halfway between pixel centers
+    let doubled = gradient(4, 1).resize(8, 1, ResizeFilter::Bilinear);
+    assert!(cmp_color(Color::new(0.75, 0.0, 1.0), doubled.pixel_at(2, 0)));
+    assert!(cmp_color(Color::new(1.25, 0.0, 1.0), doubled.pixel_at(3, 0)));
+    // halving averages pairs
+    let halved = gradient(8, 2).resize(4, 1, ResizeFilter::Bilinear);
+    assert!(cmp_color(Color::new(2.5, 0.5, 1.0), halved.pixel_at(1, 0)));
+
+    // Lanczos keeps a hard edge steeper than bilinear, at the price of ringing
+    let mut edge = Canvas::new(8, 1);
+    for x in 4..8 {
+      edge.write(x, 0, &Color::new(1.0, 1.0, 1.0));
+    }
+    let (bilinear, lanczos) = (edge.resize(32, 1, ResizeFilter::Bilinear), edge.resize(32, 1, ResizeFilter::Lanczos3));
+    assert!(lanczos.pixel_at(17, 0).r() > bilinear.pixel_at(17, 0).r());
+    assert!(lanczos.pixels().any(|(_, _, c)| c.r() < 0.0));
+  }
+
+  #[test]
+  fn implements_bilinear_at() {
+    let canvas = gradient(3, 3);
+    assert!(cmp_color(Color::new(1.0, 1.0, 1.0), canvas.bilinear_at(1.5, 1.5)));
+    assert!(cmp_color(Color::new(1.25, 0.5, 1.0), canvas.bilinear_at(1.75, 1.0)));
+    assert!(cmp_color(Color::new(0.0, 2.0, 1.0), canvas.bilinear_at(-3.0, 7.0)));
+    assert_eq!(BLACK, Canvas::new(0, 3).bilinear_at(0.5, 0.5));
+    assert_eq!(BLACK, Canvas::new(0, 0).bilinear_at(0.5, 0.5));
+  }
+}
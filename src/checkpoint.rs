@@ -0,0 +1,264 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::errors::CheckpointError;
+use crate::render::{band_film_rows, Band, Film, RenderSettings, BAND_ROWS};
+use crate::world::World;
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{self, Write};
+use std::fs;
+
+const MAGIC: &[u8; 8] = b"RCCKPT01";
+
+/// Identifies the render a checkpoint file belongs to.
+///
+/// The file stores, for every finished band of rows, its float accumulation
+/// buffer (filter weighted color sums and weights) and its per-pixel sample
+/// counts. Random numbers need no state beyond the seed: every pixel sample
+/// derives its own generator from the seed and its position, so the missing
+/// bands come out exactly as they would have in one run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Checkpoint {
+  pub scene_hash: u64,
+  pub seed: u64,
+  pub width: usize,
+  pub height: usize
+}
+
+impl Checkpoint {
+  pub fn new(camera: &Camera, world: &World, settings: &RenderSettings) -> Self {
+    Self {
+      scene_hash: scene_hash(camera, world, settings),
+      seed: settings.seed,
+      width: camera.hsize,
+      height: camera.vsize
+    }
+  }
+
+  fn band_count(&self) -> usize {
+    self.height.div_ceil(BAND_ROWS)
+  }
+
+  /// Writes the finished `bands`, replacing `file` only once the new data is
+  /// completely written.
+  pub(crate) fn save(&self, file: &str, bands: &[Option<Band>]) -> Result<(), CheckpointError> {
+    let mut data = MAGIC.to_vec();
+    for value in [self.scene_hash, self.seed, self.width as u64, self.height as u64, bands.len() as u64] {
+      data.extend_from_slice(&value.to_le_bytes());
+    }
+    for (index, band) in bands.iter().enumerate() {
+      let band = match band {
+        Some(band) => band,
+        None => continue
+      };
+      let film = &band.film;
+      for value in [index as u64, film.top as u64, film.height as u64] {
+        data.extend_from_slice(&value.to_le_bytes());
+      }
+      for (sum, weight) in film.sums.iter().zip(&film.weights) {
+        for value in [sum.r(), sum.g(), sum.b(), *weight] {
+          data.extend_from_slice(&value.to_le_bytes());
+        }
+      }
+      for count in &band.sample_counts {
+        data.extend_from_slice(&(*count as u64).to_le_bytes());
+      }
+    }
+    let partial = format!("{}.partial", file);
+    fs::write(&partial, data)?;
+    fs::rename(&partial, file)?;
+    Ok(())
+  }
+
+  /// Bands saved in `file`, which must belong to this render.
+  pub(crate) fn load(&self, file: &str, settings: &RenderSettings) -> Result<Vec<Option<Band>>, CheckpointError> {
+    self.decode(&fs::read(file)?, settings)
+  }
+
+  fn decode(&self, data: &[u8], settings: &RenderSettings) -> Result<Vec<Option<Band>>, CheckpointError> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+      return Err(CheckpointError::Format(String::from("not a checkpoint file")));
+    }
+    let saved = Checkpoint {
+      scene_hash: reader.u64()?,
+      seed: reader.u64()?,
+      width: reader.usize()?,
+      height: reader.usize()?
+    };
+    if saved != *self || reader.usize()? != self.band_count() {
+      return Err(CheckpointError::Mismatch);
+    }
+
+    let mut bands: Vec<Option<Band>> = (0..self.band_count()).map(|_| None).collect();
+    while !reader.is_empty() {
+      let index = reader.usize()?;
+      let (top, rows) = (reader.usize()?, reader.usize()?);
+      let invalid = || CheckpointError::Format(format!("invalid band {}", index));
+      if index >= bands.len() || bands[index].is_some() {
+        return Err(invalid());
+      }
+      // the film must cover exactly the rows this band's samples splat into
+      let band_rows = index * BAND_ROWS..((index + 1) * BAND_ROWS).min(self.height);
+      let film_rows = band_film_rows(&band_rows, self.height, settings.filter);
+      if top != film_rows.start || top.checked_add(rows) != Some(film_rows.end) {
+        return Err(invalid());
+      }
+      let mut film = Film::rows(self.width, film_rows, settings.filter);
+      for (sum, weight) in film.sums.iter_mut().zip(film.weights.iter_mut()) {
+        *sum = Color::new(reader.f32()?, reader.f32()?, reader.f32()?);
+        *weight = reader.f32()?;
+      }
+      let sample_counts = (0..band_rows.len() * self.width).map(|_| reader.usize()).collect::<Result<_, _>>()?;
+      bands[index] = Some(Band { film, sample_counts });
+    }
+    Ok(bands)
+  }
+}
+
+struct Reader<'a> {
+  data: &'a [u8],
+  pos: usize
+}
+
+impl<'a> Reader<'a> {
+  fn is_empty(&self) -> bool {
+    self.pos >= self.data.len()
+  }
+
+  fn take(&mut self, n: usize) -> Result<&'a [u8], CheckpointError> {
+    let bytes = self.data.get(self.pos..self.pos + n)
+      .ok_or_else(|| CheckpointError::Format(String::from("unexpected end of data")))?;
+    self.pos += n;
+    Ok(bytes)
+  }
+
+  fn u64(&mut self) -> Result<u64, CheckpointError> {
+    Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+  }
+
+  fn usize(&mut self) -> Result<usize, CheckpointError> {
+    usize::try_from(self.u64()?).map_err(|_| CheckpointError::Format(String::from("value out of range")))
+  }
+
+  fn f32(&mut self) -> Result<f32, CheckpointError> {
+    Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+}
+
+/// 64-bit FNV-1a hash of everything that affects the rendered image. The
+/// thread count is left out, as images do not depend on it.
+pub fn scene_hash(camera: &Camera, world: &World, settings: &RenderSettings) -> u64 {
+  let mut hasher = Fnv1a(0xcbf2_9ce4_8422_2325);
+  let settings = RenderSettings { threads: 0, ..*settings };
+  // Debug output spells out every field, with floats written exactly
+  write!(hasher, "{:?}{:?}{:?}", camera, world, settings).unwrap();
+  hasher.0
+}
+
+struct Fnv1a(u64);
+
+impl Write for Fnv1a {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    for byte in s.bytes() {
+      self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{scene_hash, Checkpoint};
+  use crate::errors::CheckpointError;
+  use crate::render::{render_resumable, render_with_counts, CheckpointSettings, RenderSettings};
+  use crate::sampler::SamplePattern;
+  use crate::filter::Filter;
+  use crate::test_utils::default_world;
+  use crate::camera::{view_transform, Camera};
+  use rm::Tuple4;
+  use std::f32::consts::FRAC_PI_3;
+  use std::fs;
+  use std::path::Path;
+  use std::time::Duration;
+
+  fn scene() -> (Camera, crate::world::World, RenderSettings) {
+    let mut camera = Camera::new(11, 27, FRAC_PI_3);
+    camera.set_transform(view_transform(Tuple4::point(0.0, 0.5, -5.0), Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 1.0, 0.0))).unwrap();
+    let settings = RenderSettings {
+      samples_per_pixel: 3,
+      pattern: SamplePattern::Jittered,
+      filter: Filter::mitchell(),
+      seed: 5,
+      threads: 2,
+      ..RenderSettings::default()
+    };
+    (camera, default_world(), settings)
+  }
+
+  fn temp_file(name: &str) -> String {
+    std::env::temp_dir().join(format!("rusty_cage_{}_{}", std::process::id(), name)).to_string_lossy().into_owned()
+  }
+
+  #[test]
+  fn implements_scene_hash() {
+    let (camera, world, settings) = scene();
+    let hash = scene_hash(&camera, &world, &settings);
+    assert_eq!(hash, scene_hash(&camera, &world, &RenderSettings { threads: 7, ..settings }));
+    assert_ne!(hash, scene_hash(&camera, &world, &RenderSettings { seed: 6, ..settings }));
+    let mut moved = default_world();
+    moved.objects[1].set_transform(rm::Matrix4::scaling(0.5, 0.5, 0.50001));
+    assert_ne!(hash, scene_hash(&camera, &moved, &settings));
+  }
+
+  #[test]
+  fn implements_resume() {
+    let (camera, world, settings) = scene();
+    let expected = render_with_counts(&camera, &world, &settings);
+    let checkpoint = CheckpointSettings { file: temp_file("resume.ckpt"), interval: Duration::ZERO };
+    let interrupted = temp_file("interrupted.ckpt");
+
+    // keep a copy of the checkpoint written after the first band, as a killed run would leave it
+    let output = render_resumable(&camera, &world, &settings, &checkpoint, false, |p| {
+      if p.completed_rows == 8 {
+        fs::copy(&checkpoint.file, &interrupted).unwrap();
+      }
+    }).unwrap();
+    assert_eq!(expected.image, output.image);
+    assert!(!Path::new(&checkpoint.file).exists());
+
+    let header = Checkpoint::new(&camera, &world, &settings);
+    let saved = header.load(&interrupted, &settings).unwrap();
+    assert_eq!(1, saved.iter().flatten().count());
+
+    fs::rename(&interrupted, &checkpoint.file).unwrap();
+    let resumed = render_resumable(&camera, &world, &settings, &checkpoint, true, |p| assert!(p.completed_rows > 8)).unwrap();
+    assert_eq!(expected.image, resumed.image);
+    assert_eq!(expected.sample_counts, resumed.sample_counts);
+  }
+
+  #[test]
+  fn rejects_foreign_checkpoints() {
+    let (camera, world, settings) = scene();
+    let file = temp_file("foreign.ckpt");
+    let header = Checkpoint::new(&camera, &world, &settings);
+    header.save(&file, &[None, None, None, None]).unwrap();
+    let other = Checkpoint::new(&camera, &world, &RenderSettings { samples_per_pixel: 4, ..settings });
+    assert!(matches!(other.load(&file, &settings), Err(CheckpointError::Mismatch)));
+    let checkpoint = CheckpointSettings { file: file.clone(), interval: Duration::ZERO };
+    let resumed = render_resumable(&camera, &world, &RenderSettings { seed: 9, ..settings }, &checkpoint, true, |_| {});
+    assert!(matches!(resumed, Err(CheckpointError::Mismatch)));
+
+    let data = fs::read(&file).unwrap();
+    assert!(matches!(header.decode(&data[..20], &settings), Err(CheckpointError::Format(_))));
+    assert!(matches!(header.decode(b"P3 1 1 255 0 0 0", &settings), Err(CheckpointError::Format(_))));
+    // corrupt band headers: out-of-range index, overflowing rows, films not matching the band
+    for band in [[u64::MAX, 0, 8], [0, u64::MAX, u64::MAX], [1, 8, 8], [1, 5, u64::MAX]] {
+      let mut corrupt = data.clone();
+      band.iter().for_each(|value| corrupt.extend_from_slice(&value.to_le_bytes()));
+      assert!(matches!(header.decode(&corrupt, &settings), Err(CheckpointError::Format(_))));
+    }
+    fs::remove_file(&file).unwrap();
+    assert!(matches!(header.load(&file, &settings), Err(CheckpointError::Io(_))));
+  }
+}
@@ -0,0 +1,438 @@
+use rusty_cage::camera::MAX_PIXELS;
+use rusty_cage::errors::{CheckpointError, ImageError, SceneError};
+use rusty_cage::integrator::Integrator;
+use rusty_cage::render::Progress;
+
+use std::time::Duration;
+use std::{error::Error, fmt};
+
+pub const USAGE: &str = "\
+Usage: rusty-cage [OPTIONS] <SCENE>
+
+Renders SCENE, a scene file or the name of a built-in demo (sphere, spheres, sky).
+
+Options:
+  -o, --output <FILE>  Image to write, .ppm or .hdr [default: render.ppm]
+      --width <N>      Image width in pixels; keeps the aspect ratio unless --height is also given
+      --height <N>     Image height in pixels; keeps the aspect ratio unless --width is also given
+  -s, --samples <N>    Samples per pixel [default: 16]
+  -i, --integrator <NAME>
+                       Light transport: whitted for direct lighting, path for global illumination [default: whitted]
+  -t, --threads <N>    Worker threads, 0 for every core [default: 0]
+      --seed <N>       Random seed [default: 0]
+      --checkpoint <FILE>
+                       Save progress to FILE so an interrupted render can be resumed
+      --checkpoint-every <SECONDS>
+                       Time between checkpoint saves [default: 60]
+      --resume         Continue the render saved in the --checkpoint file
+  -p, --progressive    Render at 1 sample per pixel, then refine up to --samples, writing the image after every pass
+      --time <TIME>    Stop refining after TIME, such as 90s, 5m or 2h; implies --progressive
+      --aovs           Also write depth, normal, albedo, object-id, material-id, uv and position
+                       passes as HDR images named after the output, such as render.depth.hdr
+      --denoise        Smooth noise with an edge-aware filter guided by the normal, albedo and depth passes
+  -q, --quiet          Print nothing but errors
+  -h, --help           Print this help
+
+Post-processing, applied after denoising in the order given:
+      --bloom <THRESHOLD>   Make radiance above THRESHOLD glow
+      --vignette <STRENGTH> Darken the corners by STRENGTH, between 0 and 1
+      --aberration <AMOUNT> Split red and blue towards the edges, e.g. 0.005
+      --grain <STRENGTH>    Add film grain, e.g. 0.05
+      --lut <FILE>          Grade through a 3D LUT in .cube format
+";
+
+/// Post-processing step requested on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PostArg {
+  Bloom(f32),
+  Vignette(f32),
+  Aberration(f32),
+  Grain(f32),
+  Lut(String)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+  pub scene: String,
+  pub output: String,
+  pub width: Option<usize>,
+  pub height: Option<usize>,
+  pub samples: usize,
+  pub integrator: Integrator,
+  pub threads: usize,
+  pub seed: u64,
+  pub checkpoint: Option<String>,
+  pub checkpoint_every: u64,
+  pub resume: bool,
+  pub progressive: bool,
+  pub time: Option<Duration>,
+  pub aovs: bool,
+  pub denoise: bool,
+  pub post: Vec<PostArg>,
+  pub quiet: bool
+}
+
+impl Default for Options {
+  fn default() -> Self {
+    Self {
+      scene: String::new(),
+      output: String::from("render.ppm"),
+      width: None,
+      height: None,
+      samples: 16,
+      integrator: Integrator::Whitted,
+      threads: 0,
+      seed: 0,
+      checkpoint: None,
+      checkpoint_every: 60,
+      resume: false,
+      progressive: false,
+      time: None,
+      aovs: false,
+      denoise: false,
+      post: Vec::new(),
+      quiet: false
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+  Render(Box<Options>),
+  Help
+}
+
+#[derive(Debug)]
+pub enum CliError {
+  /// Invalid command line; the usage hint applies.
+  Usage(String),
+  Scene(SceneError),
+  Image(ImageError),
+  Checkpoint(CheckpointError),
+  /// A grading LUT given with `--lut` could not be loaded.
+  Lut(String, ImageError)
+}
+
+impl Error for CliError {}
+
+impl fmt::Display for CliError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CliError::Usage(msg) => write!(f, "{}", msg),
+      CliError::Scene(e) => write!(f, "{}", e),
+      CliError::Image(e) => write!(f, "{}", e),
+      CliError::Checkpoint(e) => write!(f, "{}", e),
+      CliError::Lut(file, e) => write!(f, "cannot load LUT '{}': {}", file, e)
+    }
+  }
+}
+
+impl From<SceneError> for CliError {
+  fn from(e: SceneError) -> Self {
+    CliError::Scene(e)
+  }
+}
+
+impl From<ImageError> for CliError {
+  fn from(e: ImageError) -> Self {
+    CliError::Image(e)
+  }
+}
+
+impl From<CheckpointError> for CliError {
+  fn from(e: CheckpointError) -> Self {
+    CliError::Checkpoint(e)
+  }
+}
+
+/// Parses the arguments following the program name. Options take their value
+/// either as the next argument or after `=`.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+  let mut options = Options::default();
+  let mut scene = None;
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    if !arg.starts_with('-') || arg == "-" {
+      if scene.is_some() {
+        return Err(CliError::Usage(format!("unexpected argument '{}'", arg)));
+      }
+      scene = Some(arg);
+      continue;
+    }
+    let (name, inline_value) = match arg.split_once('=') {
+      Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+      _ => (arg.clone(), None)
+    };
+    let mut value = || -> Result<String, CliError> {
+      match inline_value.clone().or_else(|| args.next()) {
+        Some(value) => Ok(value),
+        None => Err(CliError::Usage(format!("'{}' needs a value", name)))
+      }
+    };
+    match name.as_str() {
+      "-h" | "--help" => return Ok(Command::Help),
+      "-q" | "--quiet" => options.quiet = true,
+      "--resume" => options.resume = true,
+      "-p" | "--progressive" => options.progressive = true,
+      "--aovs" => options.aovs = true,
+      "--denoise" => options.denoise = true,
+      "--bloom" => options.post.push(PostArg::Bloom(decimal(&name, &value()?)?)),
+      "--vignette" => options.post.push(PostArg::Vignette(decimal(&name, &value()?)?)),
+      "--aberration" => options.post.push(PostArg::Aberration(decimal(&name, &value()?)?)),
+      "--grain" => options.post.push(PostArg::Grain(decimal(&name, &value()?)?)),
+      "--lut" => options.post.push(PostArg::Lut(value()?)),
+      "-o" | "--output" => options.output = value()?,
+      "--width" => options.width = Some(positive(&name, &value()?)?),
+      "--height" => options.height = Some(positive(&name, &value()?)?),
+      "-s" | "--samples" => options.samples = positive(&name, &value()?)?,
+      "-i" | "--integrator" => options.integrator = integrator(&name, &value()?)?,
+      "-t" | "--threads" => options.threads = number(&name, &value()?)?,
+      "--seed" => options.seed = number(&name, &value()?)?,
+      "--checkpoint" => options.checkpoint = Some(value()?),
+      "--checkpoint-every" => options.checkpoint_every = number(&name, &value()?)?,
+      "--time" => {
+        options.time = Some(duration(&name, &value()?)?);
+        options.progressive = true;
+      },
+      _ => return Err(CliError::Usage(format!("unknown option '{}'", name)))
+    }
+    if inline_value.is_some() && matches!(name.as_str(), "--help" | "--quiet" | "--resume" | "--progressive" | "--aovs" | "--denoise") {
+      return Err(CliError::Usage(format!("'{}' does not take a value", name)));
+    }
+  }
+  options.scene = scene.ok_or_else(|| CliError::Usage(String::from("missing scene file or demo name")))?;
+  if !options.output.to_ascii_lowercase().ends_with(".ppm") && !options.output.to_ascii_lowercase().ends_with(".hdr") {
+    return Err(CliError::Usage(format!("cannot write '{}': the output must end in .ppm or .hdr", options.output)));
+  }
+  if options.resume && options.checkpoint.is_none() {
+    return Err(CliError::Usage(String::from("'--resume' needs a '--checkpoint' file")));
+  }
+  if options.progressive && options.checkpoint.is_some() {
+    return Err(CliError::Usage(String::from("'--checkpoint' cannot be combined with progressive rendering")));
+  }
+  Ok(Command::Render(Box::new(options)))
+}
+
+fn number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, CliError> {
+  value.parse().map_err(|_| CliError::Usage(format!("'{}' expects a whole number, got '{}'", name, value)))
+}
+
+fn decimal(name: &str, value: &str) -> Result<f32, CliError> {
+  match value.parse::<f32>() {
+    Ok(v) if v.is_finite() => Ok(v),
+    _ => Err(CliError::Usage(format!("'{}' expects a number, got '{}'", name, value)))
+  }
+}
+
+fn positive(name: &str, value: &str) -> Result<usize, CliError> {
+  match number(name, value)? {
+    0 => Err(CliError::Usage(format!("'{}' must be at least 1", name))),
+    n => Ok(n)
+  }
+}
+
+fn integrator(name: &str, value: &str) -> Result<Integrator, CliError> {
+  match value {
+    "whitted" => Ok(Integrator::Whitted),
+    "path" => Ok(Integrator::path_tracer()),
+    _ => Err(CliError::Usage(format!("'{}' expects whitted or path, got '{}'", name, value)))
+  }
+}
+
+/// Whole seconds, minutes or hours such as `90s`, `5m` or `2h`; plain numbers are seconds.
+fn duration(name: &str, value: &str) -> Result<Duration, CliError> {
+  let (digits, unit) = match value.char_indices().last() {
+    Some((i, c)) if c.is_ascii_alphabetic() => (&value[..i], c),
+    _ => (value, 's')
+  };
+  let scale = match unit {
+    's' => 1,
+    'm' => 60,
+    'h' => 3600,
+    _ => return Err(CliError::Usage(format!("'{}' expects a time such as 90s, 5m or 2h, got '{}'", name, value)))
+  };
+  match digits.parse::<u64>().ok().and_then(|n| n.checked_mul(scale)) {
+    Some(seconds) => Ok(Duration::from_secs(seconds)),
+    None => Err(CliError::Usage(format!("'{}' expects a time such as 90s, 5m or 2h, got '{}'", name, value)))
+  }
+}
+
+/// File for AOV pass `name` of a render written to `output`.
+pub fn aov_file(output: &str, name: &str) -> String {
+  let stem = match output.rfind('.') {
+    Some(dot) if !output[dot..].contains('/') => &output[..dot],
+    _ => output
+  };
+  format!("{}.{}.hdr", stem, name)
+}
+
+/// Image size for `options` on a scene rendered at `hsize` x `vsize`; a single
+/// dimension keeps the scene's aspect ratio.
+pub fn image_size(options: &Options, hsize: usize, vsize: usize) -> Result<(usize, usize), CliError> {
+  let aspect = |a: usize, b: usize, c: usize| (a as f64 * b as f64 / c as f64).round().max(1.0) as usize;
+  let (width, height) = match (options.width, options.height) {
+    (Some(width), Some(height)) => (width, height),
+    (Some(width), None) => (width, aspect(width, vsize, hsize)),
+    (None, Some(height)) => (aspect(height, hsize, vsize), height),
+    (None, None) => (hsize, vsize)
+  };
+  match width.checked_mul(height) {
+    Some(pixels) if pixels <= MAX_PIXELS => Ok((width, height)),
+    _ => Err(CliError::Usage(format!("a {}x{} image has more than {} pixels", width, height, MAX_PIXELS)))
+  }
+}
+
+const BAR_WIDTH: usize = 30;
+
+/// One-line progress bar with the estimated time left, extrapolated from the
+/// time taken so far.
+pub fn progress_bar(progress: Progress, elapsed: Duration) -> String {
+  let fraction = progress.fraction().clamp(0.0, 1.0);
+  let filled = (fraction * BAR_WIDTH as f32).round() as usize;
+  let eta = if fraction > 0.0 {
+    format_duration(elapsed.mul_f32((1.0 - fraction) / fraction))
+  } else {
+    String::from("--")
+  };
+  format!(
+    "[{}{}] {:3.0}% ETA {}",
+    "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), fraction * 100.0, eta
+  )
+}
+
+/// Whole seconds as `42s`, `3m07s` or `1h05m`.
+pub fn format_duration(duration: Duration) -> String {
+  let seconds = duration.as_secs_f32().round() as u64;
+  match seconds {
+    s if s < 60 => format!("{}s", s),
+    s if s < 3600 => format!("{}m{:02}s", s / 60, s % 60),
+    s => format!("{}h{:02}m", s / 3600, s % 3600 / 60)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    aov_file, format_duration, image_size, parse_args, progress_bar, CliError, Command, Duration, Integrator, Options, PostArg,
+    Progress
+  };
+
+  fn parse(args: &[&str]) -> Result<Command, CliError> {
+    parse_args(args.iter().map(|a| a.to_string()))
+  }
+
+  fn usage_error(args: &[&str]) -> String {
+    match parse(args) {
+      Err(CliError::Usage(msg)) => msg,
+      other => panic!("expected a usage error, got {:?}", other)
+    }
+  }
+
+  #[test]
+  fn implements_parse_args() {
+    assert_eq!(
+      Ok(Command::Render(Box::new(Options { scene: String::from("spheres"), ..Options::default() }))),
+      parse(&["spheres"]).map_err(|e| e.to_string())
+    );
+    let expected = Options {
+      scene: String::from("scenes/spheres.yaml"),
+      output: String::from("out/image.HDR"),
+      width: Some(640),
+      height: Some(480),
+      samples: 64,
+      integrator: Integrator::path_tracer(),
+      threads: 3,
+      seed: 42,
+      checkpoint: Some(String::from("render.ckpt")),
+      checkpoint_every: 300,
+      resume: true,
+      progressive: false,
+      time: None,
+      aovs: true,
+      denoise: true,
+      post: vec![PostArg::Grain(0.05), PostArg::Lut(String::from("film.cube")), PostArg::Vignette(0.3)],
+      quiet: true
+    };
+    let args = [
+      "--width", "640", "-o", "out/image.HDR", "scenes/spheres.yaml", "--height=480", "-s", "64", "-i", "path", "--threads=3",
+      "--seed", "42", "--checkpoint", "render.ckpt", "--checkpoint-every=300", "--resume", "--aovs", "--denoise", "--grain=0.05", "--lut", "film.cube", "--vignette", ".3", "-q"
+    ];
+    assert_eq!(Ok(Command::Render(Box::new(expected))), parse(&args).map_err(|e| e.to_string()));
+    assert_eq!(Ok(Command::Help), parse(&["spheres", "--help"]).map_err(|e| e.to_string()));
+
+    let progressive = Options { scene: String::from("sky"), progressive: true, ..Options::default() };
+    assert_eq!(Ok(Command::Render(Box::new(progressive.clone()))), parse(&["-p", "sky"]).map_err(|e| e.to_string()));
+    for (time, seconds) in [("60s", 60), ("90", 90), ("5m", 300), ("2h", 7200)] {
+      let timed = Options { time: Some(Duration::from_secs(seconds)), ..progressive.clone() };
+      assert_eq!(Ok(Command::Render(Box::new(timed))), parse(&["sky", "--time", time]).map_err(|e| e.to_string()));
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_args() {
+    assert_eq!("missing scene file or demo name", usage_error(&[]));
+    assert_eq!("unexpected argument 'b'", usage_error(&["a", "b"]));
+    assert_eq!("unknown option '--frobnicate'", usage_error(&["a", "--frobnicate"]));
+    assert_eq!("'--output' needs a value", usage_error(&["a", "--output"]));
+    assert_eq!("'--samples' expects a whole number, got 'lots'", usage_error(&["a", "--samples", "lots"]));
+    assert_eq!("'--width' must be at least 1", usage_error(&["a", "--width=0"]));
+    assert_eq!("'--seed' expects a whole number, got '-1'", usage_error(&["a", "--seed=-1"]));
+    assert_eq!("'--quiet' does not take a value", usage_error(&["a", "--quiet=yes"]));
+    assert_eq!("cannot write 'a.png': the output must end in .ppm or .hdr", usage_error(&["a", "-o", "a.png"]));
+    assert_eq!("'--resume' needs a '--checkpoint' file", usage_error(&["a", "--resume"]));
+    assert_eq!("'--bloom' expects a number, got 'bright'", usage_error(&["a", "--bloom", "bright"]));
+    assert_eq!("'--time' expects a time such as 90s, 5m or 2h, got '1d'", usage_error(&["a", "--time=1d"]));
+    assert_eq!("'--vignette' expects a number, got 'x'", usage_error(&["a", "--vignette", "x"]));
+    assert_eq!("'--integrator' expects whitted or path, got 'bdpt'", usage_error(&["a", "--integrator=bdpt"]));
+    assert_eq!("'--time' expects a time such as 90s, 5m or 2h, got 'm'", usage_error(&["a", "--time=m"]));
+    assert_eq!(
+      "'--time' expects a time such as 90s, 5m or 2h, got '9999999999999999h'",
+      usage_error(&["a", "--time", "9999999999999999h"])
+    );
+    assert_eq!(
+      "'--checkpoint' cannot be combined with progressive rendering",
+      usage_error(&["a", "-p", "--checkpoint", "a.ckpt"])
+    );
+  }
+
+  #[test]
+  fn implements_image_size() {
+    let size = |width, height| image_size(&Options { width, height, ..Options::default() }, 40, 20);
+    assert_eq!((40, 20), size(None, None).unwrap());
+    assert_eq!((100, 50), size(Some(100), None).unwrap());
+    assert_eq!((2, 1), size(None, Some(1)).unwrap());
+    assert_eq!((7, 9), size(Some(7), Some(9)).unwrap());
+    match size(Some(100_000_000_000), None) {
+      Err(CliError::Usage(msg)) => assert_eq!("a 100000000000x50000000000 image has more than 67108864 pixels", msg),
+      other => panic!("expected a usage error, got {:?}", other.map_err(|e| e.to_string()))
+    }
+    assert!(matches!(size(Some(usize::MAX), Some(2)), Err(CliError::Usage(_))));
+  }
+
+  #[test]
+  fn implements_aov_file() {
+    assert_eq!("render.depth.hdr", aov_file("render.ppm", "depth"));
+    assert_eq!("out.v2/frame.normal.hdr", aov_file("out.v2/frame.hdr", "normal"));
+  }
+
+  #[test]
+  fn implements_progress_bar() {
+    let progress = Progress { completed_rows: 25, total_rows: 100 };
+    assert_eq!(
+      "[########----------------------]  25% ETA 30s",
+      progress_bar(progress, Duration::from_secs(10))
+    );
+    let start = Progress { completed_rows: 0, total_rows: 100 };
+    assert_eq!("[------------------------------]   0% ETA --", progress_bar(start, Duration::from_secs(1)));
+    let done = Progress { completed_rows: 100, total_rows: 100 };
+    assert!(progress_bar(done, Duration::from_secs(5)).ends_with("100% ETA 0s"));
+  }
+
+  #[test]
+  fn implements_format_duration() {
+    assert_eq!("0s", format_duration(Duration::from_millis(400)));
+    assert_eq!("59s", format_duration(Duration::from_secs(59)));
+    assert_eq!("3m07s", format_duration(Duration::from_secs(187)));
+    assert_eq!("1h05m", format_duration(Duration::from_secs(3900)));
+  }
+}
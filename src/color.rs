@@ -3,9 +3,46 @@ use std::ops::{Add, Mul, Sub};
 
 pub const BLACK: Color = Color([0.0, 0.0, 0.0]);
 
+/// Linear RGB color. All shading math happens in linear space; values are only
+/// encoded for display (see `Transfer`) when exported.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color([f32; 3]);
 
+/// Transfer function between linear light and encoded display values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transfer {
+  /// No encoding; channels are stored as linear values.
+  Linear,
+  /// Exact piecewise sRGB curve (IEC 61966-2-1).
+  Srgb,
+  /// Plain power curve with the given display gamma, e.g. `Gamma(2.2)`.
+  Gamma(f32)
+}
+
+impl Transfer {
+  /// Linear value -> encoded value.
+  pub fn encode(&self, v: f32) -> f32 {
+    match self {
+      Transfer::Linear => v,
+      Transfer::Srgb => {
+        if v <= 0.003_130_8 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+      },
+      Transfer::Gamma(gamma) => v.max(0.0).powf(1.0 / gamma)
+    }
+  }
+
+  /// Encoded value -> linear value.
+  pub fn decode(&self, v: f32) -> f32 {
+    match self {
+      Transfer::Linear => v,
+      Transfer::Srgb => {
+        if v <= 0.040_45 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+      },
+      Transfer::Gamma(gamma) => v.max(0.0).powf(*gamma)
+    }
+  }
+}
+
 impl Color {
   pub fn new(r: f32, g: f32, b: f32) -> Self {
     Self([r, g, b])
@@ -22,6 +59,19 @@ impl Color {
   pub fn b(&self) -> f32 {
     self.0[2]
   }
+
+  /// Relative luminance of linear Rec. 709 / sRGB primaries.
+  pub fn luminance(&self) -> f32 {
+    0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+  }
+
+  pub fn encoded(&self, transfer: Transfer) -> Self {
+    Self([transfer.encode(self.r()), transfer.encode(self.g()), transfer.encode(self.b())])
+  }
+
+  pub fn decoded(&self, transfer: Transfer) -> Self {
+    Self([transfer.decode(self.r()), transfer.decode(self.g()), transfer.decode(self.b())])
+  }
 }
 
 impl fmt::Display for Color {
@@ -93,7 +143,7 @@ impl Mul<Color> for f32 {
 
 #[cfg(test)]
 mod tests {
-  use super::Color;
+  use super::{Color, Transfer};
 
   static EPSILON: f32 = 0.0001;
 
@@ -154,4 +204,43 @@ mod tests {
     let c2 = Color::new(0.9, 1.0, 0.1);
     assert!(equal(Color::new(0.9, 0.2, 0.04), c1 * c2));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn implements_luminance() {
+    assert!(f32_cmp(1.0, Color::new(1.0, 1.0, 1.0).luminance()));
+    assert_eq!(0.7152, Color::new(0.0, 1.0, 0.0).luminance());
+  }
+
+  #[test]
+  fn implements_srgb_transfer() {
+    let srgb = Transfer::Srgb;
+    assert_eq!(0.0, srgb.encode(0.0));
+    assert!((srgb.encode(1.0) - 1.0).abs() < EPSILON);
+    assert!((srgb.encode(0.5) - 0.735_356_9).abs() < EPSILON);
+    assert!((srgb.encode(0.002) - 0.025_84).abs() < EPSILON);
+    assert!((srgb.decode(0.735_356_9) - 0.5).abs() < EPSILON);
+    assert!((srgb.decode(0.02) - 0.001_547_99).abs() < EPSILON);
+    for i in 0..=100 {
+      let v = i as f32 / 100.0;
+      assert!((srgb.decode(srgb.encode(v)) - v).abs() < EPSILON);
+    }
+  }
+
+  #[test]
+  fn implements_gamma_transfer() {
+    let gamma = Transfer::Gamma(2.2);
+    assert!((gamma.encode(0.5) - 0.729_740_1).abs() < EPSILON);
+    assert!((gamma.decode(0.729_740_1) - 0.5).abs() < EPSILON);
+    assert_eq!(0.0, gamma.encode(-1.0));
+    assert_eq!(0.25, Transfer::Linear.encode(0.25));
+    assert_eq!(0.25, Transfer::Linear.decode(0.25));
+  }
+
+  #[test]
+  fn implements_encoded_and_decoded() {
+    let c = Color::new(0.0, 0.5, 1.0);
+    let encoded = c.encoded(Transfer::Srgb);
+    assert!(equal(Color::new(0.0, 0.735_356_9, 1.0), encoded));
+    assert!(equal(c, encoded.decoded(Transfer::Srgb)));
+  }
+}
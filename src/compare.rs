@@ -0,0 +1,271 @@
+use crate::canvas::Canvas;
+use crate::color::{Color, Transfer};
+use crate::post::gaussian_blur;
+use crate::render::heat;
+
+use std::fmt;
+
+/// Standard deviation in pixels of the window SSIM compares statistics over.
+const SSIM_SIGMA: f32 = 1.5;
+/// Blur standing in for the eye's limited resolution in `flip_map`, about
+/// what a viewer at arm's length from a desktop monitor resolves.
+const FLIP_SIGMA: f32 = 0.5;
+
+/// Every metric between two images, see the `Canvas` methods of the same names.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Comparison {
+  pub mse: f32,
+  pub psnr: f32,
+  pub ssim: f32,
+  pub flip: f32
+}
+
+impl fmt::Display for Comparison {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "MSE {:.6}, PSNR {:.2} dB, SSIM {:.4}, FLIP {:.4}", self.mse, self.psnr, self.ssim, self.flip)
+  }
+}
+
+/// Image differences. Every method compares pixels at the same position and
+/// panics when the two canvases differ in size.
+impl Canvas {
+  pub fn compare(&self, other: &Canvas) -> Comparison {
+    Comparison { mse: self.mse(other), psnr: self.psnr(other), ssim: self.ssim(other), flip: self.flip(other) }
+  }
+
+  /// Per-channel absolute difference.
+  pub fn abs_difference(&self, other: &Canvas) -> Canvas {
+    self.zip(other, |a, b| {
+      let d = a - b;
+      Color::new(d.r().abs(), d.g().abs(), d.b().abs())
+    })
+  }
+
+  /// Mean squared difference over all channels of all pixels, on the stored
+  /// linear values.
+  pub fn mse(&self, other: &Canvas) -> f32 {
+    let difference = self.zip(other, |a, b| a - b);
+    let sum: f32 = difference.pixels().map(|(_, _, d)| d.r() * d.r() + d.g() * d.g() + d.b() * d.b()).sum();
+    sum / (3 * self.width() * self.height()).max(1) as f32
+  }
+
+  /// Peak signal-to-noise ratio in decibels, taking 1 as the peak; identical
+  /// images are infinitely far apart.
+  pub fn psnr(&self, other: &Canvas) -> f32 {
+    -10.0 * self.mse(other).log10()
+  }
+
+  /// Mean structural similarity (Wang et al. 2004) of the display luma, with a
+  /// Gaussian window. 1 for identical images, lower as structure differs.
+  pub fn ssim(&self, other: &Canvas) -> f32 {
+    let (a, b) = (luma(self), luma(other));
+    let mean = |canvas: &Canvas| gaussian_blur(canvas, SSIM_SIGMA);
+    let (mu_a, mu_b) = (mean(&a), mean(&b));
+    let (aa, bb, ab) = (mean(&a.zip(&a, |x, y| x * y)), mean(&b.zip(&b, |x, y| x * y)), mean(&a.zip(&b, |x, y| x * y)));
+    let (c1, c2) = (0.01f32.powi(2), 0.03f32.powi(2));
+    let sum: f32 = mu_a.pixels().map(|(x, y, ma)| {
+      let (ma, mb) = (ma.r(), mu_b.pixel_at(x, y).r());
+      let var_a = aa.pixel_at(x, y).r() - ma * ma;
+      let var_b = bb.pixel_at(x, y).r() - mb * mb;
+      let covariance = ab.pixel_at(x, y).r() - ma * mb;
+      (2.0 * ma * mb + c1) * (2.0 * covariance + c2) / ((ma * ma + mb * mb + c1) * (var_a + var_b + c2))
+    }).sum();
+    sum / (self.width() * self.height()).max(1) as f32
+  }
+
+  /// Mean of `flip_map`.
+  pub fn flip(&self, other: &Canvas) -> f32 {
+    let map = self.flip_map(other);
+    map.pixels().map(|(_, _, e)| e.r()).sum::<f32>() / (self.width() * self.height()).max(1) as f32
+  }
+
+  /// Per-pixel perceived difference in [0, 1], after the FLIP metric
+  /// (Andersson et al. 2020), in gray. Both images are clamped to the display
+  /// range and blurred as the eye would see them, then compared in CIELAB by
+  /// a hybrid lightness and chroma distance. Differences in edges, which
+  /// draw the eye, raise the error towards 1.
+  pub fn flip_map(&self, other: &Canvas) -> Canvas {
+    let display = |canvas: &Canvas| canvas.map(|c| Color::new(c.r().clamp(0.0, 1.0), c.g().clamp(0.0, 1.0), c.b().clamp(0.0, 1.0)));
+    let (a, b) = (display(self), display(other));
+    let (seen_a, seen_b) = (gaussian_blur(&a, FLIP_SIGMA).map(lab), gaussian_blur(&b, FLIP_SIGMA).map(lab));
+    let (edges_a, edges_b) = (edges(&a.map(lab)), edges(&b.map(lab)));
+    seen_a.zip(&seen_b, |la, lb| {
+      let e = color_error(hyab(la, lb));
+      Color::new(e, e, e)
+    }).zip(&edges_a.zip(&edges_b, |ea, eb| ea - eb), |color, edge| {
+      let feature = (edge.r().abs() / std::f32::consts::SQRT_2).min(1.0).sqrt();
+      let e = color.r().powf(1.0 - feature);
+      Color::new(e, e, e)
+    })
+  }
+
+  /// Visualises `flip_map` from blue (no difference) through green to red
+  /// (glaring). The scale is absolute, so heatmaps of different images compare.
+  pub fn difference_heatmap(&self, other: &Canvas) -> Canvas {
+    self.flip_map(other).map(|e| heat(e.r()))
+  }
+
+  fn zip<F: Fn(Color, Color) -> Color>(&self, other: &Canvas, f: F) -> Canvas {
+    assert!(
+      self.width() == other.width() && self.height() == other.height(),
+      "cannot compare a {}x{} image with a {}x{} one", self.width(), self.height(), other.width(), other.height()
+    );
+    let mut zipped = Canvas::new(self.width(), self.height());
+    for (x, y, a) in self.pixels() {
+      zipped.write(x, y, &f(a, other.pixel_at(x, y)));
+    }
+    zipped
+  }
+}
+
+/// sRGB encoded luminance of the display range, in all three channels.
+fn luma(canvas: &Canvas) -> Canvas {
+  canvas.map(|c| {
+    let y = Transfer::Srgb.encode(c.luminance().clamp(0.0, 1.0));
+    Color::new(y, y, y)
+  })
+}
+
+/// CIELAB coordinates of a linear sRGB color under D65, as L*, a*, b*.
+fn lab(c: Color) -> Color {
+  let x = (0.4124 * c.r() + 0.3576 * c.g() + 0.1805 * c.b()) / 0.9505;
+  let y = 0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b();
+  let z = (0.0193 * c.r() + 0.1192 * c.g() + 0.9505 * c.b()) / 1.089;
+  let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+  let (fx, fy, fz) = (f(x), f(y), f(z));
+  Color::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Distance between two CIELAB colors, taxicab in lightness and Euclidean in
+/// chroma, which matches large color differences better than plain Euclidean.
+fn hyab(a: Color, b: Color) -> f32 {
+  let d = a - b;
+  d.r().abs() + (d.g() * d.g() + d.b() * d.b()).sqrt()
+}
+
+/// Maps a HyAB distance to [0, 1] as FLIP does: linearly up to 0.95 at 40%
+/// of the distance between green and blue, the most different pair of
+/// primaries, and compressed from there on.
+fn color_error(distance: f32) -> f32 {
+  let max = hyab(lab(Color::new(0.0, 1.0, 0.0)), lab(Color::new(0.0, 0.0, 1.0))).powf(0.7);
+  let (knee, at_knee) = (0.4 * max, 0.95);
+  let d = distance.powf(0.7);
+  if d < knee {
+    d / knee * at_knee
+  } else {
+    (at_knee + (d - knee) / (max - knee) * (1.0 - at_knee)).min(1.0)
+  }
+}
+
+/// Sobel gradient magnitude of the lightness of a CIELAB image, scaled so a
+/// black to white step gives 1; edge pixels repeat past the border.
+fn edges(image: &Canvas) -> Canvas {
+  let (width, height) = (image.width() as isize, image.height() as isize);
+  let lightness = |x: isize, y: isize| image.pixel_at(x.clamp(0, width - 1) as usize, y.clamp(0, height - 1) as usize).r() / 100.0;
+  let mut magnitudes = Canvas::new(image.width(), image.height());
+  for (x, y, _) in image.pixels() {
+    let (x, y) = (x as isize, y as isize);
+    let gx = lightness(x + 1, y - 1) + 2.0 * lightness(x + 1, y) + lightness(x + 1, y + 1)
+      - lightness(x - 1, y - 1) - 2.0 * lightness(x - 1, y) - lightness(x - 1, y + 1);
+    let gy = lightness(x - 1, y + 1) + 2.0 * lightness(x, y + 1) + lightness(x + 1, y + 1)
+      - lightness(x - 1, y - 1) - 2.0 * lightness(x, y - 1) - lightness(x + 1, y - 1);
+    let g = (gx * gx + gy * gy).sqrt() / 4.0;
+    magnitudes.write(x as usize, y as usize, &Color::new(g, g, g));
+  }
+  magnitudes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Comparison;
+  use crate::canvas::Canvas;
+  use crate::color::Color;
+  use crate::rng::Rng;
+
+  fn gray(width: usize, height: usize, v: f32) -> Canvas {
+    let mut canvas = Canvas::new(width, height);
+    canvas.fill(&Color::new(v, v, v));
+    canvas
+  }
+
+  /// Vertical stripes two pixels wide.
+  fn stripes() -> Canvas {
+    let mut canvas = gray(16, 16, 0.1);
+    for y in 0..16 {
+      for x in (0..16).filter(|x| x / 2 % 2 == 0) {
+        canvas.write(x, y, &Color::new(0.8, 0.8, 0.8));
+      }
+    }
+    canvas
+  }
+
+  fn noisy(image: &Canvas, amount: f32, seed: u64) -> Canvas {
+    let mut rng = Rng::new(seed);
+    image.map(|c| c + Color::new(1.0, 1.0, 1.0) * ((rng.next_f32() - 0.5) * amount))
+  }
+
+  #[test]
+  fn implements_difference_and_psnr() {
+    let (a, mut b) = (gray(4, 2, 0.5), gray(4, 2, 0.5));
+    b.write(1, 1, &Color::new(0.3, 0.9, 0.5));
+    let difference = a.abs_difference(&b);
+    assert_eq!(Color::new(0.0, 0.0, 0.0), difference.pixel_at(0, 0));
+    assert!(crate::test_utils::cmp_color(Color::new(0.2, 0.4, 0.0), difference.pixel_at(1, 1)));
+    assert!((a.mse(&b) - (0.04 + 0.16) / 24.0).abs() < 1e-6);
+    assert!((a.psnr(&b) - 10.0 * (24.0f32 / 0.2).log10()).abs() < 1e-3);
+    assert_eq!(0.0, a.mse(&a));
+    assert_eq!(f32::INFINITY, a.psnr(&a));
+  }
+
+  #[test]
+  fn implements_ssim() {
+    let image = stripes();
+    assert!((image.ssim(&image) - 1.0).abs() < 1e-4);
+    let (slightly, badly) = (noisy(&image, 0.05, 1), noisy(&image, 0.5, 1));
+    assert!(image.ssim(&slightly) > 0.9);
+    assert!(image.ssim(&badly) < image.ssim(&slightly));
+    // losing the structure costs more than a brightness shift of the same energy
+    let blurred = crate::post::gaussian_blur(&image, 2.0);
+    let shift = image.mse(&blurred).sqrt();
+    let brighter = image.map(|c| c + Color::new(shift, shift, shift));
+    assert!((image.mse(&brighter) - image.mse(&blurred)).abs() < 1e-3);
+    assert!(image.ssim(&blurred) < image.ssim(&brighter));
+  }
+
+  #[test]
+  fn implements_flip() {
+    let image = stripes();
+    assert_eq!(0.0, image.flip(&image));
+    assert!(gray(8, 8, 0.0).flip(&gray(8, 8, 1.0)) > 0.9);
+    let small = image.flip(&noisy(&image, 0.02, 3));
+    let large = image.flip(&noisy(&image, 0.3, 3));
+    assert!(small > 0.0 && small < 0.1 && large > small);
+    // values past white look the same on a display
+    assert_eq!(0.0, gray(8, 8, 1.0).flip(&gray(8, 8, 4.0)));
+    // a change that makes an edge stands out more than the same change everywhere
+    let mut band = gray(16, 16, 0.5);
+    for y in 0..16 {
+      band.write(7, y, &Color::new(0.6, 0.6, 0.6));
+      band.write(8, y, &Color::new(0.6, 0.6, 0.6));
+    }
+    let base = gray(16, 16, 0.5);
+    assert!(base.flip_map(&band).pixel_at(7, 8).r() > base.flip_map(&gray(16, 16, 0.6)).pixel_at(7, 8).r());
+  }
+
+  #[test]
+  fn implements_comparison_and_heatmap() {
+    let (image, other) = (stripes(), noisy(&stripes(), 0.1, 9));
+    let comparison = image.compare(&other);
+    assert_eq!(Comparison { mse: image.mse(&other), psnr: image.psnr(&other), ssim: image.ssim(&other), flip: image.flip(&other) }, comparison);
+    assert!(comparison.to_string().starts_with("MSE 0.000"));
+    let heatmap = image.difference_heatmap(&image);
+    assert!(heatmap.pixels().all(|(_, _, c)| c == Color::new(0.0, 0.0, 1.0)));
+    assert!(gray(2, 2, 0.0).difference_heatmap(&gray(2, 2, 1.0)).pixel_at(1, 1).r() > 0.9);
+  }
+
+  #[test]
+  #[should_panic(expected = "cannot compare a 4x2 image with a 2x4 one")]
+  fn rejects_different_sizes() {
+    gray(4, 2, 0.0).mse(&gray(2, 4, 0.0));
+  }
+}
@@ -0,0 +1,74 @@
+use rusty_cage::area_light::AreaLight;
+use rusty_cage::camera::{view_transform, Camera};
+use rusty_cage::color::Color;
+use rusty_cage::environment::Background;
+use rusty_cage::errors::SceneError;
+use rusty_cage::material::Material;
+use rusty_cage::scene::Scene;
+use rusty_cage::sky::PhysicalSky;
+use rusty_cage::sphere::Sphere;
+use rusty_cage::world::World;
+use rm::{Matrix4, Tuple4};
+
+pub const NAMES: [&str; 3] = ["sphere", "spheres", "sky"];
+
+/// Built-in scene called `name`, if there is one.
+pub fn scene(name: &str) -> Option<Result<Scene, SceneError>> {
+  match name {
+    "sphere" => Some(Ok(sphere())),
+    "spheres" => Some(Scene::parse(include_str!("../scenes/spheres.yaml"))),
+    "sky" => Some(sky()),
+    _ => None
+  }
+}
+
+/// A single sphere under a soft area light.
+fn sphere() -> Scene {
+  let wall_z = 10.0;
+  let half_wall_size: f32 = 7.0 / 2.0;
+
+  let mut sphere = Sphere::unit();
+  sphere.set_material(Material { color: Color::new(1.0, 0.2, 1.0), ..Material::default() });
+  let light = AreaLight::new(
+    Tuple4::point(-11.0, 10.0, -11.0),
+    Tuple4::vector(2.0, 0.0, 0.0), 4,
+    Tuple4::vector(0.0, 0.0, 2.0), 4,
+    true, Color::new(1.0, 1.0, 1.0)
+  );
+  let world = World::new(vec![sphere], vec![Box::new(light)]);
+
+  // the camera sits at z = -5 looking at a wall of `wall_size` at `wall_z`
+  let field_of_view = 2.0 * (half_wall_size / (wall_z + 5.0)).atan();
+  let mut camera = Camera::new(100, 100, field_of_view);
+  camera.set_transform(view_transform(
+    Tuple4::point(0.0, 0.0, -5.0),
+    Tuple4::point(0.0, 0.0, 0.0),
+    Tuple4::vector(0.0, 1.0, 0.0)
+  )).unwrap();
+  Scene { camera, world }
+}
+
+/// The `spheres` scene lit by late afternoon daylight.
+fn sky() -> Result<Scene, SceneError> {
+  let mut scene = Scene::parse(include_str!("../scenes/spheres.yaml"))?;
+  let sky = PhysicalSky::new(0.35, 2.4, 3.0);
+  scene.world.lights = vec![Box::new(sky.sun_light())];
+  scene.world.background = Background::Sky(sky);
+  // a wider floor so the horizon shows behind the spheres
+  scene.world.objects[0].set_transform(Matrix4::scaling(100.0, 0.01, 100.0));
+  Ok(scene)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{scene, NAMES};
+
+  #[test]
+  fn implements_demo_scenes() {
+    for name in NAMES {
+      let scene = scene(name).unwrap().unwrap();
+      assert!(!scene.world.objects.is_empty() && !scene.world.lights.is_empty(), "{}", name);
+    }
+    assert!(scene("teapot").is_none());
+  }
+}
@@ -0,0 +1,195 @@
+use crate::aov::Aovs;
+use crate::canvas::Canvas;
+use crate::color::{Color, BLACK};
+
+/// Taps of the B3 spline kernel, applied along both axes.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Tuning of `denoise`. Each `*_sigma` sets how different two pixels may be
+/// in that buffer before they stop being averaged; larger values smooth more.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DenoiseSettings {
+  /// Filter passes. Pass `i` spaces its 5 x 5 taps `2^i` pixels apart, so the
+  /// footprint doubles with every pass.
+  pub iterations: usize,
+  /// Color difference; halved after every pass as the noise fades.
+  pub color_sigma: f32,
+  /// Difference between normals.
+  pub normal_sigma: f32,
+  /// Albedo difference, which keeps texture and material edges.
+  pub albedo_sigma: f32,
+  /// Depth difference relative to the nearer depth, per pixel of tap spacing.
+  pub depth_sigma: f32
+}
+
+impl Default for DenoiseSettings {
+  fn default() -> Self {
+    Self { iterations: 5, color_sigma: 1.0, normal_sigma: 0.3, albedo_sigma: 0.1, depth_sigma: 0.02 }
+  }
+}
+
+/// Denoises `image` with an edge-avoiding à-trous wavelet filter (Dammertz et
+/// al. 2010). Neighbours are weighted by the spline kernel and by how closely
+/// their color and the normal, albedo and depth `aovs` match, so that edges in
+/// any of them survive while flat regions are smoothed. The result depends
+/// only on the inputs.
+pub fn denoise(image: &Canvas, aovs: &Aovs, settings: &DenoiseSettings) -> Canvas {
+  let (width, height) = (image.width(), image.height());
+  let pixels = |canvas: &Canvas| -> Vec<Color> { canvas.pixels().map(|(_, _, c)| c).collect() };
+  let (normals, albedos) = (pixels(&aovs.normal), pixels(&aovs.albedo));
+  let depths: Vec<f32> = pixels(&aovs.depth).iter().map(|d| d.r()).collect();
+  let mut colors = pixels(image);
+
+  let mut color_sigma = settings.color_sigma;
+  for iteration in 0..settings.iterations {
+    let step = 1usize << iteration;
+    let mut filtered = vec![BLACK; colors.len()];
+    for y in 0..height {
+      for x in 0..width {
+        let p = y * width + x;
+        let (mut sum, mut total) = (BLACK, 0.0);
+        for (ky, hy) in KERNEL.iter().enumerate() {
+          let qy = y as isize + (ky as isize - 2) * step as isize;
+          if qy < 0 || qy >= height as isize {
+            continue;
+          }
+          for (kx, hx) in KERNEL.iter().enumerate() {
+            let qx = x as isize + (kx as isize - 2) * step as isize;
+            if qx < 0 || qx >= width as isize {
+              continue;
+            }
+            let q = qy as usize * width + qx as usize;
+            let depth = (depths[p] - depths[q]).abs() / (depths[p].min(depths[q]).abs().max(f32::EPSILON) * step as f32);
+            let weight = hx * hy
+              * edge_weight(distance(colors[p], colors[q]), color_sigma)
+              * edge_weight(distance(normals[p], normals[q]), settings.normal_sigma)
+              * edge_weight(distance(albedos[p], albedos[q]), settings.albedo_sigma)
+              * edge_weight(depth * depth, settings.depth_sigma);
+            sum = sum + colors[q] * weight;
+            total += weight;
+          }
+        }
+        // the center tap always has a positive weight
+        filtered[p] = sum * (1.0 / total);
+      }
+    }
+    colors = filtered;
+    color_sigma *= 0.5;
+  }
+
+  let mut canvas = Canvas::new(width, height);
+  for (i, color) in colors.iter().enumerate() {
+    canvas.write(i % width, i / width, color);
+  }
+  canvas
+}
+
+/// Squared distance between two colors, or vectors stored as colors.
+fn distance(a: Color, b: Color) -> f32 {
+  let d = a - b;
+  d.r() * d.r() + d.g() * d.g() + d.b() * d.b()
+}
+
+/// Gaussian falloff of a squared difference; a zero sigma only accepts equal values.
+fn edge_weight(squared: f32, sigma: f32) -> f32 {
+  if squared == 0.0 {
+    1.0
+  } else if sigma <= 0.0 {
+    0.0
+  } else {
+    (-squared / (sigma * sigma)).exp()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{denoise, DenoiseSettings};
+  use crate::aov::Aovs;
+  use crate::canvas::Canvas;
+  use crate::color::Color;
+  use crate::rng::Rng;
+
+  const SIZE: usize = 24;
+
+  fn flat(color: Color) -> Canvas {
+    let mut canvas = Canvas::new(SIZE, SIZE);
+    canvas.fill(&color);
+    canvas
+  }
+
+  /// A wall facing the camera, its left half tilted to face left.
+  fn guides() -> Aovs {
+    let mut normal = flat(Color::new(0.0, 0.0, -1.0));
+    for y in 0..SIZE {
+      for x in 0..SIZE / 2 {
+        normal.write(x, y, &Color::new(1.0, 0.0, 0.0));
+      }
+    }
+    let white = flat(Color::new(1.0, 1.0, 1.0));
+    Aovs {
+      depth: flat(Color::new(5.0, 5.0, 5.0)),
+      normal,
+      albedo: white.clone(),
+      object_id: white.clone(),
+      material_id: white.clone(),
+      uv: white.clone(),
+      position: white
+    }
+  }
+
+  /// The left half lit at 0.2, the right at 0.8, with uniform noise of +-0.1.
+  fn noisy() -> Canvas {
+    let mut rng = Rng::new(7);
+    let mut canvas = Canvas::new(SIZE, SIZE);
+    for y in 0..SIZE {
+      for x in 0..SIZE {
+        let base = if x < SIZE / 2 { 0.2 } else { 0.8 };
+        let v = base + (rng.next_f32() - 0.5) * 0.2;
+        canvas.write(x, y, &Color::new(v, v, v));
+      }
+    }
+    canvas
+  }
+
+  fn error(canvas: &Canvas) -> f32 {
+    let mut sum = 0.0;
+    for y in 0..SIZE {
+      for x in 0..SIZE {
+        let expected = if x < SIZE / 2 { 0.2 } else { 0.8 };
+        sum += (canvas.pixel_at(x, y).r() - expected).powi(2);
+      }
+    }
+    (sum / (SIZE * SIZE) as f32).sqrt()
+  }
+
+  #[test]
+  fn implements_denoising() {
+    let (image, aovs) = (noisy(), guides());
+    let settings = DenoiseSettings::default();
+    let denoised = denoise(&image, &aovs, &settings);
+    assert!(error(&denoised) < 0.25 * error(&image));
+    assert_eq!(denoised, denoise(&image, &aovs, &settings));
+
+    // the normals keep the two halves apart
+    for y in 0..SIZE {
+      assert!((denoised.pixel_at(SIZE / 2 - 1, y).r() - 0.2).abs() < 0.1);
+      assert!((denoised.pixel_at(SIZE / 2, y).r() - 0.8).abs() < 0.1);
+    }
+    // without them the edge blurs
+    let unguided = DenoiseSettings { color_sigma: 10.0, normal_sigma: 10.0, ..settings };
+    let blurred = denoise(&image, &aovs, &unguided);
+    assert!((blurred.pixel_at(SIZE / 2 - 1, SIZE / 2).r() - 0.2).abs() > 0.1);
+  }
+
+  #[test]
+  fn implements_denoise_settings() {
+    let (image, aovs) = (noisy(), guides());
+    assert_eq!(image, denoise(&image, &aovs, &DenoiseSettings { iterations: 0, ..DenoiseSettings::default() }));
+    let flat_image = flat(Color::new(0.3, 0.6, 0.9));
+    let denoised = denoise(&flat_image, &aovs, &DenoiseSettings::default());
+    assert!(crate::test_utils::cmp_color(Color::new(0.3, 0.6, 0.9), denoised.pixel_at(3, 17)));
+    // more passes reach further and smooth more
+    let passes = |iterations| denoise(&image, &aovs, &DenoiseSettings { iterations, ..DenoiseSettings::default() });
+    assert!(error(&passes(4)) < error(&passes(1)));
+  }
+}
@@ -0,0 +1,71 @@
+extern crate rusty_math as rm;
+
+use crate::Color;
+use crate::light::{Light, LightSample};
+use crate::rng::Rng;
+use rm::Tuple4;
+
+/// Infinitely distant light (e.g. the sun) shining along `direction` with no falloff.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+  pub direction: Tuple4,
+  pub intensity: Color
+}
+
+impl DirectionalLight {
+  pub fn new(direction: Tuple4, intensity: Color) -> Self {
+    Self {
+      direction: direction.normalized(),
+      intensity
+    }
+  }
+}
+
+impl Light for DirectionalLight {
+  fn samples(&self, _point: Tuple4, _rng: &mut Rng) -> Vec<LightSample> {
+    vec![LightSample { direction: -self.direction, distance: f32::INFINITY, intensity: self.intensity }]
+  }
+
+  fn intensity(&self) -> Color {
+    self.intensity
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Color, DirectionalLight, Light, Rng, Tuple4};
+  use crate::test_utils::default_world;
+
+  #[test]
+  fn implements_constructor() {
+    let light = DirectionalLight::new(Tuple4::vector(0.0, -2.0, 0.0), Color::new(1.0, 1.0, 1.0));
+    assert_eq!(Tuple4::vector(0.0, -1.0, 0.0), light.direction);
+  }
+
+  #[test]
+  fn implements_samples() {
+    let light = DirectionalLight::new(Tuple4::vector(0.0, -1.0, 0.0), Color::new(0.5, 0.5, 0.5));
+    let mut rng = Rng::new(0);
+    for point in [Tuple4::point(0.0, 0.0, 0.0), Tuple4::point(100.0, -50.0, 3.0)] {
+      let samples = light.samples(point, &mut rng);
+      assert_eq!(1, samples.len());
+      assert_eq!(Tuple4::vector(0.0, 1.0, 0.0), samples[0].direction);
+      assert_eq!(f32::INFINITY, samples[0].distance);
+      assert_eq!(Color::new(0.5, 0.5, 0.5), samples[0].intensity);
+    }
+  }
+
+  #[test]
+  fn implements_occlusion() {
+    let w = default_world();
+    let light = DirectionalLight::new(Tuple4::vector(0.0, -1.0, 0.0), Color::new(1.0, 1.0, 1.0));
+    let mut rng = Rng::new(0);
+    let mut occluded = |point: Tuple4| {
+      let sample = light.samples(point, &mut rng)[0];
+      w.is_occluded(point, sample.direction, sample.distance, 0.0)
+    };
+    assert!(occluded(Tuple4::point(0.0, -2.0, 0.0)));
+    assert!(!occluded(Tuple4::point(0.0, 2.0, 0.0)));
+    assert!(!occluded(Tuple4::point(2.0, -2.0, 0.0)));
+  }
+}
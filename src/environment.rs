@@ -0,0 +1,234 @@
+extern crate rusty_math as rm;
+
+use crate::canvas::Canvas;
+use crate::color::{Color, BLACK};
+use crate::errors::ImageError;
+use crate::light::RadianceSample;
+use crate::rng::Rng;
+use crate::sampler::{uniform_sample_sphere, Distribution2D};
+use crate::sky::PhysicalSky;
+use rm::Tuple4;
+
+use std::f32::consts::PI;
+
+/// Radiance arriving from infinitely far away along rays that miss every object.
+#[derive(Debug)]
+pub enum Background {
+  Constant(Color),
+  /// Blend from `bottom`, straight down, to `top`, straight up.
+  Gradient { bottom: Color, top: Color },
+  Map(EnvironmentMap),
+  /// Analytic daylight; pair it with `PhysicalSky::sun_light` for the sun.
+  Sky(PhysicalSky)
+}
+
+impl Background {
+  /// Radiance arriving from `direction`, i.e. seen along a ray travelling that way.
+  pub fn radiance(&self, direction: Tuple4) -> Color {
+    match self {
+      Background::Constant(color) => *color,
+      Background::Gradient { bottom, top } => {
+        let t = 0.5 * (direction.normalized().y() + 1.0);
+        *bottom * (1.0 - t) + *top * t
+      },
+      Background::Map(map) => map.radiance(direction),
+      Background::Sky(sky) => sky.radiance(direction)
+    }
+  }
+
+  /// Direction towards the background for next-event estimation. Maps are
+  /// importance sampled; colors are sampled uniformly over the sphere.
+  pub fn sample(&self, rng: &mut Rng) -> Option<RadianceSample> {
+    let (u, v) = (rng.next_f32(), rng.next_f32());
+    let (direction, pdf) = match self {
+      Background::Constant(color) if *color == BLACK => return None,
+      Background::Map(map) => map.sample(u, v)?,
+      _ => (uniform_sample_sphere(u, v), 1.0 / (4.0 * PI))
+    };
+    Some(RadianceSample { direction, distance: f32::INFINITY, radiance: self.radiance(direction), pdf: Some(pdf) })
+  }
+
+  /// Solid angle density with which `sample` returns `direction`.
+  pub fn pdf(&self, direction: Tuple4) -> f32 {
+    match self {
+      Background::Constant(color) if *color == BLACK => 0.0,
+      Background::Map(map) => map.pdf(direction),
+      _ => 1.0 / (4.0 * PI)
+    }
+  }
+}
+
+impl Default for Background {
+  fn default() -> Self {
+    Background::Constant(BLACK)
+  }
+}
+
+/// Equirectangular (latitude-longitude) radiance map. The top row looks
+/// straight up (+y) and `u` runs around the vertical axis starting at -x.
+#[derive(Debug)]
+pub struct EnvironmentMap {
+  image: Canvas,
+  distribution: Distribution2D
+}
+
+impl EnvironmentMap {
+  /// Map showing `image`, which must have at least one pixel.
+  pub fn new(image: Canvas) -> Result<Self, ImageError> {
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+      return Err(ImageError::Format(format!("environment map is empty ({}x{})", width, height)));
+    }
+    // pixels near the poles cover less solid angle
+    let mut func = Vec::with_capacity(width * height);
+    for y in 0..height {
+      let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+      for x in 0..width {
+        func.push(image.pixel_at(x, y).luminance().max(0.0) * sin_theta);
+      }
+    }
+    let distribution = Distribution2D::new(&func, width, height);
+    Ok(Self { image, distribution })
+  }
+
+  /// Loads a Radiance .hdr file.
+  pub fn import(file: &str) -> Result<Self, ImageError> {
+    Self::new(Canvas::import_hdr(file)?)
+  }
+
+  pub fn radiance(&self, direction: Tuple4) -> Color {
+    let (u, v) = direction_to_uv(direction);
+    let x = ((u * self.image.width() as f32) as usize).min(self.image.width() - 1);
+    let y = ((v * self.image.height() as f32) as usize).min(self.image.height() - 1);
+    self.image.pixel_at(x, y)
+  }
+
+  /// Direction distributed according to the map's luminance, and its solid
+  /// angle density.
+  pub fn sample(&self, u: f32, v: f32) -> Option<(Tuple4, f32)> {
+    let ((x, y), pdf) = self.distribution.sample(u, v);
+    let sin_theta = (PI * y).sin();
+    if pdf <= 0.0 || sin_theta <= 0.0 {
+      return None;
+    }
+    Some((uv_to_direction(x, y), pdf / (2.0 * PI * PI * sin_theta)))
+  }
+
+  pub fn pdf(&self, direction: Tuple4) -> f32 {
+    let (u, v) = direction_to_uv(direction);
+    let sin_theta = (PI * v).sin();
+    if sin_theta <= 0.0 {
+      return 0.0;
+    }
+    self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+  }
+}
+
+/// Equirectangular coordinates in [0, 1)^2 of `direction`.
+pub fn direction_to_uv(direction: Tuple4) -> (f32, f32) {
+  let d = direction.normalized();
+  let phi = d.z().atan2(d.x());
+  let theta = d.y().clamp(-1.0, 1.0).acos();
+  (((phi + PI) / (2.0 * PI)).fract(), theta / PI)
+}
+
+pub fn uv_to_direction(u: f32, v: f32) -> Tuple4 {
+  let phi = 2.0 * PI * u - PI;
+  let theta = PI * v;
+  Tuple4::vector(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    direction_to_uv, uv_to_direction, Background, Canvas, Color, EnvironmentMap, ImageError, PhysicalSky, Rng, Tuple4, BLACK
+  };
+  use std::f32::consts::PI;
+
+  #[test]
+  fn implements_direction_to_uv() {
+    assert_eq!((0.5, 0.0), direction_to_uv(Tuple4::vector(0.0, 1.0, 0.0)));
+    assert_eq!((0.5, 0.5), direction_to_uv(Tuple4::vector(1.0, 0.0, 0.0)));
+    let (u, v) = direction_to_uv(Tuple4::vector(0.0, 0.0, 2.0));
+    assert!((u - 0.75).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+    for (u, v) in [(0.1, 0.2), (0.6, 0.5), (0.9, 0.8)] {
+      let (u2, v2) = direction_to_uv(uv_to_direction(u, v));
+      assert!((u - u2).abs() < 1e-5 && (v - v2).abs() < 1e-5);
+    }
+  }
+
+  #[test]
+  fn implements_constant_and_gradient() {
+    let up = Tuple4::vector(0.0, 1.0, 0.0);
+    let sky = Background::Gradient { bottom: Color::new(1.0, 1.0, 1.0), top: Color::new(0.0, 0.0, 1.0) };
+    assert_eq!(Color::new(0.0, 0.0, 1.0), sky.radiance(up));
+    assert_eq!(Color::new(1.0, 1.0, 1.0), sky.radiance(-up));
+    assert_eq!(Color::new(0.5, 0.5, 1.0), sky.radiance(Tuple4::vector(1.0, 0.0, 0.0)));
+    assert_eq!(1.0 / (4.0 * PI), sky.pdf(up));
+
+    let black = Background::default();
+    assert_eq!(BLACK, black.radiance(up));
+    assert_eq!(None, black.sample(&mut Rng::new(0)));
+    assert_eq!(0.0, black.pdf(up));
+  }
+
+  #[test]
+  fn implements_physical_sky() {
+    let sky = PhysicalSky::new(0.5, 0.0, 3.0);
+    let up = Tuple4::vector(0.0, 1.0, 0.0);
+    assert_eq!(sky.radiance(up), Background::Sky(sky).radiance(up));
+    assert_eq!(1.0 / (4.0 * PI), Background::Sky(sky).pdf(up));
+  }
+
+  #[test]
+  fn implements_map_lookup() {
+    let mut image = Canvas::new(4, 2);
+    image.write(2, 0, &Color::new(1.0, 0.0, 0.0));
+    image.write(0, 1, &Color::new(0.0, 1.0, 0.0));
+    let map = EnvironmentMap::new(image).unwrap();
+    assert_eq!(Color::new(1.0, 0.0, 0.0), map.radiance(Tuple4::vector(1.0, 0.5, 0.1)));
+    assert_eq!(Color::new(0.0, 1.0, 0.0), map.radiance(Tuple4::vector(-1.0, -0.5, -0.1)));
+  }
+
+  #[test]
+  fn implements_map_importance_sampling() {
+    // a dim sky with one bright patch
+    let mut image = Canvas::new(16, 8);
+    for y in 0..8 {
+      for x in 0..16 {
+        image.write(x, y, &Color::new(0.1, 0.1, 0.1));
+      }
+    }
+    image.write(5, 2, &Color::new(50.0, 50.0, 50.0));
+    let background = Background::Map(EnvironmentMap::new(image).unwrap());
+
+    // estimate the total radiant flux over the sphere both ways
+    let mut rng = Rng::new(9);
+    let n = 4000;
+    let (mut sampled, mut uniform) = (0.0, 0.0);
+    for _ in 0..n {
+      let sample = background.sample(&mut rng).unwrap();
+      let pdf = sample.pdf.unwrap();
+      assert!((background.pdf(sample.direction) - pdf).abs() < 0.001 * pdf);
+      sampled += sample.radiance.g() / pdf;
+      let d = super::uniform_sample_sphere(rng.next_f32(), rng.next_f32());
+      uniform += background.radiance(d).g() * 4.0 * PI;
+    }
+    let (sampled, uniform) = (sampled / n as f32, uniform / n as f32);
+    // the patch covers 2 pi^2 sin(theta) / 128 steradians
+    let expected = 0.1 * 4.0 * PI + 49.9 * 2.0 * PI * PI * (PI * 2.5 / 8.0).sin() / 128.0;
+    assert!((sampled - expected).abs() < 0.02 * expected, "{} {}", sampled, expected);
+    assert!((uniform - expected).abs() < 0.2 * expected, "{} {}", uniform, expected);
+  }
+
+  #[test]
+  fn rejects_empty_maps() {
+    // a well-formed file without pixels
+    let empty = Canvas::from_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 4\n").unwrap();
+    match EnvironmentMap::new(empty) {
+      Err(ImageError::Format(message)) => assert_eq!("environment map is empty (4x0)", message),
+      other => panic!("expected a format error, got {:?}", other)
+    }
+    assert!(EnvironmentMap::new(Canvas::new(0, 3)).is_err());
+  }
+}
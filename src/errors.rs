@@ -0,0 +1,74 @@
+use std::{error::Error, fmt, io};
+
+#[derive(Debug)]
+pub enum ImageError {
+  Io(io::Error),
+  Format(String)
+}
+
+impl Error for ImageError {}
+
+impl fmt::Display for ImageError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ImageError::Io(e) => write!(f, "Unable to access image: {}", e),
+      ImageError::Format(msg) => write!(f, "Malformed image: {}", msg)
+    }
+  }
+}
+
+impl From<io::Error> for ImageError {
+  fn from(e: io::Error) -> Self {
+    ImageError::Io(e)
+  }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+  Io(io::Error),
+  /// Problem in the scene description, at a 1-based line and column.
+  Parse { line: usize, column: usize, message: String }
+}
+
+impl Error for SceneError {}
+
+impl fmt::Display for SceneError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SceneError::Io(e) => write!(f, "Unable to read scene: {}", e),
+      SceneError::Parse { line, column, message } => write!(f, "Invalid scene at line {}, column {}: {}", line, column, message)
+    }
+  }
+}
+
+impl From<io::Error> for SceneError {
+  fn from(e: io::Error) -> Self {
+    SceneError::Io(e)
+  }
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+  Io(io::Error),
+  Format(String),
+  /// The checkpoint was saved for another scene, resolution or render settings.
+  Mismatch
+}
+
+impl Error for CheckpointError {}
+
+impl fmt::Display for CheckpointError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CheckpointError::Io(e) => write!(f, "Unable to access checkpoint: {}", e),
+      CheckpointError::Format(msg) => write!(f, "Malformed checkpoint: {}", msg),
+      CheckpointError::Mismatch => write!(f, "Checkpoint was saved for a different scene or render settings")
+    }
+  }
+}
+
+impl From<io::Error> for CheckpointError {
+  fn from(e: io::Error) -> Self {
+    CheckpointError::Io(e)
+  }
+}
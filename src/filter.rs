@@ -0,0 +1,103 @@
+/// Separable reconstruction filters used to weight pixel samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+  Box { radius: f32 },
+  Tent { radius: f32 },
+  Gaussian { radius: f32, alpha: f32 },
+  /// Mitchell-Netravali cubic; `b = c = 1/3` is the recommended setting.
+  Mitchell { radius: f32, b: f32, c: f32 }
+}
+
+impl Filter {
+  pub fn gaussian() -> Self {
+    Filter::Gaussian { radius: 1.5, alpha: 2.0 }
+  }
+
+  pub fn mitchell() -> Self {
+    Filter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 }
+  }
+
+  pub fn radius(&self) -> f32 {
+    match *self {
+      Filter::Box { radius } |
+      Filter::Tent { radius } |
+      Filter::Gaussian { radius, .. } |
+      Filter::Mitchell { radius, .. } => radius
+    }
+  }
+
+  /// Weight of a sample at offset (`dx`, `dy`) from the pixel center, in pixels.
+  pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+    self.evaluate_1d(dx) * self.evaluate_1d(dy)
+  }
+
+  fn evaluate_1d(&self, d: f32) -> f32 {
+    let d = d.abs();
+    match *self {
+      Filter::Box { radius } => if d <= radius { 1.0 } else { 0.0 },
+      Filter::Tent { radius } => (radius - d).max(0.0),
+      Filter::Gaussian { radius, alpha } => {
+        ((-alpha * d * d).exp() - (-alpha * radius * radius).exp()).max(0.0)
+      },
+      Filter::Mitchell { radius, b, c } => {
+        // the cubic is defined over [-2, 2]
+        let x = 2.0 * d / radius;
+        if x >= 2.0 {
+          0.0
+        } else if x >= 1.0 {
+          ((-b - 6.0 * c) * x.powi(3) + (6.0 * b + 30.0 * c) * x.powi(2) +
+            (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+        } else {
+          ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2) +
+            (6.0 - 2.0 * b)) / 6.0
+        }
+      }
+    }
+  }
+}
+
+impl Default for Filter {
+  fn default() -> Self {
+    Filter::Box { radius: 0.5 }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Filter;
+
+  #[test]
+  fn implements_box() {
+    let f = Filter::default();
+    assert_eq!(0.5, f.radius());
+    assert_eq!(1.0, f.evaluate(0.0, 0.0));
+    assert_eq!(1.0, f.evaluate(0.4, -0.4));
+    assert_eq!(0.0, f.evaluate(0.6, 0.0));
+  }
+
+  #[test]
+  fn implements_tent() {
+    let f = Filter::Tent { radius: 1.0 };
+    assert_eq!(1.0, f.evaluate(0.0, 0.0));
+    assert_eq!(0.25, f.evaluate(0.5, 0.5));
+    assert_eq!(0.0, f.evaluate(1.0, 0.0));
+  }
+
+  #[test]
+  fn implements_gaussian() {
+    let f = Filter::gaussian();
+    assert!(f.evaluate(0.0, 0.0) > f.evaluate(0.5, 0.0));
+    assert!(f.evaluate(0.5, 0.0) > f.evaluate(1.0, 0.0));
+    assert_eq!(0.0, f.evaluate(1.5, 0.0));
+    assert_eq!(f.evaluate(0.3, 0.7), f.evaluate(-0.3, -0.7));
+  }
+
+  #[test]
+  fn implements_mitchell() {
+    let f = Filter::mitchell();
+    assert!((f.evaluate(0.0, 0.0) - (8.0f32 / 9.0).powi(2)).abs() < 1e-6);
+    assert_eq!(0.0, f.evaluate(2.0, 0.0));
+    // negative lobe between 1 and 2 pixels
+    assert!(f.evaluate(1.5, 0.0) < 0.0);
+  }
+}
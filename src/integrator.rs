@@ -0,0 +1,354 @@
+use crate::color::{Color, BLACK};
+use crate::light::MisHeuristic;
+use crate::ray::Ray;
+use crate::rng::Rng;
+use crate::stats::{self, Counter};
+use crate::world::{SurfaceHit, World};
+use rm::Tuple4;
+
+/// Strategy used to estimate the radiance arriving along a camera ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+  /// Direct lighting with shadows and a flat ambient term.
+  Whitted,
+  /// Unidirectional path tracing with next-event estimation towards the lights.
+  /// Paths are cut after `max_depth` bounces and are subject to Russian
+  /// roulette from `russian_roulette_depth` onwards. Emissive objects are
+  /// sampled like lights. With `mis` set, lights and emitters hit by BSDF
+  /// sampled rays also contribute, weighted against light sampling; otherwise
+  /// direct lighting comes from light sampling alone.
+  PathTracer { max_depth: usize, russian_roulette_depth: usize, mis: Option<MisHeuristic> }
+}
+
+impl Integrator {
+  pub fn path_tracer() -> Self {
+    Integrator::PathTracer { max_depth: 8, russian_roulette_depth: 3, mis: Some(MisHeuristic::Power) }
+  }
+
+  pub fn radiance(&self, world: &World, r: Ray, rng: &mut Rng) -> Color {
+    match *self {
+      Integrator::Whitted => world.color_at(r, rng),
+      Integrator::PathTracer { max_depth, russian_roulette_depth, mis } => {
+        trace_path(world, r, max_depth, russian_roulette_depth, mis, rng)
+      }
+    }
+  }
+}
+
+fn trace_path(
+  world: &World,
+  r: Ray,
+  max_depth: usize,
+  russian_roulette_depth: usize,
+  mis: Option<MisHeuristic>,
+  rng: &mut Rng
+) -> Color {
+  let mut radiance = BLACK;
+  let mut throughput = Color::new(1.0, 1.0, 1.0);
+  let mut ray = r;
+  let mut depth = 0;
+  // density of the BSDF sample that produced `ray`, none for camera rays
+  let mut bsdf_pdf: Option<f32> = None;
+  let emitters = world.emitters();
+
+  loop {
+    let hit = world.hit(ray);
+    let max_t = hit.as_ref().map_or(f32::INFINITY, |surface| surface.t);
+    radiance = radiance + throughput * emitted_by_lights(world, ray, max_t, mis, bsdf_pdf);
+    let surface = match hit {
+      Some(surface) => surface,
+      None => {
+        let weight = match (bsdf_pdf, mis) {
+          (None, _) => 1.0,
+          (Some(_), None) => 0.0,
+          (Some(pdf), Some(heuristic)) => heuristic.weight(pdf, world.background.pdf(ray.direction))
+        };
+        radiance = radiance + throughput * world.background.radiance(ray.direction) * weight;
+        break;
+      }
+    };
+    let material = &surface.material;
+    if material.emission != BLACK {
+      let weight = match (bsdf_pdf, mis) {
+        (None, _) => 1.0,
+        (Some(_), None) => 0.0,
+        (Some(pdf), Some(heuristic)) => heuristic.weight(pdf, world.emitter_pdf(ray.origin, &surface))
+      };
+      radiance = radiance + throughput * material.emission * weight;
+    }
+    radiance = radiance + throughput * sample_lights(world, &surface, &emitters, mis, rng);
+
+    if depth >= max_depth {
+      break;
+    }
+
+    let wi = match material.model.sample(material, surface.eye, surface.normal, rng) {
+      Some(wi) => wi,
+      None => break
+    };
+    let pdf = material.model.pdf(material, surface.eye, wi, surface.normal);
+    if pdf <= 0.0 {
+      break;
+    }
+    let f = material.model.brdf(material, wi, surface.eye, surface.normal);
+    throughput = throughput * f * (Tuple4::dot(wi, surface.normal) / pdf);
+
+    if depth >= russian_roulette_depth {
+      let survival = throughput.r().max(throughput.g()).max(throughput.b()).clamp(0.05, 1.0);
+      if rng.next_f32() >= survival {
+        break;
+      }
+      throughput = throughput * (1.0 / survival);
+    }
+    if throughput == BLACK {
+      break;
+    }
+
+    ray = Ray::with_time(surface.over_point, wi, ray.time);
+    stats::record(Counter::ReflectionRay, 1);
+    bsdf_pdf = Some(pdf);
+    depth += 1;
+  }
+  radiance
+}
+
+/// Next-event estimation: one sample towards every light, emitter and the
+/// background.
+fn sample_lights(
+  world: &World,
+  surface: &SurfaceHit,
+  emitters: &[usize],
+  mis: Option<MisHeuristic>,
+  rng: &mut Rng
+) -> Color {
+  let material = &surface.material;
+  let mut radiance = BLACK;
+  let mut samples = Vec::with_capacity(world.lights.len() + emitters.len() + 1);
+  for light in world.lights.iter() {
+    samples.push(light.sample_radiance(surface.over_point, rng));
+  }
+  for &object in emitters {
+    samples.push(world.sample_emitter(object, surface.over_point, surface.time, rng));
+  }
+  samples.push(world.background.sample(rng));
+  for sample in samples.into_iter().flatten() {
+    let cos_i = Tuple4::dot(sample.direction, surface.normal);
+    if cos_i <= 0.0 || world.is_occluded(surface.over_point, sample.direction, sample.distance, surface.time) {
+      continue;
+    }
+    let f = material.model.brdf(material, sample.direction, surface.eye, surface.normal);
+    let weight = match (sample.pdf, mis) {
+      (None, _) => 1.0,
+      (Some(pdf), None) => 1.0 / pdf,
+      (Some(pdf), Some(heuristic)) => {
+        let bsdf_pdf = material.model.pdf(material, surface.eye, sample.direction, surface.normal);
+        heuristic.weight(pdf, bsdf_pdf) / pdf
+      }
+    };
+    radiance = radiance + f * sample.radiance * (cos_i * weight);
+  }
+  radiance
+}
+
+/// Radiance from lights hit by `ray` before `max_t`. Camera rays see it in
+/// full, as they do emissive objects. Rays BSDF sampled with density
+/// `bsdf_pdf` are weighted against light sampling by `mis`, or dropped without
+/// it, as light sampling already accounts for them.
+fn emitted_by_lights(world: &World, ray: Ray, max_t: f32, mis: Option<MisHeuristic>, bsdf_pdf: Option<f32>) -> Color {
+  let mut radiance = BLACK;
+  for light in world.lights.iter() {
+    if let Some((t, emitted)) = light.emitted(ray) {
+      if t >= max_t {
+        continue;
+      }
+      let weight = match (bsdf_pdf, mis) {
+        (None, _) => 1.0,
+        (Some(_), None) => 0.0,
+        (Some(pdf), Some(heuristic)) => heuristic.weight(pdf, light.pdf(ray.origin, ray.direction))
+      };
+      radiance = radiance + emitted * weight;
+    }
+  }
+  radiance
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Color, Integrator, MisHeuristic, Ray, Rng, World};
+  use crate::area_light::AreaLight;
+  use crate::canvas::Canvas;
+  use crate::environment::{Background, EnvironmentMap};
+  use crate::material::Material;
+  use crate::sphere::Sphere;
+  use crate::test_utils::{cmp_color, default_world};
+  use rm::{Matrix4, Tuple4};
+
+  #[test]
+  fn implements_whitted() {
+    let w = default_world();
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    assert_eq!(w.color_at(r, &mut Rng::new(0)), Integrator::Whitted.radiance(&w, r, &mut Rng::new(0)));
+  }
+
+  #[test]
+  fn test_path_tracer_direct_lighting_matches_whitted() {
+    let mut w = default_world();
+    w.ambient = Color::new(0.0, 0.0, 0.0);
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    let direct_only = Integrator::PathTracer { max_depth: 0, russian_roulette_depth: 0, mis: None };
+    assert!(cmp_color(w.color_at(r, &mut Rng::new(0)), direct_only.radiance(&w, r, &mut Rng::new(0))));
+  }
+
+  #[test]
+  fn test_path_tracer_emission() {
+    let mut s = Sphere::unit();
+    s.set_material(Material::emissive(Color::new(2.0, 1.0, 0.5)));
+    let w = World::new(vec![s], vec![]);
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    assert_eq!(Color::new(2.0, 1.0, 0.5), Integrator::path_tracer().radiance(&w, r, &mut Rng::new(0)));
+    let miss = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 1.0, 0.0));
+    assert_eq!(Color::new(0.0, 0.0, 0.0), Integrator::path_tracer().radiance(&w, miss, &mut Rng::new(0)));
+  }
+
+  #[test]
+  fn test_path_tracer_furnace() {
+    // inside a closed emitter of radiance 1 with albedo 0.5 the radiance is 1 / (1 - 0.5)
+    let mut s = Sphere::unit();
+    s.set_transform(Matrix4::scaling(10.0, 10.0, 10.0));
+    s.set_material(Material {
+      color: Color::new(0.5, 0.5, 0.5),
+      diffuse: 1.0,
+      specular: 0.0,
+      emission: Color::new(1.0, 1.0, 1.0),
+      ..Material::default()
+    });
+    let w = World::new(vec![s], vec![]);
+    for mis in [None, Some(MisHeuristic::Power)] {
+      let integrator = Integrator::PathTracer { max_depth: 32, russian_roulette_depth: 2, mis };
+      let mut sum = 0.0;
+      let paths = 4000;
+      for i in 0..paths {
+        let mut rng = Rng::for_sample(11, 0, 0, i);
+        let r = Ray::new(Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 0.0, 1.0));
+        sum += integrator.radiance(&w, r, &mut rng).g();
+      }
+      assert!((sum / paths as f32 - 2.0).abs() < 0.1, "{:?} {}", mis, sum / paths as f32);
+    }
+  }
+
+  #[test]
+  fn test_path_tracer_is_deterministic() {
+    let w = default_world();
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    let integrator = Integrator::path_tracer();
+    let a = integrator.radiance(&w, r, &mut Rng::for_sample(1, 2, 3, 4));
+    let b = integrator.radiance(&w, r, &mut Rng::for_sample(1, 2, 3, 4));
+    assert_eq!(a, b);
+  }
+
+  fn glossy_world() -> World {
+    let mut floor = Sphere::unit();
+    floor.set_transform(Matrix4::translation(0.0, -100.0, 0.0) * Matrix4::scaling(100.0, 100.0, 100.0));
+    floor.set_material(Material::ggx(Color::new(0.8, 0.8, 0.8), 0.15, 1.0));
+    let light = AreaLight::new(
+      Tuple4::point(-1.0, 2.0, -1.0),
+      Tuple4::vector(2.0, 0.0, 0.0), 1,
+      Tuple4::vector(0.0, 0.0, 2.0), 1,
+      true, Color::new(5.0, 5.0, 5.0)
+    );
+    let mut w = World::new(vec![floor], vec![Box::new(light)]);
+    w.ambient = Color::new(0.0, 0.0, 0.0);
+    w
+  }
+
+  fn estimate(w: &World, integrator: Integrator, paths: usize) -> (f32, f32) {
+    let r = Ray::new(Tuple4::point(0.0, 2.0, -2.0), Tuple4::vector(0.0, -2.0, 1.0).normalized());
+    let values: Vec<f32> = (0..paths)
+      .map(|i| integrator.radiance(w, r, &mut Rng::for_sample(5, 0, 0, i)).g())
+      .collect();
+    let mean = values.iter().sum::<f32>() / paths as f32;
+    let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / paths as f32;
+    (mean, variance)
+  }
+
+  #[test]
+  fn test_path_tracer_mis() {
+    let w = glossy_world();
+    let paths = 4000;
+    let tracer = |mis| Integrator::PathTracer { max_depth: 1, russian_roulette_depth: 8, mis };
+    let (lights_only, lights_only_variance) = estimate(&w, tracer(None), paths);
+    let (power, power_variance) = estimate(&w, tracer(Some(MisHeuristic::Power)), paths);
+    let (balance, balance_variance) = estimate(&w, tracer(Some(MisHeuristic::Balance)), paths);
+    // every strategy converges to the same image, MIS with far less noise
+    assert!((power - balance).abs() < 0.05 * power, "{} {}", power, balance);
+    assert!((power - lights_only).abs() < 0.15 * power, "{} {}", power, lights_only);
+    assert!(power_variance < 0.5 * lights_only_variance, "{} {}", power_variance, lights_only_variance);
+    assert!(balance_variance < 0.5 * lights_only_variance, "{} {}", balance_variance, lights_only_variance);
+  }
+
+  #[test]
+  fn test_path_tracer_sees_area_lights() {
+    let w = glossy_world();
+    let up = Ray::new(Tuple4::point(0.0, 1.0, 0.0), Tuple4::vector(0.0, 1.0, 0.0));
+    assert_eq!(Color::new(5.0, 5.0, 5.0), Integrator::path_tracer().radiance(&w, up, &mut Rng::new(0)));
+    // camera rays see lights without MIS too, like emissive objects
+    let without_mis = Integrator::PathTracer { max_depth: 8, russian_roulette_depth: 3, mis: None };
+    assert_eq!(Color::new(5.0, 5.0, 5.0), without_mis.radiance(&w, up, &mut Rng::new(0)));
+  }
+
+  #[test]
+  fn test_path_tracer_emissive_objects() {
+    // a diffuse floor lit only by a glowing sphere
+    let mut floor = Sphere::unit();
+    floor.set_transform(Matrix4::translation(0.0, -100.0, 0.0) * Matrix4::scaling(100.0, 100.0, 100.0));
+    floor.set_material(Material { color: Color::new(0.5, 0.5, 0.5), diffuse: 1.0, specular: 0.0, ..Material::default() });
+    let mut lamp = Sphere::unit();
+    lamp.set_transform(Matrix4::translation(0.0, 2.0, 0.0) * Matrix4::scaling(0.5, 0.5, 0.5));
+    lamp.set_material(Material::emissive(Color::new(8.0, 8.0, 8.0)));
+    let w = World::new(vec![floor, lamp], vec![]);
+
+    // lamp seen directly
+    let r = Ray::new(Tuple4::point(0.0, 2.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    assert_eq!(Color::new(8.0, 8.0, 8.0), Integrator::path_tracer().radiance(&w, r, &mut Rng::new(0)));
+
+    // a sphere of radiance L, radius r at distance d irradiates the floor with
+    // pi * L * r^2 / d^2, so the diffuse floor reflects 0.5 * L * r^2 / d^2
+    let expected = 0.5 * 8.0 * 0.25 / 4.0;
+    let r = Ray::new(Tuple4::point(0.0, 1.0, -1.0), Tuple4::vector(0.0, -1.0, 1.0).normalized());
+    for mis in [None, Some(MisHeuristic::Power)] {
+      let integrator = Integrator::PathTracer { max_depth: 1, russian_roulette_depth: 8, mis };
+      let paths = 4000;
+      let sum: f32 = (0..paths).map(|i| integrator.radiance(&w, r, &mut Rng::for_sample(3, 0, 0, i)).g()).sum();
+      let mean = sum / paths as f32;
+      assert!((mean - expected).abs() < 0.05 * expected, "{:?} {} {}", mis, mean, expected);
+    }
+  }
+
+  #[test]
+  fn test_path_tracer_environment() {
+    // a convex diffuse object under a uniform sky reflects albedo * sky
+    let mut s = Sphere::unit();
+    s.set_material(Material { color: Color::new(0.5, 0.5, 0.5), diffuse: 1.0, specular: 0.0, ..Material::default() });
+    let mut w = World::new(vec![s], vec![]);
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    let mut image = Canvas::new(8, 4);
+    for y in 0..4 {
+      for x in 0..8 {
+        image.write(x, y, &Color::new(2.0, 2.0, 2.0));
+      }
+    }
+    let backgrounds = [Background::Constant(Color::new(2.0, 2.0, 2.0)), Background::Map(EnvironmentMap::new(image).unwrap())];
+    for background in backgrounds {
+      w.background = background;
+      let miss = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 1.0, 0.0));
+      assert_eq!(Color::new(2.0, 2.0, 2.0), Integrator::path_tracer().radiance(&w, miss, &mut Rng::new(0)));
+      for mis in [None, Some(MisHeuristic::Power)] {
+        let integrator = Integrator::PathTracer { max_depth: 4, russian_roulette_depth: 8, mis };
+        let paths = 2000;
+        let sum: f32 = (0..paths).map(|i| integrator.radiance(&w, r, &mut Rng::for_sample(7, 0, 0, i)).g()).sum();
+        let mean = sum / paths as f32;
+        assert!((mean - 1.0).abs() < 0.05, "{:?} {:?} {}", w.background, mis, mean);
+      }
+    }
+  }
+}
@@ -0,0 +1,44 @@
+// The original chapter modules predate these lints and are kept as written
+#![allow(mismatched_lifetime_syntaxes)]
+#![allow(clippy::len_zero, clippy::needless_borrow, clippy::redundant_field_names, clippy::should_implement_trait)]
+#![cfg_attr(test, allow(clippy::approx_constant, clippy::needless_bool, clippy::useless_vec))]
+
+extern crate rusty_math as rm;
+
+pub mod aov;
+pub mod area_light;
+pub mod camera;
+pub mod canvas;
+pub mod checkpoint;
+pub mod color;
+pub mod compare;
+pub mod denoise;
+pub mod directional_light;
+pub mod environment;
+pub mod errors;
+pub mod filter;
+pub mod integrator;
+pub mod intersection;
+pub mod light;
+pub mod lighting;
+pub mod lut;
+pub mod material;
+pub mod motion;
+pub mod point_light;
+pub mod post;
+pub mod ray;
+pub mod render;
+pub mod rng;
+pub mod sampler;
+pub mod scene;
+pub mod shading;
+pub mod sky;
+pub mod spot_light;
+pub mod stats;
+pub mod sphere;
+#[cfg(test)]
+mod test_utils;
+pub mod world;
+pub mod yaml;
+
+pub use color::Color;
@@ -0,0 +1,154 @@
+extern crate rusty_math as rm;
+
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::rng::Rng;
+use rm::Tuple4;
+
+use std::f32::consts::PI;
+use std::fmt;
+
+/// Light arriving at a shaded point from one position on a light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightSample {
+  /// Unit vector from the shaded point towards the light.
+  pub direction: Tuple4,
+  /// Distance to the light along `direction`; infinite for directional lights.
+  pub distance: f32,
+  /// Intensity reaching the shaded point, including any falloff.
+  pub intensity: Color
+}
+
+/// Single direction towards a light, chosen for Monte Carlo integration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RadianceSample {
+  pub direction: Tuple4,
+  pub distance: f32,
+  /// Radiance arriving along `direction`. For delta lights this already folds
+  /// in the sampling density, so it is used as-is.
+  pub radiance: Color,
+  /// Solid angle density of `direction`, or `None` for delta lights.
+  pub pdf: Option<f32>
+}
+
+/// Falloff of a light's intensity with distance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Attenuation {
+  /// Constant intensity at any distance.
+  None,
+  /// Physically based `1 / d^2` falloff.
+  InverseSquare,
+  /// `1 / (constant + linear * d + quadratic * d^2)`.
+  Polynomial { constant: f32, linear: f32, quadratic: f32 }
+}
+
+impl Attenuation {
+  pub fn factor(&self, distance: f32) -> f32 {
+    match *self {
+      Attenuation::None => 1.0,
+      Attenuation::InverseSquare => 1.0 / (distance * distance).max(f32::MIN_POSITIVE),
+      Attenuation::Polynomial { constant, linear, quadratic } => {
+        let denominator = constant + linear * distance + quadratic * distance * distance;
+        1.0 / denominator.max(f32::MIN_POSITIVE)
+      }
+    }
+  }
+}
+
+pub trait Light: fmt::Debug + Send + Sync {
+  /// Samples over the light as seen from `point`. Lights without extent
+  /// return a single sample.
+  fn samples(&self, point: Tuple4, rng: &mut Rng) -> Vec<LightSample>;
+
+  /// Nominal intensity of the light, independent of any shaded point.
+  fn intensity(&self) -> Color;
+
+  /// One direction towards the light for Monte Carlo integrators. Lights
+  /// without extent pick one of their `samples` and act as delta lights, so
+  /// that path traced direct lighting matches `lighting::direct`.
+  fn sample_radiance(&self, point: Tuple4, rng: &mut Rng) -> Option<RadianceSample> {
+    let samples = self.samples(point, rng);
+    if samples.is_empty() {
+      return None;
+    }
+    let index = ((rng.next_f32() * samples.len() as f32) as usize).min(samples.len() - 1);
+    let sample = samples[index];
+    Some(RadianceSample {
+      direction: sample.direction,
+      distance: sample.distance,
+      radiance: sample.intensity * PI,
+      pdf: None
+    })
+  }
+
+  /// Solid angle density with which `sample_radiance` picks `direction` from
+  /// `point`. Zero for lights that rays cannot hit.
+  fn pdf(&self, _point: Tuple4, _direction: Tuple4) -> f32 {
+    0.0
+  }
+
+  /// Distance along `r` and radiance towards its origin, if `r` hits the light.
+  fn emitted(&self, _r: Ray) -> Option<(f32, Color)> {
+    None
+  }
+}
+
+/// Multiple importance sampling weighting of two sampling strategies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MisHeuristic {
+  Balance,
+  Power
+}
+
+impl MisHeuristic {
+  /// Weight of a sample drawn with density `pdf` when the other strategy
+  /// would have drawn it with density `other_pdf`.
+  pub fn weight(&self, pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = match self {
+      MisHeuristic::Balance => (pdf, other_pdf),
+      MisHeuristic::Power => (pdf * pdf, other_pdf * other_pdf)
+    };
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Attenuation, Light, MisHeuristic};
+  use crate::color::Color;
+  use crate::point_light::PointLight;
+  use crate::rng::Rng;
+  use rm::Tuple4;
+  use std::f32::consts::PI;
+
+  #[test]
+  fn implements_attenuation() {
+    assert_eq!(1.0, Attenuation::None.factor(10.0));
+    assert_eq!(0.25, Attenuation::InverseSquare.factor(2.0));
+    assert_eq!(0.01, Attenuation::InverseSquare.factor(10.0));
+    let polynomial = Attenuation::Polynomial { constant: 1.0, linear: 0.5, quadratic: 0.25 };
+    assert_eq!(1.0, polynomial.factor(0.0));
+    assert_eq!(1.0 / 3.0, polynomial.factor(2.0));
+    assert!(Attenuation::InverseSquare.factor(0.0).is_finite());
+  }
+
+  #[test]
+  fn implements_mis_heuristics() {
+    assert_eq!(0.5, MisHeuristic::Balance.weight(2.0, 2.0));
+    assert_eq!(0.75, MisHeuristic::Balance.weight(3.0, 1.0));
+    assert_eq!(0.9, MisHeuristic::Power.weight(3.0, 1.0));
+    assert_eq!(1.0, MisHeuristic::Power.weight(1.0, 0.0));
+    assert_eq!(0.0, MisHeuristic::Power.weight(0.0, 0.0));
+  }
+
+  #[test]
+  fn implements_delta_sample_radiance() {
+    let light = PointLight::new(Tuple4::point(0.0, 2.0, 0.0), Color::new(1.0, 1.0, 1.0));
+    let sample = light.sample_radiance(Tuple4::point(0.0, 0.0, 0.0), &mut Rng::new(0)).unwrap();
+    assert_eq!(Tuple4::vector(0.0, 1.0, 0.0), sample.direction);
+    assert_eq!(2.0, sample.distance);
+    assert_eq!(Color::new(PI, PI, PI), sample.radiance);
+    assert_eq!(None, sample.pdf);
+    assert_eq!(0.0, light.pdf(Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 1.0, 0.0)));
+  }
+}
@@ -1,42 +1,57 @@
 extern crate rusty_math as rm;
 
 use crate::color::{Color, BLACK};
+use crate::light::{Light, LightSample};
 use crate::material::Material;
-use crate::point_light::PointLight;
+use crate::rng::Rng;
 use rm::Tuple4;
 
 
-pub fn lighting(mat: &Material, light: &PointLight, point: Tuple4, eye_v: Tuple4, normal_v: Tuple4) -> Color {
-    let color = mat.color * light.intensity;
-    let ambient = color * mat.ambient;
-    let mut diffuse = BLACK;
-    let mut specular = BLACK;
-
-    let mut light_v = light.position - point;
-    light_v.normalize();
-    let light_dot_normal = Tuple4::dot(light_v, normal_v);
-    if light_dot_normal >= 0.0 {
-      diffuse = color * mat.diffuse * light_dot_normal;
-      let reflect_v = Tuple4::reflect(-light_v, normal_v);
-      let reflect_dot_eye = Tuple4::dot(reflect_v, eye_v);
-      if reflect_dot_eye > 0.0 {
-        let factor = reflect_dot_eye.powf(mat.shininess);
-        specular = light.intensity * mat.specular * factor;
-      }
+/// Phong shading of `point` lit by a single unoccluded light, ambient term
+/// included.
+pub fn lighting(mat: &Material, light: &dyn Light, point: Tuple4, eye_v: Tuple4, normal_v: Tuple4, rng: &mut Rng) -> Color {
+    ambient(mat, light.intensity()) + direct(mat, light, point, eye_v, normal_v, rng)
+}
+
+/// Ambient term for `mat` under the given ambient light intensity.
+pub fn ambient(mat: &Material, intensity: Color) -> Color {
+    mat.color * intensity * mat.ambient
+}
+
+/// Diffuse and specular contribution of one unoccluded light, averaged over its
+/// samples and shaded with the material's `ShadingModel`.
+pub fn direct(mat: &Material, light: &dyn Light, point: Tuple4, eye_v: Tuple4, normal_v: Tuple4, rng: &mut Rng) -> Color {
+    direct_shadowed(mat, light, point, eye_v, normal_v, |_| true, rng)
+}
+
+/// Like `direct`, but shadowing each light sample on its own: only the samples
+/// `visible` accepts contribute, so soft shadows darken exactly the shading of
+/// the part of the light they hide.
+pub fn direct_shadowed<F>(mat: &Material, light: &dyn Light, point: Tuple4, eye_v: Tuple4, normal_v: Tuple4, visible: F, rng: &mut Rng) -> Color
+where
+    F: Fn(&LightSample) -> bool
+{
+    let samples = light.samples(point, rng);
+    if samples.is_empty() {
+      return BLACK;
     }
-    ambient + diffuse + specular
+    let sum = samples.iter().filter(|sample| visible(sample)).fold(BLACK, |sum, sample| {
+      sum + mat.model.reflect(mat, sample.direction, eye_v, normal_v, sample.intensity)
+    });
+    sum * (1.0 / samples.len() as f32)
 }
 
 #[cfg(test)]
 mod tests {
-  use super::{Color, lighting, Material, PointLight, Tuple4};
-  use rusty_math::test_utils;
-
-  fn cmp_color(lhs: Color, rhs: Color) -> bool {
-    test_utils::cmp_f32(lhs.r(), rhs.r()) &&
-    test_utils::cmp_f32(lhs.g(), rhs.g()) &&
-    test_utils::cmp_f32(lhs.b(), rhs.b())
-  }
+  use super::{ambient, Color, direct, direct_shadowed, lighting, Material, Rng, Tuple4};
+  use crate::area_light::AreaLight;
+  use crate::directional_light::DirectionalLight;
+  use crate::point_light::PointLight;
+  use crate::shading::ShadingModel;
+  use crate::spot_light::SpotLight;
+  use crate::sphere::Sphere;
+  use crate::test_utils::cmp_color;
+  use std::f32::consts::FRAC_1_SQRT_2;
 
   #[test]
   fn test_lighting_case_eye_between_light_and_surface() {
@@ -45,7 +60,7 @@ mod tests {
     let eye_v = Tuple4::vector(0.0, 0.0, -1.0);
     let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
     let light = PointLight::new(Tuple4::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
-    assert!(cmp_color(Color::new(1.9, 1.9, 1.9), lighting(&m, &light, pos, eye_v, normal_v)));
+    assert!(cmp_color(Color::new(1.9, 1.9, 1.9), lighting(&m, &light, pos, eye_v, normal_v, &mut Rng::new(0))));
   }
 
   #[test]
@@ -56,7 +71,7 @@ mod tests {
     let eye_v = Tuple4::vector(0.0, coord, -coord);
     let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
     let light = PointLight::new(Tuple4::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
-    assert!(cmp_color(Color::new(1.0, 1.0, 1.0), lighting(&m, &light, pos, eye_v, normal_v)));
+    assert!(cmp_color(Color::new(1.0, 1.0, 1.0), lighting(&m, &light, pos, eye_v, normal_v, &mut Rng::new(0))));
   }
 
   #[test]
@@ -66,7 +81,7 @@ mod tests {
     let eye_v = Tuple4::vector(0.0, 0.0, -1.0);
     let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
     let light = PointLight::new(Tuple4::point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
-    assert!(cmp_color(Color::new(0.7363, 0.7363, 0.7363), lighting(&m, &light, pos, eye_v, normal_v)));
+    assert!(cmp_color(Color::new(0.7363, 0.7363, 0.7363), lighting(&m, &light, pos, eye_v, normal_v, &mut Rng::new(0))));
   }
 
   #[test]
@@ -77,7 +92,7 @@ mod tests {
     let eye_v = Tuple4::vector(0.0, -coord, -coord);
     let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
     let light = PointLight::new(Tuple4::point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
-    assert!(cmp_color(Color::new(1.6363962, 1.6363962, 1.6363962), lighting(&m, &light, pos, eye_v, normal_v)));
+    assert!(cmp_color(Color::new(1.6363962, 1.6363962, 1.6363962), lighting(&m, &light, pos, eye_v, normal_v, &mut Rng::new(0))));
   }
 
   #[test]
@@ -87,6 +102,107 @@ mod tests {
     let eye_v = Tuple4::vector(0.0, 0.0, -1.0);
     let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
     let light = PointLight::new(Tuple4::point(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
-    assert!(cmp_color(Color::new(0.1, 0.1, 0.1), lighting(&m, &light, pos, eye_v, normal_v)));
+    assert!(cmp_color(Color::new(0.1, 0.1, 0.1), lighting(&m, &light, pos, eye_v, normal_v, &mut Rng::new(0))));
+  }
+
+  #[test]
+  fn test_lighting_case_surface_in_shadow() {
+    let m = Material::default();
+    let pos = Tuple4::point(0.0, 0.0, 0.0);
+    let eye_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let light = PointLight::new(Tuple4::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+    let color = ambient(&m, light.intensity) + direct_shadowed(&m, &light, pos, eye_v, normal_v, |_| false, &mut Rng::new(0));
+    assert!(cmp_color(Color::new(0.1, 0.1, 0.1), color));
+  }
+
+  #[test]
+  fn test_lighting_samples_area_light() {
+    let light = AreaLight::new(
+      Tuple4::point(-0.5, -0.5, -5.0),
+      Tuple4::vector(1.0, 0.0, 0.0), 2,
+      Tuple4::vector(0.0, 1.0, 0.0), 2,
+      false, Color::new(1.0, 1.0, 1.0)
+    );
+    let m = Material { ambient: 0.1, diffuse: 0.9, specular: 0.0, ..Material::default() };
+    let shape = Sphere::unit();
+    let eye = Tuple4::point(0.0, 0.0, -5.0);
+    let mut rng = Rng::new(0);
+
+    let pos = Tuple4::point(0.0, 0.0, -1.0);
+    let color = lighting(&m, &light, pos, (eye - pos).normalized(), shape.normal_at(pos), &mut rng);
+    assert!(cmp_color(Color::new(0.9965, 0.9965, 0.9965), color), "{}", color);
+
+    let pos = Tuple4::point(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
+    let color = lighting(&m, &light, pos, (eye - pos).normalized(), shape.normal_at(pos), &mut rng);
+    assert!(cmp_color(Color::new(0.6232, 0.6232, 0.6232), color), "{}", color);
+  }
+
+  #[test]
+  fn test_direct_shadows_each_sample() {
+    let light = AreaLight::new(
+      Tuple4::point(-2.0, -0.5, -5.0),
+      Tuple4::vector(4.0, 0.0, 0.0), 4,
+      Tuple4::vector(0.0, 1.0, 0.0), 1,
+      false, Color::new(1.0, 1.0, 1.0)
+    );
+    let m = Material { diffuse: 0.9, specular: 0.0, ..Material::default() };
+    let pos = Tuple4::point(0.0, 0.0, 0.0);
+    let (eye_v, normal_v) = (Tuple4::vector(0.0, 0.0, -1.0), Tuple4::vector(-1.0, 0.0, -1.0).normalized());
+    // the half of the light the surface faces is hidden
+    let visible = |s: &crate::light::LightSample| s.direction.x() > 0.0;
+    let shadowed = direct_shadowed(&m, &light, pos, eye_v, normal_v, visible, &mut Rng::new(4));
+    let everything = direct_shadowed(&m, &light, pos, eye_v, normal_v, |_| true, &mut Rng::new(4));
+    assert_eq!(everything, direct(&m, &light, pos, eye_v, normal_v, &mut Rng::new(4)));
+    // half the light remains, but it is the half that lights the surface least
+    assert!(shadowed.r() > 0.0 && shadowed.r() < 0.5 * everything.r());
+    assert_eq!(Color::new(0.0, 0.0, 0.0), direct_shadowed(&m, &light, pos, eye_v, normal_v, |_| false, &mut Rng::new(4)));
+  }
+
+  #[test]
+  fn test_lighting_with_directional_light() {
+    let m = Material::default();
+    let pos = Tuple4::point(0.0, 0.0, 0.0);
+    let eye_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let light = DirectionalLight::new(Tuple4::vector(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0));
+    assert!(cmp_color(Color::new(1.9, 1.9, 1.9), lighting(&m, &light, pos, eye_v, normal_v, &mut Rng::new(0))));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_lighting_with_spot_light() {
+    let m = Material::default();
+    let pos = Tuple4::point(0.0, 0.0, 0.0);
+    let eye_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let aimed = SpotLight::new(Tuple4::point(0.0, 0.0, -10.0), Tuple4::vector(0.0, 0.0, 1.0), 0.5, 0.1, Color::new(1.0, 1.0, 1.0));
+    assert!(cmp_color(Color::new(1.9, 1.9, 1.9), lighting(&m, &aimed, pos, eye_v, normal_v, &mut Rng::new(0))));
+    let away = SpotLight { direction: Tuple4::vector(0.0, 1.0, 0.0), ..aimed };
+    assert!(cmp_color(Color::new(0.1, 0.1, 0.1), lighting(&m, &away, pos, eye_v, normal_v, &mut Rng::new(0))));
+  }
+
+  #[test]
+  fn test_lighting_uses_material_model() {
+    let pos = Tuple4::point(0.0, 0.0, 0.0);
+    let coord = f32::sqrt(2.0) / 2.0;
+    let eye_v = Tuple4::vector(0.0, -0.2, -1.0).normalized();
+    let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let light = PointLight::new(Tuple4::point(0.0, 10.0 * coord, -10.0 * coord), Color::new(1.0, 1.0, 1.0));
+    let phong = Material::default();
+    let blinn = Material { model: ShadingModel::BlinnPhong, ..phong };
+    let ggx = Material { model: ShadingModel::Ggx { roughness: 0.5, metallic: 0.0 }, ..phong };
+    let mut rng = Rng::new(0);
+    let phong_color = lighting(&phong, &light, pos, eye_v, normal_v, &mut rng);
+    let blinn_color = lighting(&blinn, &light, pos, eye_v, normal_v, &mut rng);
+    let ggx_color = lighting(&ggx, &light, pos, eye_v, normal_v, &mut rng);
+    // the reflected light vector points well away from the eye, so Phong shows
+    // only ambient and diffuse; the half vector still lies close to the normal
+    assert!((phong_color.r() - 0.7364).abs() < 0.0001, "{}", phong_color);
+    let half_v = (light.position - pos).normalized() + eye_v;
+    let highlight = 0.9 * Tuple4::dot(normal_v, half_v.normalized()).powf(200.0);
+    assert!(highlight > 0.0001 && (blinn_color.r() - phong_color.r() - highlight).abs() < 0.000001, "{} {}", blinn_color, highlight);
+    // GGX keeps 96% of the Lambertian term at normal Fresnel and adds a faint off-peak lobe
+    let lambert = FRAC_1_SQRT_2;
+    assert!(ggx_color.r() > 0.1 + 0.95 * lambert && ggx_color.r() < 0.1 + 1.05 * lambert, "{}", ggx_color);
+  }
+}
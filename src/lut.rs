@@ -0,0 +1,156 @@
+use crate::color::Color;
+use crate::errors::ImageError;
+
+use std::fs;
+
+/// Color lookup table sampling a cube of `size`^3 entries spread evenly over
+/// [`domain_min`, `domain_max`] per channel, read from Adobe/Resolve `.cube`
+/// files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut3D {
+  size: usize,
+  domain_min: Color,
+  domain_max: Color,
+  /// Entries with red varying fastest, then green, then blue.
+  table: Vec<Color>
+}
+
+impl Lut3D {
+  /// Table that maps every color in [0, 1] to itself.
+  pub fn identity(size: usize) -> Self {
+    let size = size.max(2);
+    let scale = 1.0 / (size - 1) as f32;
+    let table = (0..size * size * size)
+      .map(|i| Color::new((i % size) as f32 * scale, (i / size % size) as f32 * scale, (i / (size * size)) as f32 * scale))
+      .collect();
+    Self { size, domain_min: Color::new(0.0, 0.0, 0.0), domain_max: Color::new(1.0, 1.0, 1.0), table }
+  }
+
+  /// Parses a `.cube` file with a `LUT_3D_SIZE` and optional `TITLE`,
+  /// `DOMAIN_MIN` and `DOMAIN_MAX` keywords, followed by its entries.
+  pub fn parse(source: &str) -> Result<Self, ImageError> {
+    let mut size = None;
+    let mut domain_min = Color::new(0.0, 0.0, 0.0);
+    let mut domain_max = Color::new(1.0, 1.0, 1.0);
+    let mut table = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+      let error = |message: &str| ImageError::Format(format!("LUT line {}: {}", index + 1, message));
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let mut words = line.split_whitespace();
+      let keyword = words.next().unwrap_or("");
+      let triple = |words: std::str::SplitWhitespace| -> Result<Color, ImageError> {
+        let values = words.map(|w| w.parse::<f32>()).collect::<Result<Vec<_>, _>>().map_err(|_| error("expected numbers"))?;
+        match values[..] {
+          [r, g, b] => Ok(Color::new(r, g, b)),
+          _ => Err(error("expected three values"))
+        }
+      };
+      match keyword {
+        "TITLE" => {},
+        "LUT_3D_SIZE" => {
+          let n = words.next().and_then(|w| w.parse::<usize>().ok()).filter(|n| (2..=256).contains(n));
+          size = Some(n.ok_or_else(|| error("LUT_3D_SIZE must be between 2 and 256"))?);
+        },
+        "LUT_1D_SIZE" => return Err(error("1D LUTs are not supported")),
+        "DOMAIN_MIN" => domain_min = triple(words)?,
+        "DOMAIN_MAX" => domain_max = triple(words)?,
+        _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => return Err(error(&format!("unknown keyword '{}'", keyword))),
+        _ => {
+          if size.is_none() {
+            return Err(error("entries before LUT_3D_SIZE"));
+          }
+          table.push(triple(line.split_whitespace())?);
+        }
+      }
+    }
+    let size = size.ok_or_else(|| ImageError::Format(String::from("LUT has no LUT_3D_SIZE")))?;
+    if table.len() != size * size * size {
+      return Err(ImageError::Format(format!("LUT has {} entries, expected {}", table.len(), size * size * size)));
+    }
+    if domain_min.r() >= domain_max.r() || domain_min.g() >= domain_max.g() || domain_min.b() >= domain_max.b() {
+      return Err(ImageError::Format(String::from("LUT domain is empty")));
+    }
+    Ok(Self { size, domain_min, domain_max, table })
+  }
+
+  pub fn load(file: &str) -> Result<Self, ImageError> {
+    Self::parse(&fs::read_to_string(file)?)
+  }
+
+  /// Trilinearly interpolated table value at `c`, which is clamped to the domain.
+  pub fn lookup(&self, c: Color) -> Color {
+    let last = (self.size - 1) as f32;
+    let coordinate = |v: f32, min: f32, max: f32| {
+      let t = ((v - min) / (max - min)).clamp(0.0, 1.0) * last;
+      let i = (t.floor() as usize).min(self.size - 2);
+      (i, t - i as f32)
+    };
+    let (r, fr) = coordinate(c.r(), self.domain_min.r(), self.domain_max.r());
+    let (g, fg) = coordinate(c.g(), self.domain_min.g(), self.domain_max.g());
+    let (b, fb) = coordinate(c.b(), self.domain_min.b(), self.domain_max.b());
+    let at = |dr: usize, dg: usize, db: usize| self.table[((b + db) * self.size + g + dg) * self.size + r + dr];
+    let lerp = |a: Color, b: Color, t: f32| a * (1.0 - t) + b * t;
+    let (c00, c10) = (lerp(at(0, 0, 0), at(1, 0, 0), fr), lerp(at(0, 1, 0), at(1, 1, 0), fr));
+    let (c01, c11) = (lerp(at(0, 0, 1), at(1, 0, 1), fr), lerp(at(0, 1, 1), at(1, 1, 1), fr));
+    lerp(lerp(c00, c10, fg), lerp(c01, c11, fg), fb)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Lut3D;
+  use crate::color::Color;
+  use crate::errors::ImageError;
+  use crate::test_utils::cmp_color;
+
+  /// Swaps red and blue.
+  const SWAP: &str = "\
+# made by hand
+TITLE \"swap\"
+LUT_3D_SIZE 2
+
+0 0 0
+0 0 1
+0 1 0
+0 1 1
+1 0 0
+1 0 1
+1 1 0
+1 1 1
+";
+
+  #[test]
+  fn implements_lookup() {
+    let identity = Lut3D::identity(5);
+    for c in [Color::new(0.0, 0.0, 0.0), Color::new(0.3, 0.55, 0.9), Color::new(1.0, 1.0, 1.0)] {
+      assert!(cmp_color(c, identity.lookup(c)));
+    }
+    // out of domain values clamp
+    assert!(cmp_color(Color::new(1.0, 0.0, 0.5), identity.lookup(Color::new(2.0, -1.0, 0.5))));
+
+    let swap = Lut3D::parse(SWAP).unwrap();
+    assert!(cmp_color(Color::new(0.8, 0.5, 0.2), swap.lookup(Color::new(0.2, 0.5, 0.8))));
+    let scaled = Lut3D::parse(&SWAP.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nDOMAIN_MAX 2 2 2")).unwrap();
+    assert!(cmp_color(Color::new(0.5, 0.25, 0.0), scaled.lookup(Color::new(0.0, 0.5, 1.0))));
+  }
+
+  #[test]
+  fn rejects_malformed_luts() {
+    let message = |source: &str| match Lut3D::parse(source) {
+      Err(ImageError::Format(msg)) => msg,
+      other => panic!("expected a format error, got {:?}", other)
+    };
+    assert_eq!("LUT has 7 entries, expected 8", message(&SWAP.replace("1 1 1\n", "")));
+    assert_eq!("LUT line 6: expected three values", message(&SWAP.replace("0 0 1", "0 0")));
+    assert_eq!("LUT line 7: expected numbers", message(&SWAP.replace("0 1 0", "0 one 0")));
+    assert_eq!("LUT line 1: 1D LUTs are not supported", message("LUT_1D_SIZE 4"));
+    assert_eq!("LUT line 1: LUT_3D_SIZE must be between 2 and 256", message("LUT_3D_SIZE 1"));
+    assert_eq!("LUT line 1: entries before LUT_3D_SIZE", message("0 0 0"));
+    assert_eq!("LUT line 1: unknown keyword 'LUT_SIZE'", message("LUT_SIZE 2"));
+    assert_eq!("LUT has no LUT_3D_SIZE", message("# empty"));
+    assert!(matches!(Lut3D::load("no/such/file.cube"), Err(ImageError::Io(_))));
+  }
+}
@@ -1,61 +1,172 @@
+extern crate rusty_cage;
 extern crate rusty_math as rm;
 
-mod canvas;
-mod color;
-mod material;
-mod lighting;
-mod point_light;
-mod ray;
-mod sphere;
-mod intersection;
-
-use canvas::Canvas;
-use color::Color;
-use lighting::lighting;
-use material::Material;
-use point_light::PointLight;
-use rm::Tuple4;
-use ray::Ray;
-use sphere::{Sphere};
-use intersection::{Intersect, hit};
-
-fn main() {
-    let canvas_pixels = 100;
-    let mut canvas = Canvas::new(canvas_pixels, canvas_pixels);
-
-    let wall_z = 10.0;
-    let wall_size = 7.0;
-    let half_wall_size = wall_size / 2.0;
-    let pixel_size = wall_size / canvas_pixels as f32;
-
-    let mut material = Material::default();
-    material.color = Color::new(1.0, 0.2, 1.0);
-
-    let mut sphere = Sphere::unit();
-    sphere.set_material(material);
-
-    let ray_origin = Tuple4::point(0.0, 0.0, -5.0);
-
-    let light = PointLight::new(Tuple4::point(-10.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
-
-    for x in 0..canvas_pixels {
-        for y in 0 ..canvas_pixels {
-            let p_x = -half_wall_size + (x as f32 * pixel_size);
-            let p_y = half_wall_size - (y as f32 * pixel_size);
-            let p = Tuple4::point(p_x, p_y, wall_z);
-            let mut ray_direction = p - ray_origin;
-            ray_direction.normalize();
-            let ray = Ray::new(ray_origin, ray_direction);    
-            
-            if let Some(intersections) = sphere.intersects(ray) {
-                let hit_record = hit(&intersections).unwrap();
-                let hit_point = ray.point_at(hit_record.t);
-                let normal = hit_record.o.normal_at(hit_point);
-                let eye = -ray.direction;
-                let color = lighting(&hit_record.o.get_material(), &light, hit_point, eye, normal);
-                canvas.write(x, y, &color);
+mod cli;
+mod demos;
+
+use cli::{CliError, Command, Options, PostArg};
+use rusty_cage::filter::Filter;
+use rusty_cage::lut::Lut3D;
+use rusty_cage::post::{post_process, Effect};
+use rusty_cage::aov::render_aovs;
+use rusty_cage::canvas::Canvas;
+use rusty_cage::color::{Color, Transfer};
+use rusty_cage::denoise::{denoise, DenoiseSettings};
+use rusty_cage::errors::ImageError;
+use rusty_cage::render::{
+    render_progressive, render_resumable, render_with_progress, CheckpointSettings, Progress, ProgressiveSettings,
+    RenderSettings
+};
+use rusty_cage::sampler::SamplePattern;
+use rusty_cage::scene::Scene;
+
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+fn main() -> ExitCode {
+    match run(env::args().skip(1)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e @ CliError::Usage(_)) => {
+            eprintln!("error: {}", e);
+            eprintln!("Try 'rusty-cage --help' for more information.");
+            ExitCode::from(2)
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: impl Iterator<Item = String>) -> Result<(), CliError> {
+    let options = match cli::parse_args(args)? {
+        Command::Help => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        },
+        Command::Render(options) => *options
+    };
+
+    let mut scene = load_scene(&options.scene)?;
+    let (width, height) = cli::image_size(&options, scene.camera.hsize, scene.camera.vsize)?;
+    scene.camera.set_size(width, height);
+
+    let settings = render_settings(&options);
+    // the denoiser is guided by the AOVs, so they come first
+    let aovs = (options.aovs || options.denoise).then(|| render_aovs(&scene.camera, &scene.world, &settings));
+    let effects = post_effects(&options)?;
+    let finish = |image: &Canvas| {
+        let image = match &aovs {
+            Some(aovs) if options.denoise => denoise(image, aovs, &DenoiseSettings::default()),
+            _ => image.clone()
+        };
+        post_process(&image, &effects)
+    };
+    let start = Instant::now();
+    // redraw the bar only when the rounded percentage moves
+    let shown = AtomicUsize::new(usize::MAX);
+    let report = |progress: Progress| {
+        let percent = (progress.fraction() * 100.0) as usize;
+        if options.quiet || shown.swap(percent, Ordering::Relaxed) == percent {
+            return;
+        }
+        eprint!("\r{}", cli::progress_bar(progress, start.elapsed()));
+        let _ = io::stderr().flush();
+    };
+    let output = if options.progressive {
+        let progressive = ProgressiveSettings { max_samples: options.samples, time_budget: options.time };
+        // every pass overwrites the output, so a usable image exists from the first one on
+        render_progressive(&scene.camera, &scene.world, &settings, &progressive, |image, pass| {
+            finish(image).save(&options.output)?;
+            if !options.quiet {
+                eprintln!(
+                    "Pass {}: {} samples per pixel after {}",
+                    pass.index + 1, pass.samples_per_pixel, cli::format_duration(pass.elapsed)
+                );
             }
+            Ok::<(), ImageError>(())
+        })?
+    } else {
+        let output = match &options.checkpoint {
+            Some(file) => {
+                let checkpoint = CheckpointSettings {
+                    file: file.clone(),
+                    interval: Duration::from_secs(options.checkpoint_every)
+                };
+                render_resumable(&scene.camera, &scene.world, &settings, &checkpoint, options.resume, report)?
+            },
+            None => render_with_progress(&scene.camera, &scene.world, &settings, report)
+        };
+        if !options.quiet {
+            eprintln!();
         }
+        finish(&output.image).save(&options.output)?;
+        output
+    };
+    if let Some(aovs) = aovs.as_ref().filter(|_| options.aovs) {
+        for (name, pass) in aovs.passes() {
+            // HDR files cannot hold negative values, so normals are mapped from [-1, 1] to [0, 1]
+            let pass = if name == "normal" { encode_normals(pass) } else { pass.clone() };
+            pass.save(&cli::aov_file(&options.output, name))?;
+        }
+    }
+    if !options.quiet {
+        // a time budget may end progressive renders short of the requested samples
+        let samples = output.sample_counts.iter().copied().max().unwrap_or(0);
+        println!(
+            "Rendered {}x{} at {} samples per pixel to {}",
+            scene.camera.hsize, scene.camera.vsize, samples, options.output
+        );
+        println!("{}", output.stats);
+    }
+    Ok(())
+}
+
+fn post_effects(options: &Options) -> Result<Vec<Effect>, CliError> {
+    let mut effects = Vec::new();
+    for arg in &options.post {
+        effects.push(match arg {
+            PostArg::Bloom(threshold) => Effect::Bloom { threshold: *threshold, radius: 0.01, intensity: 0.3 },
+            PostArg::Vignette(strength) => Effect::Vignette { strength: *strength, falloff: 2.0 },
+            PostArg::Aberration(amount) => Effect::ChromaticAberration { amount: *amount },
+            PostArg::Grain(strength) => Effect::Grain { strength: *strength, seed: options.seed },
+            PostArg::Lut(file) => Effect::Lut { lut: Lut3D::load(file).map_err(|e| CliError::Lut(file.clone(), e))?, transfer: Transfer::Srgb }
+        });
+    }
+    Ok(effects)
+}
+
+fn encode_normals(normals: &Canvas) -> Canvas {
+    normals.map(|n| n * 0.5 + Color::new(0.5, 0.5, 0.5))
+}
+
+/// Loads the scene file `name`, or the demo of that name when no such file exists.
+fn load_scene(name: &str) -> Result<Scene, CliError> {
+    if !Path::new(name).exists() {
+        if let Some(scene) = demos::scene(name) {
+            return Ok(scene?);
+        }
+        if !name.contains('.') && !name.contains('/') {
+            return Err(CliError::Usage(format!(
+                "no scene file or demo named '{}' (demos: {})", name, demos::NAMES.join(", ")
+            )));
+        }
+    }
+    Ok(Scene::load(name)?)
+}
+
+fn render_settings(options: &Options) -> RenderSettings {
+    RenderSettings {
+        samples_per_pixel: options.samples,
+        integrator: options.integrator,
+        pattern: SamplePattern::Jittered,
+        filter: Filter::mitchell(),
+        seed: options.seed,
+        threads: options.threads,
+        ..RenderSettings::default()
     }
-    canvas.export_ppm(&String::from("sphere.ppm"));
 }
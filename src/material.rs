@@ -1,4 +1,5 @@
-use crate::Color;
+use crate::color::{Color, BLACK};
+use crate::shading::ShadingModel;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Material {
@@ -6,7 +7,10 @@ pub struct Material {
   pub ambient: f32,
   pub diffuse: f32,
   pub specular: f32,
-  pub shininess: f32
+  pub shininess: f32,
+  pub model: ShadingModel,
+  /// Radiance emitted by the surface itself.
+  pub emission: Color
 }
 
 impl Material {
@@ -16,24 +20,47 @@ impl Material {
       ambient: ambient,
       diffuse: diffuse,
       specular: specular,
-      shininess: shininess
+      shininess: shininess,
+      model: ShadingModel::Phong,
+      emission: BLACK
     } 
   }
 
+  pub fn emissive(emission: Color) -> Self {
+    Self {
+      color: BLACK,
+      ambient: 0.0,
+      diffuse: 0.0,
+      specular: 0.0,
+      emission,
+      ..Self::default()
+    }
+  }
+
+  pub fn ggx(color: Color, roughness: f32, metallic: f32) -> Self {
+    Self {
+      color,
+      model: ShadingModel::Ggx { roughness, metallic },
+      ..Self::default()
+    }
+  }
+
   pub fn default() -> Self {
     Self {
       color: Color::new(1.0, 1.0, 1.0),
       ambient: 0.1,
       diffuse: 0.9,
       specular: 0.9,
-      shininess: 200.0
+      shininess: 200.0,
+      model: ShadingModel::Phong,
+      emission: BLACK
     }
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use super::{Color, Material};
+  use super::{Color, Material, ShadingModel, BLACK};
 
   #[test]
   fn implements_constructor() {
@@ -43,7 +70,9 @@ mod tests {
       ambient: 0.1,
       diffuse: 0.9,
       specular: 0.9,
-      shininess: 200.0
+      shininess: 200.0,
+      model: ShadingModel::Phong,
+      emission: BLACK
     }, mat);
 
     let mat = Material::new(Color::new(1.0, 0.0, 0.0), 0.0, 1.0, 2.0, 3.0);
@@ -52,7 +81,17 @@ mod tests {
       ambient: 0.0,
       diffuse: 1.0,
       specular: 2.0,
-      shininess: 3.0
+      shininess: 3.0,
+      model: ShadingModel::Phong,
+      emission: BLACK
     }, mat);
+
+    let mat = Material::ggx(Color::new(1.0, 0.5, 0.0), 0.3, 1.0);
+    assert_eq!(Color::new(1.0, 0.5, 0.0), mat.color);
+    assert_eq!(ShadingModel::Ggx { roughness: 0.3, metallic: 1.0 }, mat.model);
+
+    let mat = Material::emissive(Color::new(4.0, 4.0, 2.0));
+    assert_eq!(Color::new(4.0, 4.0, 2.0), mat.emission);
+    assert_eq!(BLACK, mat.color);
   }
 }
\ No newline at end of file
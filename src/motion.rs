@@ -0,0 +1,90 @@
+extern crate rusty_math as rm;
+
+use rm::{Matrix4, MatrixInversionError, Quaternion, Tuple4};
+
+/// Transform moving from `start` at time 0 to `end` at time 1. In between, the
+/// translation, rotation and scale of both ends are interpolated separately so
+/// that rotating objects keep their shape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimatedTransform {
+  start: Matrix4,
+  end: Matrix4,
+  translations: (Tuple4, Tuple4),
+  rotations: (Quaternion, Quaternion),
+  scales: (Matrix4, Matrix4)
+}
+
+impl AnimatedTransform {
+  pub fn new(start: Matrix4, end: Matrix4) -> Result<Self, MatrixInversionError> {
+    let (start_translation, start_rotation, start_scale) = start.decompose()?;
+    let (end_translation, end_rotation, end_scale) = end.decompose()?;
+    Ok(Self {
+      start,
+      end,
+      translations: (start_translation, end_translation),
+      rotations: (start_rotation, end_rotation),
+      scales: (start_scale, end_scale)
+    })
+  }
+
+  pub fn start(&self) -> Matrix4 {
+    self.start
+  }
+
+  pub fn end(&self) -> Matrix4 {
+    self.end
+  }
+
+  /// Transform at `time`, clamped to [0, 1].
+  pub fn at(&self, time: f32) -> Matrix4 {
+    if time <= 0.0 {
+      return self.start;
+    }
+    if time >= 1.0 {
+      return self.end;
+    }
+    let lerp = |a: Tuple4, b: Tuple4| a * (1.0 - time) + b * time;
+    let translation = lerp(self.translations.0, self.translations.1);
+    let rotation = Quaternion::slerp(self.rotations.0, self.rotations.1, time);
+    let (a, b) = self.scales;
+    let scale = Matrix4::new(lerp(a.c0, b.c0), lerp(a.c1, b.c1), lerp(a.c2, b.c2), lerp(a.c3, b.c3));
+    Matrix4::compose(translation, rotation, scale)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{AnimatedTransform, Matrix4, Tuple4};
+  use std::f32::consts::FRAC_PI_2;
+
+  fn cmp_matrix(a: Matrix4, b: Matrix4) -> bool {
+    [(a.c0, b.c0), (a.c1, b.c1), (a.c2, b.c2), (a.c3, b.c3)].iter().all(|(x, y)| (*x - *y).length() < 0.0001)
+  }
+
+  #[test]
+  fn implements_at() {
+    let start = Matrix4::translation(0.0, 0.0, 0.0);
+    let end = Matrix4::rotation_y(FRAC_PI_2).scale(3.0, 3.0, 3.0).translate(4.0, 2.0, 0.0);
+    let motion = AnimatedTransform::new(start, end).unwrap();
+    assert_eq!(start, motion.at(0.0));
+    assert_eq!(start, motion.at(-1.0));
+    assert_eq!(end, motion.at(1.0));
+    assert!(cmp_matrix(
+      Matrix4::rotation_y(FRAC_PI_2 / 2.0).scale(2.0, 2.0, 2.0).translate(2.0, 1.0, 0.0),
+      motion.at(0.5)
+    ));
+  }
+
+  #[test]
+  fn implements_rigid_rotation() {
+    // interpolating matrices directly would shrink a half-turning object midway
+    let motion = AnimatedTransform::new(Matrix4::identity(), Matrix4::rotation_z(3.0)).unwrap();
+    let p = motion.at(0.5) * Tuple4::point(1.0, 0.0, 0.0);
+    assert!((p - Tuple4::point(1.5f32.cos(), 1.5f32.sin(), 0.0)).length() < 0.0001);
+  }
+
+  #[test]
+  fn rejects_singular_transforms() {
+    assert!(AnimatedTransform::new(Matrix4::identity(), Matrix4::scaling(0.0, 1.0, 1.0)).is_err());
+  }
+}
@@ -1,33 +1,75 @@
 extern crate rusty_math as rm;
 
 use crate::Color;
+use crate::light::{Attenuation, Light, LightSample};
+use crate::rng::Rng;
 use rm::Tuple4;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct PointLight {
   pub position: Tuple4,
-  pub intensity: Color
+  pub intensity: Color,
+  pub attenuation: Attenuation
 }
 
 impl PointLight {
   pub fn new(position: Tuple4, intensity: Color) -> Self {
     Self {
       position: position,
-      intensity: intensity
+      intensity: intensity,
+      attenuation: Attenuation::None
     }
   }
+
+  pub fn set_attenuation(&mut self, attenuation: Attenuation) {
+    self.attenuation = attenuation;
+  }
+}
+
+impl Light for PointLight {
+  fn samples(&self, point: Tuple4, _rng: &mut Rng) -> Vec<LightSample> {
+    let v = self.position - point;
+    let distance = v.length();
+    vec![LightSample {
+      direction: v.normalized(),
+      distance,
+      intensity: self.intensity * self.attenuation.factor(distance)
+    }]
+  }
+
+  fn intensity(&self) -> Color {
+    self.intensity
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use super::{Color, PointLight, Tuple4};
+  use super::{Attenuation, Color, Light, LightSample, PointLight, Rng, Tuple4};
 
   #[test]
   fn implements_constructor() {
     let position = Tuple4::point(0.0, 0.0, 0.0);
     let color = Color::new(1.0, 1.0, 1.0);
     let light = PointLight::new(position, color);
-    assert_eq!(PointLight { position: position, intensity: color }, light);
+    assert_eq!(PointLight { position: position, intensity: color, attenuation: Attenuation::None }, light);
   }
 
-}
\ No newline at end of file
+  #[test]
+  fn implements_samples() {
+    let light = PointLight::new(Tuple4::point(0.0, 3.0, 4.0), Color::new(1.0, 0.5, 1.0));
+    assert_eq!(
+      vec![LightSample { direction: Tuple4::vector(0.0, 0.6, 0.8), distance: 5.0, intensity: Color::new(1.0, 0.5, 1.0) }],
+      light.samples(Tuple4::point(0.0, 0.0, 0.0), &mut Rng::new(0))
+    );
+    assert_eq!(Color::new(1.0, 0.5, 1.0), light.intensity());
+  }
+
+  #[test]
+  fn implements_attenuation() {
+    let mut light = PointLight::new(Tuple4::point(0.0, 0.0, 2.0), Color::new(4.0, 4.0, 4.0));
+    light.set_attenuation(Attenuation::InverseSquare);
+    let samples = light.samples(Tuple4::point(0.0, 0.0, 0.0), &mut Rng::new(0));
+    assert_eq!(Color::new(1.0, 1.0, 1.0), samples[0].intensity);
+    assert_eq!(Color::new(4.0, 4.0, 4.0), light.intensity());
+  }
+}
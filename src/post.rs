@@ -0,0 +1,214 @@
+use crate::canvas::Canvas;
+use crate::color::{Color, Transfer, BLACK};
+use crate::lut::Lut3D;
+use crate::rng::Rng;
+
+/// Image operation in a post-processing chain. Effects work on linear
+/// radiance, after rendering and denoising and before export. Sizes are
+/// fractions of the image width, so looks carry over between resolutions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect {
+  /// Adds the parts of the image brighter than `threshold`, blurred by a
+  /// Gaussian of standard deviation `radius` and scaled by `intensity`.
+  Bloom { threshold: f32, radius: f32, intensity: f32 },
+  /// Darkens towards the corners by up to `strength`, following the distance
+  /// from the center raised to `falloff`.
+  Vignette { strength: f32, falloff: f32 },
+  /// Lateral chromatic aberration: red is magnified by `amount` about the
+  /// center and blue shrunk by as much, splitting the channels towards the edges.
+  ChromaticAberration { amount: f32 },
+  /// Monochrome film grain, strongest in the midtones. The pattern depends
+  /// only on `seed` and the pixel position.
+  Grain { strength: f32, seed: u64 },
+  /// Grades through `lut`, which maps colors encoded with `transfer`; grading
+  /// LUTs usually expect sRGB values.
+  Lut { lut: Lut3D, transfer: Transfer }
+}
+
+impl Effect {
+  pub fn apply(&self, image: &Canvas) -> Canvas {
+    let (width, height) = (image.width(), image.height());
+    let mut output = Canvas::new(width, height);
+    // offsets from the center, with the half diagonal at 1
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    let half_diagonal = (cx * cx + cy * cy).sqrt().max(f32::EPSILON);
+    match self {
+      Effect::Bloom { threshold, radius, intensity } => {
+        let over = |v: f32| (v - threshold).max(0.0);
+        let bright = image.map(|c| Color::new(over(c.r()), over(c.g()), over(c.b())));
+        let glow = gaussian_blur(&bright, radius * width as f32);
+        for y in 0..height {
+          for x in 0..width {
+            output.write(x, y, &(image.pixel_at(x, y) + glow.pixel_at(x, y) * *intensity));
+          }
+        }
+      },
+      Effect::Vignette { strength, falloff } => {
+        for y in 0..height {
+          for x in 0..width {
+            let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+            let r = (dx * dx + dy * dy).sqrt() / half_diagonal;
+            let factor = (1.0 - strength * r.powf(*falloff)).max(0.0);
+            output.write(x, y, &(image.pixel_at(x, y) * factor));
+          }
+        }
+      },
+      Effect::ChromaticAberration { amount } => {
+        for y in 0..height {
+          for x in 0..width {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            // sampling nearer the center magnifies the channel
+            let channel = |scale: f32| image.bilinear_at(cx + (px - cx) / scale, cy + (py - cy) / scale);
+            let (red, blue) = (channel(1.0 + amount), channel(1.0 - amount));
+            output.write(x, y, &Color::new(red.r(), image.pixel_at(x, y).g(), blue.b()));
+          }
+        }
+      },
+      Effect::Grain { strength, seed } => {
+        for y in 0..height {
+          for x in 0..width {
+            let c = image.pixel_at(x, y);
+            let mut rng = Rng::for_pixel(*seed, x, y);
+            // a sum of two uniforms is triangular, closer to real grain than flat noise
+            let noise = rng.next_f32() + rng.next_f32() - 1.0;
+            let luminance = c.luminance().clamp(0.0, 1.0);
+            let amplitude = strength * 4.0 * luminance * (1.0 - luminance);
+            let grained = c + Color::new(1.0, 1.0, 1.0) * (noise * amplitude);
+            output.write(x, y, &Color::new(grained.r().max(0.0), grained.g().max(0.0), grained.b().max(0.0)));
+          }
+        }
+      },
+      Effect::Lut { lut, transfer } => {
+        output = image.map(|c| lut.lookup(c.encoded(*transfer)).decoded(*transfer));
+      }
+    }
+    output
+  }
+}
+
+/// Applies `effects` to `image` in order.
+pub fn post_process(image: &Canvas, effects: &[Effect]) -> Canvas {
+  effects.iter().fold(image.clone(), |image, effect| effect.apply(&image))
+}
+
+/// Separable Gaussian blur with standard deviation `sigma` in pixels. Taps past
+/// the border are left out and the rest renormalised, so edges do not darken.
+pub(crate) fn gaussian_blur(image: &Canvas, sigma: f32) -> Canvas {
+  if sigma <= 0.0 {
+    return image.clone();
+  }
+  let radius = (3.0 * sigma).ceil() as isize;
+  let weights: Vec<f32> = (-radius..=radius).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+  let (width, height) = (image.width() as isize, image.height() as isize);
+  let pass = |source: &Canvas, (dx, dy): (isize, isize)| {
+    let mut blurred = Canvas::new(source.width(), source.height());
+    for y in 0..height {
+      for x in 0..width {
+        let (mut sum, mut total) = (BLACK, 0.0);
+        for (i, weight) in (-radius..=radius).zip(&weights) {
+          let (sx, sy) = (x + i * dx, y + i * dy);
+          if (0..width).contains(&sx) && (0..height).contains(&sy) {
+            sum = sum + source.pixel_at(sx as usize, sy as usize) * *weight;
+            total += weight;
+          }
+        }
+        blurred.write(x as usize, y as usize, &(sum * (1.0 / total)));
+      }
+    }
+    blurred
+  };
+  pass(&pass(image, (1, 0)), (0, 1))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{post_process, Effect};
+  use crate::canvas::Canvas;
+  use crate::color::{Color, Transfer, BLACK};
+  use crate::lut::Lut3D;
+  use crate::test_utils::cmp_color;
+
+  fn gray(width: usize, height: usize, v: f32) -> Canvas {
+    let mut canvas = Canvas::new(width, height);
+    canvas.fill(&Color::new(v, v, v));
+    canvas
+  }
+
+  #[test]
+  fn implements_bloom() {
+    let mut image = gray(21, 21, 0.5);
+    image.write(10, 10, &Color::new(20.0, 20.0, 20.0));
+    let bloom = Effect::Bloom { threshold: 1.0, radius: 0.1, intensity: 0.5 };
+    let bloomed = bloom.apply(&image);
+    // light spills around the bright pixel and fades with distance, dim areas gain nothing of their own
+    let glow = |x: usize| bloomed.pixel_at(x, 10).r() - 0.5;
+    assert!(glow(11) > glow(13) && glow(13) > glow(16) && glow(16) > 0.0);
+    assert!(cmp_color(Color::new(0.5, 0.5, 0.5), bloom.apply(&gray(21, 21, 0.5)).pixel_at(3, 4)));
+    // the spread energy matches the part over the threshold
+    let total: f32 = bloomed.pixels().map(|(x, y, c)| c.r() - image.pixel_at(x, y).r()).sum();
+    assert!((total - 0.5 * 19.0).abs() < 0.05);
+  }
+
+  #[test]
+  fn implements_vignette() {
+    let image = gray(20, 10, 1.0);
+    let vignetted = Effect::Vignette { strength: 0.5, falloff: 2.0 }.apply(&image);
+    assert!(vignetted.pixel_at(10, 5).r() > 0.99);
+    let corner = vignetted.pixel_at(0, 0).r();
+    assert!(corner < 0.6 && corner > 0.5);
+    assert!(vignetted.pixel_at(5, 5).r() > vignetted.pixel_at(2, 5).r());
+  }
+
+  #[test]
+  fn implements_chromatic_aberration() {
+    // a white column right of center
+    let mut image = Canvas::new(21, 3);
+    for y in 0..3 {
+      image.write(15, y, &Color::new(1.0, 1.0, 1.0));
+    }
+    let split = Effect::ChromaticAberration { amount: 0.2 }.apply(&image);
+    let (red, blue) = (|x| split.pixel_at(x, 1).r(), |x| split.pixel_at(x, 1).b());
+    assert_eq!(1.0, split.pixel_at(15, 1).g());
+    // red is pushed outwards, blue inwards
+    assert!(red(16) + red(17) > 0.9 && red(15) < 0.2);
+    assert!(blue(13) + blue(14) > 0.9 && blue(15) < 0.1);
+    // flat areas are unchanged
+    let flat = Effect::ChromaticAberration { amount: 0.2 }.apply(&gray(21, 3, 0.4));
+    assert!(cmp_color(Color::new(0.4, 0.4, 0.4), flat.pixel_at(0, 0)));
+  }
+
+  #[test]
+  fn implements_grain() {
+    let image = gray(16, 16, 0.5);
+    let grain = Effect::Grain { strength: 0.1, seed: 4 };
+    let grained = grain.apply(&image);
+    assert_eq!(grained, grain.apply(&image));
+    assert_ne!(grained, Effect::Grain { strength: 0.1, seed: 5 }.apply(&image));
+    let values: Vec<f32> = grained.pixels().map(|(_, _, c)| c.r()).collect();
+    assert!(values.iter().all(|v| (v - 0.5).abs() <= 0.1));
+    assert!(values.iter().any(|v| (v - 0.5).abs() > 0.02));
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    assert!((mean - 0.5).abs() < 0.01);
+    // monochrome, and black stays black
+    let c = grained.pixel_at(3, 7);
+    assert!(c.r() == c.g() && c.g() == c.b());
+    assert_eq!(BLACK, grain.apply(&gray(4, 4, 0.0)).pixel_at(1, 1));
+  }
+
+  #[test]
+  fn implements_lut_grading_and_chains() {
+    let image = gray(4, 4, 0.18);
+    let identity = Effect::Lut { lut: Lut3D::identity(17), transfer: Transfer::Srgb };
+    assert!(cmp_color(Color::new(0.18, 0.18, 0.18), identity.apply(&image).pixel_at(2, 2)));
+    // an inverting LUT applies to encoded values
+    let invert = Lut3D::parse("LUT_3D_SIZE 2\n1 1 1\n0 1 1\n1 0 1\n0 0 1\n1 1 0\n0 1 0\n1 0 0\n0 0 0\n").unwrap();
+    let inverted = Effect::Lut { lut: invert, transfer: Transfer::Srgb }.apply(&image).pixel_at(0, 0);
+    let expected = Transfer::Srgb.decode(1.0 - Transfer::Srgb.encode(0.18));
+    assert!((inverted.r() - expected).abs() < 0.0001);
+
+    let chain = [Effect::Vignette { strength: 0.5, falloff: 2.0 }, Effect::Grain { strength: 0.1, seed: 1 }];
+    let expected = chain[1].apply(&chain[0].apply(&image));
+    assert_eq!(expected, post_process(&image, &chain));
+    assert_eq!(image, post_process(&image, &[]));
+  }
+}
@@ -5,14 +5,21 @@ use rm::{Matrix4, Tuple4};
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Ray {
   pub origin: Tuple4,
-  pub direction: Tuple4
+  pub direction: Tuple4,
+  /// Instant the ray samples, for animated objects; 0 is the start of the motion.
+  pub time: f32
 }
 
 impl Ray {
   pub fn new(origin: Tuple4, direction: Tuple4) -> Self {
+    Self::with_time(origin, direction, 0.0)
+  }
+
+  pub fn with_time(origin: Tuple4, direction: Tuple4, time: f32) -> Self {
     Self {
       origin: origin,
-      direction: direction
+      direction: direction,
+      time: time
     }
   }
 
@@ -23,7 +30,8 @@ impl Ray {
   pub fn transform(&self, t: Matrix4) -> Self {
     Self {
       origin: t * self.origin,
-      direction: t * self.direction
+      direction: t * self.direction,
+      time: self.time
     }
   }
 }
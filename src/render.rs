@@ -0,0 +1,886 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::checkpoint::Checkpoint;
+use crate::color::{Color, BLACK};
+use crate::errors::CheckpointError;
+use crate::filter::Filter;
+use crate::integrator::Integrator;
+use crate::ray::Ray;
+use crate::rng::Rng;
+use crate::sampler::SamplePattern;
+use crate::stats::{self, Counter, Counters, RenderStats};
+use crate::world::World;
+
+use std::convert::Infallible;
+use std::fs;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+  pub samples_per_pixel: usize,
+  pub pattern: SamplePattern,
+  pub filter: Filter,
+  pub seed: u64,
+  /// When set, `samples_per_pixel` is ignored in favour of per-pixel adaptive counts.
+  pub adaptive: Option<AdaptiveSampling>,
+  pub integrator: Integrator,
+  /// Worker threads; 0 uses every available core. Images do not depend on it.
+  pub threads: usize
+}
+
+impl Default for RenderSettings {
+  fn default() -> Self {
+    Self {
+      samples_per_pixel: 1,
+      pattern: SamplePattern::Grid,
+      filter: Filter::default(),
+      seed: 0,
+      adaptive: None,
+      integrator: Integrator::Whitted,
+      threads: 0
+    }
+  }
+}
+
+impl RenderSettings {
+  /// Number of worker threads `threads` resolves to.
+  pub fn thread_count(&self) -> usize {
+    match self.threads {
+      0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+      n => n
+    }
+  }
+}
+
+/// Keeps sampling a pixel until the standard error of its mean luminance drops
+/// below `threshold` relative to the mean, taking between `min_samples` and
+/// `max_samples` samples. Means below 1.0 use `threshold` as an absolute error
+/// so that dark pixels are not oversampled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+  pub min_samples: usize,
+  pub max_samples: usize,
+  pub threshold: f32
+}
+
+impl AdaptiveSampling {
+  fn converged(&self, stats: &PixelStats) -> bool {
+    if stats.count < self.min_samples.max(2) {
+      return false;
+    }
+    stats.standard_error() <= self.threshold * stats.mean.max(MIN_RELATIVE_LUMINANCE)
+  }
+}
+
+const MIN_RELATIVE_LUMINANCE: f32 = 1.0;
+
+pub struct RenderOutput {
+  pub image: Canvas,
+  /// Samples taken per pixel, row-major.
+  pub sample_counts: Vec<usize>,
+  pub stats: RenderStats
+}
+
+/// How far a render has got, reported as rows complete.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+  pub completed_rows: usize,
+  pub total_rows: usize
+}
+
+impl Progress {
+  pub fn fraction(&self) -> f32 {
+    if self.total_rows == 0 { 1.0 } else { self.completed_rows as f32 / self.total_rows as f32 }
+  }
+}
+
+impl RenderOutput {
+  /// Visualises `sample_counts`, from blue (fewest) through green to red (most).
+  pub fn heatmap(&self) -> Canvas {
+    let (width, height) = (self.image.width(), self.image.height());
+    let min = self.sample_counts.iter().copied().min().unwrap_or(0);
+    let max = self.sample_counts.iter().copied().max().unwrap_or(0);
+    let mut canvas = Canvas::new(width, height);
+    for y in 0..height {
+      for x in 0..width {
+        let count = self.sample_counts[y * width + x];
+        let t = if max > min { (count - min) as f32 / (max - min) as f32 } else { 0.0 };
+        canvas.write(x, y, &heat(t));
+      }
+    }
+    canvas
+  }
+}
+
+pub(crate) fn heat(t: f32) -> Color {
+  if t < 0.5 {
+    let s = t * 2.0;
+    Color::new(0.0, s, 1.0 - s)
+  } else {
+    let s = (t - 0.5) * 2.0;
+    Color::new(s, 1.0 - s, 0.0)
+  }
+}
+
+pub fn render(camera: &Camera, world: &World, settings: &RenderSettings) -> Canvas {
+  render_with_counts(camera, world, settings).image
+}
+
+pub fn render_with_counts(camera: &Camera, world: &World, settings: &RenderSettings) -> RenderOutput {
+  render_with_progress(camera, world, settings, |_| {})
+}
+
+/// Renders like `render_with_counts`, calling `progress` each time a batch of
+/// rows is finished. Calls come from the worker threads, one at a time and with
+/// increasing `completed_rows`.
+pub fn render_with_progress<F>(camera: &Camera, world: &World, settings: &RenderSettings, progress: F) -> RenderOutput
+where
+  F: Fn(Progress) + Sync
+{
+  let bands = (0..camera.vsize.div_ceil(BAND_ROWS)).map(|_| None).collect();
+  let rendered = render_bands(camera, world, settings, bands, |_, p| -> Result<(), Infallible> {
+    progress(p);
+    Ok(())
+  });
+  match rendered {
+    Ok(output) => output,
+    Err(e) => match e {}
+  }
+}
+
+/// Where and how often `render_resumable` saves its progress.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckpointSettings {
+  pub file: String,
+  pub interval: Duration
+}
+
+/// Renders like `render_with_progress`, saving the finished rows to the
+/// checkpoint file when starting and then at most once per `interval`. With
+/// `resume` it first loads that file and only renders the missing rows; the
+/// image is identical to one rendered in a single run. The file is removed
+/// once the image is complete.
+pub fn render_resumable<F>(
+  camera: &Camera,
+  world: &World,
+  settings: &RenderSettings,
+  checkpoint: &CheckpointSettings,
+  resume: bool,
+  progress: F
+) -> Result<RenderOutput, CheckpointError>
+where
+  F: Fn(Progress) + Sync
+{
+  let header = Checkpoint::new(camera, world, settings);
+  let bands = if resume {
+    header.load(&checkpoint.file, settings)?
+  } else {
+    (0..camera.vsize.div_ceil(BAND_ROWS)).map(|_| None).collect()
+  };
+  // saving straight away reports an unwritable location before any work is done
+  header.save(&checkpoint.file, &bands)?;
+  let last_save = Mutex::new(Instant::now());
+  let output = render_bands(camera, world, settings, bands, |bands, p| -> Result<(), CheckpointError> {
+    let mut last_save = last_save.lock().unwrap();
+    if p.completed_rows < p.total_rows && last_save.elapsed() >= checkpoint.interval {
+      header.save(&checkpoint.file, bands)?;
+      *last_save = Instant::now();
+    }
+    progress(p);
+    Ok(())
+  })?;
+  fs::remove_file(&checkpoint.file)?;
+  Ok(output)
+}
+
+/// Renders the bands missing from `bands`, then merges them all. `on_band` is
+/// called with every band finished so far, one call at a time; its first error
+/// stops the render.
+fn render_bands<E, F>(
+  camera: &Camera,
+  world: &World,
+  settings: &RenderSettings,
+  bands: Vec<Option<Band>>,
+  on_band: F
+) -> Result<RenderOutput, E>
+where
+  E: Send,
+  F: Fn(&[Option<Band>], Progress) -> Result<(), E> + Sync
+{
+  let start = Instant::now();
+  let cpu_start = stats::cpu_time();
+  let count = match settings.adaptive {
+    Some(adaptive) => adaptive.max_samples.max(1),
+    None => settings.samples_per_pixel.max(1)
+  };
+  let (bands, counters) = render_pass(camera, world, settings, 0..count, bands, on_band)?;
+
+  // bands overlap by the filter radius, so merge them in a fixed order
+  let mut film = Film::new(camera.hsize, camera.vsize, settings.filter);
+  let mut sample_counts = Vec::with_capacity(camera.hsize * camera.vsize);
+  for band in bands {
+    film.merge(&band.film);
+    sample_counts.extend(band.sample_counts);
+  }
+  let stats = RenderStats {
+    counters,
+    wall_time: start.elapsed(),
+    cpu_time: stats::cpu_time().zip(cpu_start).map(|(end, start)| end.saturating_sub(start))
+  };
+  Ok(RenderOutput { image: film.to_canvas(), sample_counts, stats })
+}
+
+/// Takes pixel samples `samples` in the bands missing from `bands`, spread over
+/// the worker threads, and returns every band in image order.
+fn render_pass<E, F>(
+  camera: &Camera,
+  world: &World,
+  settings: &RenderSettings,
+  samples: Range<usize>,
+  bands: Vec<Option<Band>>,
+  on_band: F
+) -> Result<(Vec<Band>, Counters), E>
+where
+  E: Send,
+  F: Fn(&[Option<Band>], Progress) -> Result<(), E> + Sync
+{
+  let (width, height) = (camera.hsize, camera.vsize);
+  let pending: Vec<usize> = (0..bands.len()).filter(|&i| bands[i].is_none()).collect();
+  let completed: usize = bands.iter().flatten().map(|band| band.sample_counts.len() / width.max(1)).sum();
+  let next = AtomicUsize::new(0);
+  let finished = Mutex::new((bands, completed));
+  let error = Mutex::new(None);
+  let counters = Mutex::new(Counters::default());
+  thread::scope(|scope| {
+    for _ in 0..settings.thread_count().min(pending.len()) {
+      scope.spawn(|| {
+        while let Some(&band) = pending.get(next.fetch_add(1, Ordering::Relaxed)) {
+          if error.lock().unwrap().is_some() {
+            break;
+          }
+          let rows = band * BAND_ROWS..((band + 1) * BAND_ROWS).min(height);
+          let rendered = render_band(camera, world, settings, rows.clone(), samples.clone());
+          let mut finished = finished.lock().unwrap();
+          finished.0[band] = Some(rendered);
+          finished.1 += rows.len();
+          if let Err(e) = on_band(&finished.0, Progress { completed_rows: finished.1, total_rows: height }) {
+            error.lock().unwrap().get_or_insert(e);
+          }
+        }
+        let mut counters = counters.lock().unwrap();
+        *counters = *counters + stats::take();
+      });
+    }
+  });
+  if let Some(e) = error.into_inner().unwrap() {
+    return Err(e);
+  }
+  let bands = finished.into_inner().unwrap().0.into_iter().flatten().collect();
+  Ok((bands, counters.into_inner().unwrap()))
+}
+
+/// When `render_progressive` stops refining.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProgressiveSettings {
+  /// Samples per pixel after which the image is final.
+  pub max_samples: usize,
+  /// Wall-clock time after which no further pass is started. Passes are
+  /// shortened to end within it, but the first pass always completes.
+  pub time_budget: Option<Duration>
+}
+
+/// A finished pass of `render_progressive`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pass {
+  pub index: usize,
+  /// Samples per pixel taken so far, in this and all earlier passes.
+  pub samples_per_pixel: usize,
+  pub elapsed: Duration
+}
+
+/// Renders the whole image at one sample per pixel, then refines it in passes
+/// which each double the samples taken so far, until `progressive` says to
+/// stop. `on_pass` receives the image after every pass; its first error stops
+/// the render. Adaptive sampling is not used.
+///
+/// Grid and jittered patterns stratify each pass on its own, while the Halton
+/// sequence carries on from one pass to the next, so Halton renders converge as
+/// they would in a single pass.
+pub fn render_progressive<E, F>(
+  camera: &Camera,
+  world: &World,
+  settings: &RenderSettings,
+  progressive: &ProgressiveSettings,
+  mut on_pass: F
+) -> Result<RenderOutput, E>
+where
+  F: FnMut(&Canvas, Pass) -> Result<(), E>
+{
+  let start = Instant::now();
+  let cpu_start = stats::cpu_time();
+  let settings = RenderSettings { adaptive: None, ..*settings };
+  let (width, height) = (camera.hsize, camera.vsize);
+  let band_count = height.div_ceil(BAND_ROWS);
+  let mut film = Film::new(width, height, settings.filter);
+  let mut sample_counts = vec![0; width * height];
+  let mut counters = Counters::default();
+  let mut image = Canvas::new(width, height);
+  let mut taken = 0;
+  let mut count = 1;
+  for index in 0.. {
+    let bands = (0..band_count).map(|_| None).collect();
+    let rendered = render_pass(camera, world, &settings, taken..taken + count, bands, |_, _| -> Result<(), Infallible> {
+      Ok(())
+    });
+    let (bands, pass_counters) = match rendered {
+      Ok(rendered) => rendered,
+      Err(e) => match e {}
+    };
+    counters = counters + pass_counters;
+    for band in &bands {
+      film.merge(&band.film);
+    }
+    for (total, n) in sample_counts.iter_mut().zip(bands.iter().flat_map(|band| &band.sample_counts)) {
+      *total += n;
+    }
+    taken += count;
+    image = film.to_canvas();
+    on_pass(&image, Pass { index, samples_per_pixel: taken, elapsed: start.elapsed() })?;
+
+    count = progressive.max_samples.max(1).saturating_sub(taken).min(taken);
+    if let Some(budget) = progressive.time_budget {
+      // the time per sample so far predicts how many more fit in the budget
+      let elapsed = start.elapsed();
+      let per_sample = elapsed.as_secs_f64() / taken as f64;
+      let affordable = budget.saturating_sub(elapsed).as_secs_f64() / per_sample.max(f64::MIN_POSITIVE);
+      count = count.min(affordable as usize);
+    }
+    if count == 0 {
+      break;
+    }
+  }
+  let stats = RenderStats {
+    counters,
+    wall_time: start.elapsed(),
+    cpu_time: stats::cpu_time().zip(cpu_start).map(|(end, start)| end.saturating_sub(start))
+  };
+  Ok(RenderOutput { image, sample_counts, stats })
+}
+
+/// Rows rendered as one unit of work. The split does not depend on the thread
+/// count, which keeps images identical however many threads render them.
+pub(crate) const BAND_ROWS: usize = 8;
+
+/// Rendered image rows: a film covering them plus the rows their samples splat
+/// into, and the samples taken per pixel of the rows themselves.
+pub(crate) struct Band {
+  pub(crate) film: Film,
+  pub(crate) sample_counts: Vec<usize>
+}
+
+/// Image rows the samples of `rows` splat into with `filter`, in an image
+/// `height` rows tall.
+pub(crate) fn band_film_rows(rows: &Range<usize>, height: usize, filter: Filter) -> Range<usize> {
+  let margin = (filter.radius() + 0.5).ceil() as usize;
+  rows.start.saturating_sub(margin)..(rows.end + margin).min(height)
+}
+
+/// Takes pixel samples `samples` of every pixel in `rows`; adaptive sampling
+/// may stop a pixel early.
+fn render_band(camera: &Camera, world: &World, settings: &RenderSettings, rows: Range<usize>, samples: Range<usize>) -> Band {
+  let mut film = Film::rows(camera.hsize, band_film_rows(&rows, camera.vsize, settings.filter), settings.filter);
+  let mut sample_counts = Vec::with_capacity(camera.hsize * rows.len());
+  for y in rows {
+    for x in 0..camera.hsize {
+      let mut pixel_rng = Rng::for_pixel(settings.seed, x, y);
+      let rotation = (pixel_rng.next_f32(), pixel_rng.next_f32());
+      let mut stats = PixelStats::default();
+      for index in samples.clone() {
+        let mut rng = Rng::for_sample(settings.seed, x, y, index);
+        let batch = sample_batch(index, &samples, settings.adaptive);
+        let (u, v) = match settings.pattern {
+          SamplePattern::Halton => settings.pattern.sample(index, samples.end, rotation, &mut rng),
+          pattern => pattern.sample(index - batch.start, batch.len(), rotation, &mut rng)
+        };
+        let (px, py) = (x as f32 + u, y as f32 + v);
+        let time = if camera.shutter_close > camera.shutter_open {
+          camera.shutter_time(rng.next_f32())
+        } else {
+          camera.shutter_open
+        };
+        // every pixel sample takes its own lens position
+        let ray = if camera.has_lens() {
+          let (lu, lv) = (rng.next_f32(), rng.next_f32());
+          camera.ray_through_lens(px, py, lu, lv)
+        } else {
+          camera.ray_for_pixel(px, py)
+        };
+        let color = match ray {
+          Some(r) => {
+            stats::record(Counter::CameraRay, 1);
+            settings.integrator.radiance(world, Ray { time, ..r }, &mut rng)
+          },
+          None => BLACK
+        };
+        film.add_sample(px, py, color);
+        stats.add(color.luminance());
+        // a pixel stops only once its batch has covered it
+        let covered = settings.pattern == SamplePattern::Halton || index + 1 == batch.end;
+        if covered && settings.adaptive.is_some_and(|adaptive| adaptive.converged(&stats)) {
+          break;
+        }
+      }
+      sample_counts.push(stats.count);
+    }
+  }
+  Band { film, sample_counts }
+}
+
+/// Samples that sample `index` shares a stratification with: Grid and Jittered
+/// spread each batch over the whole pixel. Without adaptive sampling that is
+/// all of `samples`. Adaptive sampling may stop a pixel after any batch, so the
+/// first batch takes `min_samples` and every later one doubles the count.
+fn sample_batch(index: usize, samples: &Range<usize>, adaptive: Option<AdaptiveSampling>) -> Range<usize> {
+  let adaptive = match adaptive {
+    Some(adaptive) => adaptive,
+    None => return samples.clone()
+  };
+  let mut batch = samples.start..(samples.start + adaptive.min_samples.max(2)).min(samples.end);
+  while index >= batch.end && batch.end < samples.end {
+    batch = batch.end..(2 * batch.end - samples.start).min(samples.end);
+  }
+  batch
+}
+
+/// Running mean and variance of a pixel's sample luminance (Welford).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct PixelStats {
+  count: usize,
+  mean: f32,
+  m2: f32
+}
+
+impl PixelStats {
+  fn add(&mut self, value: f32) {
+    self.count += 1;
+    let delta = value - self.mean;
+    self.mean += delta / self.count as f32;
+    self.m2 += delta * (value - self.mean);
+  }
+
+  fn variance(&self) -> f32 {
+    if self.count < 2 { 0.0 } else { self.m2 / (self.count - 1) as f32 }
+  }
+
+  fn standard_error(&self) -> f32 {
+    (self.variance() / self.count as f32).sqrt()
+  }
+}
+
+/// Accumulates filter weighted samples; each sample is splatted onto every
+/// pixel whose center lies within the filter's radius. A film may hold only
+/// the image rows from `top` on.
+pub(crate) struct Film {
+  pub(crate) width: usize,
+  pub(crate) top: usize,
+  pub(crate) height: usize,
+  pub(crate) filter: Filter,
+  pub(crate) sums: Vec<Color>,
+  pub(crate) weights: Vec<f32>
+}
+
+impl Film {
+  fn new(width: usize, height: usize, filter: Filter) -> Self {
+    Self::rows(width, 0..height, filter)
+  }
+
+  pub(crate) fn rows(width: usize, rows: Range<usize>, filter: Filter) -> Self {
+    let height = rows.len();
+    Self {
+      width,
+      top: rows.start,
+      height,
+      filter,
+      sums: vec![BLACK; width * height],
+      weights: vec![0.0; width * height]
+    }
+  }
+
+  fn add_sample(&mut self, px: f32, py: f32, color: Color) {
+    let radius = self.filter.radius();
+    let x0 = (px - 0.5 - radius).ceil().max(0.0) as usize;
+    let y0 = (py - 0.5 - radius).ceil().max(self.top as f32) as usize;
+    let x1 = ((px - 0.5 + radius).floor() as isize).min(self.width as isize - 1);
+    let y1 = ((py - 0.5 + radius).floor() as isize).min((self.top + self.height) as isize - 1);
+    for y in y0 as isize..=y1 {
+      for x in x0 as isize..=x1 {
+        let weight = self.filter.evaluate(px - (x as f32 + 0.5), py - (y as f32 + 0.5));
+        if weight != 0.0 {
+          let i = (y as usize - self.top) * self.width + x as usize;
+          self.sums[i] = self.sums[i] + color * weight;
+          self.weights[i] += weight;
+        }
+      }
+    }
+  }
+
+  /// Adds the samples accumulated by `other`, which must lie within this film.
+  fn merge(&mut self, other: &Film) {
+    let offset = (other.top - self.top) * self.width;
+    for (i, (sum, weight)) in other.sums.iter().zip(&other.weights).enumerate() {
+      self.sums[offset + i] = self.sums[offset + i] + *sum;
+      self.weights[offset + i] += weight;
+    }
+  }
+
+  fn to_canvas(&self) -> Canvas {
+    let mut canvas = Canvas::new(self.width, self.height);
+    for y in 0..self.height {
+      for x in 0..self.width {
+        let i = y * self.width + x;
+        if self.weights[i].abs() > f32::EPSILON {
+          canvas.write(x, y, &(self.sums[i] * (1.0 / self.weights[i])));
+        }
+      }
+    }
+    canvas
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    render, render_progressive, render_with_counts, render_with_progress, AdaptiveSampling, Color, Counters, Duration, Film,
+    Filter, Integrator, PixelStats, Rng, Progress, ProgressiveSettings, RenderSettings, SamplePattern, BLACK
+  };
+  use std::sync::Mutex;
+  use crate::environment::Background;
+  use crate::camera::Camera;
+  use crate::material::Material;
+  use crate::point_light::PointLight;
+  use crate::sphere::Sphere;
+  use crate::world::World;
+  use rm::Tuple4;
+  use std::f32::consts::{FRAC_PI_2, PI};
+
+  fn scene() -> (Camera, World) {
+    let mut sphere = Sphere::unit();
+    sphere.set_material(Material { ambient: 1.0, diffuse: 0.0, specular: 0.0, ..Material::default() });
+    let light = PointLight::new(Tuple4::point(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
+    let mut camera = Camera::new(9, 9, FRAC_PI_2);
+    camera.set_transform(rm::Matrix4::translation(0.0, 0.0, -2.0)).unwrap();
+    (camera, World::new(vec![sphere], vec![Box::new(light)]))
+  }
+
+  #[test]
+  fn implements_film_splatting() {
+    let mut film = Film::new(3, 3, Filter::Tent { radius: 1.0 });
+    film.add_sample(1.5, 1.5, Color::new(1.0, 1.0, 1.0));
+    assert_eq!(1.0, film.weights[4]);
+    assert_eq!(0.0, film.weights[0]);
+    assert_eq!(0.0, film.weights[1]);
+
+    film.add_sample(1.0, 1.0, Color::new(1.0, 0.0, 0.0));
+    assert_eq!(0.25, film.weights[0]);
+    let canvas = film.to_canvas();
+    assert_eq!(Color::new(1.0, 0.0, 0.0), canvas.pixel_at(0, 0));
+    assert_eq!(BLACK, canvas.pixel_at(2, 2));
+  }
+
+  #[test]
+  fn implements_single_sample_render() {
+    let (camera, world) = scene();
+    let canvas = render(&camera, &world, &RenderSettings::default());
+    assert_eq!(Color::new(1.0, 1.0, 1.0), canvas.pixel_at(4, 4));
+    assert_eq!(BLACK, canvas.pixel_at(0, 0));
+  }
+
+  #[test]
+  fn implements_supersampled_edges() {
+    let (camera, world) = scene();
+    let settings = RenderSettings {
+      samples_per_pixel: 16,
+      pattern: SamplePattern::Jittered,
+      filter: Filter::Box { radius: 0.5 },
+      seed: 3,
+      adaptive: None,
+      integrator: Integrator::Whitted,
+      threads: 1
+    };
+    let canvas = render(&camera, &world, &settings);
+    // silhouette pixels average inside and outside samples
+    let edge = (0..9).map(|x| canvas.pixel_at(x, 4).r()).find(|v| *v > 0.0 && *v < 1.0);
+    assert!(edge.is_some());
+    assert_eq!(canvas, render(&camera, &world, &settings));
+
+    for filter in [Filter::Tent { radius: 1.0 }, Filter::gaussian(), Filter::mitchell()] {
+      for pattern in [SamplePattern::Grid, SamplePattern::Halton] {
+        let settings = RenderSettings { samples_per_pixel: 4, pattern, filter, ..RenderSettings::default() };
+        let canvas = render(&camera, &world, &settings);
+        assert!((canvas.pixel_at(4, 4).r() - 1.0).abs() < 0.0001);
+      }
+    }
+  }
+
+  #[test]
+  fn implements_thread_independence() {
+    let (camera, world) = scene();
+    let settings = RenderSettings {
+      samples_per_pixel: 4,
+      pattern: SamplePattern::Jittered,
+      filter: Filter::mitchell(),
+      threads: 1,
+      ..RenderSettings::default()
+    };
+    // more rows than one band, split across threads in any order
+    let mut camera = camera;
+    camera.set_size(12, 30);
+    let single = render_with_counts(&camera, &world, &settings);
+    for threads in [2, 3, 0] {
+      let output = render_with_counts(&camera, &world, &RenderSettings { threads, ..settings });
+      assert_eq!(single.image, output.image);
+      assert_eq!(single.sample_counts, output.sample_counts);
+    }
+    assert_eq!(12 * 30, single.sample_counts.len());
+  }
+
+  #[test]
+  fn implements_progress_and_stats() {
+    let (mut camera, world) = scene();
+    camera.set_size(9, 20);
+    let settings = RenderSettings { samples_per_pixel: 2, threads: 2, ..RenderSettings::default() };
+    let reports = Mutex::new(Vec::new());
+    let output = render_with_progress(&camera, &world, &settings, |p| reports.lock().unwrap().push(p));
+    let reports = reports.into_inner().unwrap();
+    // 20 rows make three bands
+    assert_eq!(3, reports.len());
+    assert!(reports.windows(2).all(|w| w[0].completed_rows < w[1].completed_rows));
+    assert_eq!(Progress { completed_rows: 20, total_rows: 20 }, reports[2]);
+    assert_eq!(1.0, reports[2].fraction());
+    assert_eq!(output.image, render(&camera, &world, &settings));
+
+    let counters = output.stats.counters;
+    if Counters::ENABLED {
+      assert_eq!(9 * 20 * 2, counters.camera_rays);
+      // Whitted shading casts one shadow ray per hit, and the world holds one object
+      assert!(counters.shadow_rays > 0 && counters.shadow_rays < counters.camera_rays);
+      assert_eq!(counters.camera_rays + counters.shadow_rays, counters.intersection_tests);
+      assert_eq!(0, counters.reflection_rays);
+    } else {
+      assert_eq!(Counters::default(), counters);
+    }
+  }
+
+  #[test]
+  fn implements_progressive_rendering() {
+    let (mut camera, world) = scene();
+    camera.set_size(9, 12);
+    let settings = RenderSettings { pattern: SamplePattern::Halton, filter: Filter::mitchell(), threads: 2, ..RenderSettings::default() };
+    let progressive = ProgressiveSettings { max_samples: 6, time_budget: None };
+    let mut passes = Vec::new();
+    let output = render_progressive(&camera, &world, &settings, &progressive, |image, pass| {
+      passes.push((image.clone(), pass));
+      Ok::<(), ()>(())
+    }).unwrap();
+    // each pass doubles the samples, the last one is cut to the target
+    let counts: Vec<usize> = passes.iter().map(|(_, pass)| pass.samples_per_pixel).collect();
+    assert_eq!(vec![1, 2, 4, 6], counts);
+    assert!(passes.iter().enumerate().all(|(i, (_, pass))| pass.index == i));
+    assert_eq!(render(&camera, &world, &settings), passes[0].0);
+    assert_eq!(passes[3].0, output.image);
+    assert!(output.sample_counts.iter().all(|&n| n == 6));
+
+    // the Halton sequence continues across passes, matching a single pass up to rounding
+    let single = render(&camera, &world, &RenderSettings { samples_per_pixel: 6, ..settings });
+    for y in 0..12 {
+      for x in 0..9 {
+        assert!((single.pixel_at(x, y).r() - output.image.pixel_at(x, y).r()).abs() < 0.0001);
+      }
+    }
+
+    // no time left after the first pass
+    let budget = ProgressiveSettings { max_samples: 1000, time_budget: Some(Duration::ZERO) };
+    let output = render_progressive(&camera, &world, &settings, &budget, |_, _| Ok::<(), ()>(())).unwrap();
+    assert!(output.sample_counts.iter().all(|&n| n == 1));
+    let failed = render_progressive(&camera, &world, &settings, &progressive, |_, pass| {
+      if pass.index == 1 { Err(pass.samples_per_pixel) } else { Ok(()) }
+    });
+    assert!(matches!(failed, Err(2)));
+  }
+
+  #[test]
+  fn implements_film_merging() {
+    let filter = Filter::Tent { radius: 1.0 };
+    let mut whole = Film::new(3, 4, filter);
+    let mut parts = Film::new(3, 4, filter);
+    let mut upper = Film::rows(3, 0..4, filter);
+    let mut lower = Film::rows(3, 2..4, filter);
+    for (px, py, band) in [(1.2, 1.5, 0), (0.7, 2.9, 0), (2.5, 3.1, 1)] {
+      let color = Color::new(px, py, 1.0);
+      whole.add_sample(px, py, color);
+      if band == 0 { upper.add_sample(px, py, color) } else { lower.add_sample(px, py, color) }
+    }
+    parts.merge(&upper);
+    parts.merge(&lower);
+    assert_eq!(whole.to_canvas(), parts.to_canvas());
+  }
+
+  #[test]
+  fn implements_pixel_stats() {
+    let mut stats = PixelStats::default();
+    for v in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+      stats.add(v);
+    }
+    assert_eq!(8, stats.count);
+    assert_eq!(5.0, stats.mean);
+    assert!((stats.variance() - 32.0 / 7.0).abs() < 0.0001);
+    assert!((stats.standard_error() - (32.0f32 / 56.0).sqrt()).abs() < 0.0001);
+  }
+
+  #[test]
+  fn implements_adaptive_sampling() {
+    let (camera, world) = scene();
+    let settings = RenderSettings {
+      pattern: SamplePattern::Jittered,
+      adaptive: Some(AdaptiveSampling { min_samples: 4, max_samples: 64, threshold: 0.01 }),
+      ..RenderSettings::default()
+    };
+    let output = render_with_counts(&camera, &world, &settings);
+    // flat background and flat interior converge immediately
+    assert_eq!(4, output.sample_counts[0]);
+    assert_eq!(4, output.sample_counts[4 * 9 + 4]);
+    // silhouette pixels keep sampling up to the cap
+    assert!(output.sample_counts.contains(&64));
+
+    let heatmap = output.heatmap();
+    assert_eq!(Color::new(0.0, 0.0, 1.0), heatmap.pixel_at(0, 0));
+    let hottest = output.sample_counts.iter().position(|c| *c == 64).unwrap();
+    assert_eq!(Color::new(1.0, 0.0, 0.0), heatmap.pixel_at(hottest % 9, hottest / 9));
+  }
+
+  #[test]
+  fn implements_adaptive_stratification() {
+    let adaptive = Some(AdaptiveSampling { min_samples: 4, max_samples: 64, threshold: 0.01 });
+    let batches: Vec<_> = [0, 3, 4, 7, 8, 63].iter().map(|&i| super::sample_batch(i, &(0..64), adaptive)).collect();
+    assert_eq!(vec![0..4, 0..4, 4..8, 4..8, 8..16, 32..64], batches);
+    assert_eq!(0..64, super::sample_batch(5, &(0..64), None));
+
+    // a pixel stopping at `min_samples` has sampled its whole footprint
+    for pattern in [SamplePattern::Grid, SamplePattern::Jittered] {
+      let mut rng = Rng::new(3);
+      let (mut cx, mut cy) = (0.0, 0.0);
+      for index in 0..4 {
+        let batch = super::sample_batch(index, &(0..64), adaptive);
+        let (u, v) = pattern.sample(index - batch.start, batch.len(), (0.0, 0.0), &mut rng);
+        cx += u / 4.0;
+        cy += v / 4.0;
+      }
+      assert!((cx - 0.5).abs() < 0.25 && (cy - 0.5).abs() < 0.25, "{:?} centroid ({}, {})", pattern, cx, cy);
+    }
+  }
+
+  #[test]
+  fn implements_reproducible_path_tracing() {
+    let (camera, _) = scene();
+    let world = crate::test_utils::default_world();
+    let settings = RenderSettings {
+      samples_per_pixel: 4,
+      pattern: SamplePattern::Jittered,
+      integrator: Integrator::path_tracer(),
+      seed: 9,
+      ..RenderSettings::default()
+    };
+    let canvas = render(&camera, &world, &settings);
+    assert_eq!(canvas, render(&camera, &world, &settings));
+    let reseeded = RenderSettings { seed: 10, ..settings };
+    assert_ne!(canvas, render(&camera, &world, &reseeded));
+  }
+
+  #[test]
+  fn implements_depth_of_field() {
+    // a small sphere in front of the plane of focus
+    let (mut camera, mut world) = scene();
+    world.objects[0].set_transform(rm::Matrix4::scaling(0.5, 0.5, 0.5));
+    // one lens position per pixel sample, so the lens is covered by the pixel samples
+    let settings = RenderSettings { samples_per_pixel: 16, pattern: SamplePattern::Jittered, ..RenderSettings::default() };
+    let sharp = render(&camera, &world, &RenderSettings::default());
+    let pinhole = render(&camera, &world, &settings);
+    camera.aperture = 0.5;
+    camera.focal_distance = 6.0;
+    let blurred = render(&camera, &world, &settings);
+    // the pinhole silhouette is hard, the defocused one spreads into partial coverage
+    let partial = |canvas: &crate::canvas::Canvas| {
+      (0..9).filter(|&x| canvas.pixel_at(x, 4).r() > 0.01 && canvas.pixel_at(x, 4).r() < 0.99).count()
+    };
+    assert_eq!(0, partial(&sharp));
+    assert!(partial(&blurred) >= 2);
+    assert_eq!(blurred, render(&camera, &world, &settings));
+
+    // focusing on the sphere brings its center back to full coverage
+    camera.focal_distance = 1.5;
+    let focused = render(&camera, &world, &settings);
+    assert!((focused.pixel_at(4, 4).r() - 1.0).abs() < 0.0001);
+    let error = |canvas: &crate::canvas::Canvas| {
+      (0..9).map(|x| (canvas.pixel_at(x, 4).r() - pinhole.pixel_at(x, 4).r()).abs()).sum::<f32>()
+    };
+    assert!(error(&focused) < 0.5 * error(&blurred));
+  }
+
+  #[test]
+  fn implements_motion_blur() {
+    let (mut camera, mut world) = scene();
+    world.objects[0].set_motion(
+      rm::Matrix4::scaling(0.5, 0.5, 0.5),
+      rm::Matrix4::scaling(0.5, 0.5, 0.5).translate(1.0, 0.0, 0.0)
+    ).unwrap();
+    let settings = RenderSettings { samples_per_pixel: 16, pattern: SamplePattern::Grid, ..RenderSettings::default() };
+    let partial = |canvas: &crate::canvas::Canvas, y: usize| {
+      (0..9).filter(|&x| canvas.pixel_at(x, y).r() > 0.01 && canvas.pixel_at(x, y).r() < 0.99).count()
+    };
+    // with the shutter closed the sphere is frozen at its start position
+    let frozen = render(&camera, &world, &settings);
+    assert_eq!(Color::new(1.0, 1.0, 1.0), frozen.pixel_at(4, 4));
+
+    camera.shutter_close = 1.0;
+    let blurred = render(&camera, &world, &settings);
+    // the sphere smears horizontally, so its row gains more partial pixels than its column
+    let column_partial = (0..9).filter(|&y| blurred.pixel_at(4, y).r() > 0.01 && blurred.pixel_at(4, y).r() < 0.99).count();
+    assert!(partial(&blurred, 4) > partial(&frozen, 4));
+    assert!(partial(&blurred, 4) > column_partial);
+    // the far end of the path is only covered part of the time
+    assert!(blurred.pixel_at(3, 4).r() > 0.0 && blurred.pixel_at(3, 4).r() < frozen.pixel_at(4, 4).r());
+    assert_eq!(blurred, render(&camera, &world, &settings));
+  }
+
+  #[test]
+  fn implements_camera_projections() {
+    let (camera, mut world) = scene();
+    world.background = Background::Constant(Color::new(0.0, 0.0, 1.0));
+    let mut fisheye = Camera::fisheye(9, 9, PI);
+    fisheye.set_transform(camera.get_transform()).unwrap();
+    let canvas = render(&fisheye, &world, &RenderSettings::default());
+    assert_eq!(Color::new(1.0, 1.0, 1.0), canvas.pixel_at(4, 4));
+    assert_eq!(Color::new(0.0, 0.0, 1.0), canvas.pixel_at(4, 0));
+    // corners lie outside the image circle
+    assert_eq!(BLACK, canvas.pixel_at(0, 0));
+
+    // a panorama sees the sphere ahead and only background behind
+    let mut panorama = Camera::equirectangular(18, 9);
+    panorama.set_transform(camera.get_transform()).unwrap();
+    let canvas = render(&panorama, &world, &RenderSettings::default());
+    assert_eq!(Color::new(1.0, 1.0, 1.0), canvas.pixel_at(9, 4));
+    assert_eq!(Color::new(0.0, 0.0, 1.0), canvas.pixel_at(0, 4));
+
+    // parallel rays keep the sphere's size regardless of distance
+    let mut orthographic = Camera::orthographic(9, 9, 4.0);
+    orthographic.set_transform(rm::Matrix4::translation(0.0, 0.0, -20.0)).unwrap();
+    let canvas = render(&orthographic, &world, &RenderSettings::default());
+    assert_eq!(Color::new(1.0, 1.0, 1.0), canvas.pixel_at(4, 4));
+    assert_eq!(Color::new(1.0, 1.0, 1.0), canvas.pixel_at(3, 4));
+    assert_eq!(Color::new(0.0, 0.0, 1.0), canvas.pixel_at(1, 4));
+  }
+}
@@ -0,0 +1,322 @@
+extern crate rusty_math as rm;
+
+use crate::rng::Rng;
+use rm::Tuple4;
+
+use std::f32::consts::PI;
+
+/// Distribution of sample positions inside a pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplePattern {
+  /// Cell centers of a regular grid.
+  Grid,
+  /// One uniformly jittered position per grid cell.
+  Jittered,
+  /// Halton (2, 3) low-discrepancy sequence.
+  Halton
+}
+
+impl SamplePattern {
+  /// Position in [0, 1)^2 of sample `index` out of `count` within a pixel.
+  ///
+  /// `rotation` is a per-pixel Cranley-Patterson offset which decorrelates the
+  /// Halton sequence between neighbouring pixels; the other patterns ignore it.
+  pub fn sample(&self, index: usize, count: usize, rotation: (f32, f32), rng: &mut Rng) -> (f32, f32) {
+    match self {
+      SamplePattern::Grid => {
+        let (col, row, cols, rows) = grid_cell(index, count);
+        ((col as f32 + 0.5) / cols as f32, (row as f32 + 0.5) / rows as f32)
+      },
+      SamplePattern::Jittered => {
+        let (col, row, cols, rows) = grid_cell(index, count);
+        ((col as f32 + rng.next_f32()) / cols as f32, (row as f32 + rng.next_f32()) / rows as f32)
+      },
+      SamplePattern::Halton => {
+        let u = radical_inverse(2, index as u64 + 1) + rotation.0;
+        let v = radical_inverse(3, index as u64 + 1) + rotation.1;
+        (u.fract(), v.fract())
+      }
+    }
+  }
+}
+
+/// Two unit vectors completing `n` to an orthonormal basis (Duff et al. 2017).
+pub fn orthonormal_basis(n: Tuple4) -> (Tuple4, Tuple4) {
+  let sign = 1.0f32.copysign(n.z());
+  let a = -1.0 / (sign + n.z());
+  let b = n.x() * n.y() * a;
+  (
+    Tuple4::vector(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+    Tuple4::vector(b, sign + n.y() * n.y() * a, -n.y())
+  )
+}
+
+/// Transforms `local` (with z along `n`) into world space.
+pub fn to_world(local: Tuple4, n: Tuple4) -> Tuple4 {
+  let (t, b) = orthonormal_basis(n);
+  t * local.x() + b * local.y() + n * local.z()
+}
+
+/// Cosine weighted direction on the hemisphere around `n` from uniform `u`, `v`;
+/// its density is `cos(theta) / pi`.
+pub fn cosine_sample_hemisphere(n: Tuple4, u: f32, v: f32) -> Tuple4 {
+  let r = u.sqrt();
+  let phi = 2.0 * PI * v;
+  let local = Tuple4::vector(r * phi.cos(), r * phi.sin(), (1.0 - u).max(0.0).sqrt());
+  to_world(local, n)
+}
+
+/// Uniformly distributed direction on the unit sphere, density `1 / (4 pi)`.
+pub fn uniform_sample_sphere(u: f32, v: f32) -> Tuple4 {
+  let z = 1.0 - 2.0 * u;
+  let r = (1.0 - z * z).max(0.0).sqrt();
+  let phi = 2.0 * PI * v;
+  Tuple4::vector(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Direction around `axis` with density `(exponent + 1) / (2 pi) * cos(theta)^exponent`.
+pub fn power_cosine_sample(axis: Tuple4, exponent: f32, u: f32, v: f32) -> Tuple4 {
+  let cos_theta = u.powf(1.0 / (exponent + 1.0));
+  let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+  let phi = 2.0 * PI * v;
+  to_world(Tuple4::vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta), axis)
+}
+
+pub fn power_cosine_pdf(cos_theta: f32, exponent: f32) -> f32 {
+  if cos_theta <= 0.0 {
+    return 0.0;
+  }
+  (exponent + 1.0) / (2.0 * PI) * cos_theta.powf(exponent)
+}
+
+/// Piecewise constant distribution over [0, 1) proportional to `func`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution1D {
+  func: Vec<f32>,
+  cdf: Vec<f32>,
+  integral: f32
+}
+
+impl Distribution1D {
+  /// Falls back to a uniform distribution when `func` integrates to zero.
+  pub fn new(func: Vec<f32>) -> Self {
+    let n = func.len().max(1) as f32;
+    let mut cdf = vec![0.0; func.len() + 1];
+    for i in 0..func.len() {
+      cdf[i + 1] = cdf[i] + func[i].max(0.0) / n;
+    }
+    let integral = cdf[func.len()];
+    for (i, c) in cdf.iter_mut().enumerate() {
+      *c = if integral > 0.0 { *c / integral } else { i as f32 / n };
+    }
+    Self { func, cdf, integral }
+  }
+
+  pub fn count(&self) -> usize {
+    self.func.len()
+  }
+
+  pub fn integral(&self) -> f32 {
+    self.integral
+  }
+
+  /// Position in [0, 1) for uniform `u`, its density and the index of its cell.
+  pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+    let n = self.func.len();
+    let offset = self.cdf.partition_point(|&c| c <= u).clamp(1, n) - 1;
+    let width = self.cdf[offset + 1] - self.cdf[offset];
+    let du = if width > 0.0 { (u - self.cdf[offset]) / width } else { 0.0 };
+    let x = ((offset as f32 + du) / n as f32).min(1.0 - f32::EPSILON);
+    (x, self.pdf(x), offset)
+  }
+
+  pub fn pdf(&self, x: f32) -> f32 {
+    if self.integral <= 0.0 {
+      return 1.0;
+    }
+    let index = ((x * self.func.len() as f32) as usize).min(self.func.len() - 1);
+    self.func[index].max(0.0) / self.integral
+  }
+}
+
+/// Piecewise constant distribution over [0, 1)^2, sampled by first picking a
+/// row from the marginal distribution, then a column within that row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution2D {
+  rows: Vec<Distribution1D>,
+  marginal: Distribution1D
+}
+
+impl Distribution2D {
+  /// `func` holds `height` rows of `width` values.
+  pub fn new(func: &[f32], width: usize, height: usize) -> Self {
+    let rows: Vec<Distribution1D> = (0..height)
+      .map(|y| Distribution1D::new(func[y * width..(y + 1) * width].to_vec()))
+      .collect();
+    let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+    Self { rows, marginal }
+  }
+
+  /// Point `(x, y)` for uniform `u`, `v` and its density.
+  pub fn sample(&self, u: f32, v: f32) -> ((f32, f32), f32) {
+    let (y, pdf_y, row) = self.marginal.sample(v);
+    let (x, pdf_x, _) = self.rows[row].sample(u);
+    ((x, y), pdf_x * pdf_y)
+  }
+
+  pub fn pdf(&self, x: f32, y: f32) -> f32 {
+    let row = ((y * self.rows.len() as f32) as usize).min(self.rows.len() - 1);
+    self.marginal.pdf(y) * self.rows[row].pdf(x)
+  }
+}
+
+fn grid_cell(index: usize, count: usize) -> (usize, usize, usize, usize) {
+  let count = count.max(1);
+  let cols = (count as f32).sqrt().ceil() as usize;
+  let rows = count.div_ceil(cols);
+  let index = index % count;
+  (index % cols, index / cols, cols, rows)
+}
+
+pub fn radical_inverse(base: u64, mut index: u64) -> f32 {
+  let inv_base = 1.0 / base as f64;
+  let mut inv_base_n = inv_base;
+  let mut result = 0.0;
+  while index > 0 {
+    result += (index % base) as f64 * inv_base_n;
+    index /= base;
+    inv_base_n *= inv_base;
+  }
+  (result as f32).min(1.0 - f32::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    cosine_sample_hemisphere, orthonormal_basis, power_cosine_pdf, power_cosine_sample, radical_inverse, uniform_sample_sphere,
+    Distribution1D, Distribution2D, Rng, SamplePattern, Tuple4
+  };
+
+  #[test]
+  fn implements_grid() {
+    let mut rng = Rng::new(0);
+    let samples: Vec<(f32, f32)> = (0..4).map(|i| SamplePattern::Grid.sample(i, 4, (0.0, 0.0), &mut rng)).collect();
+    assert_eq!(vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)], samples);
+    assert_eq!((0.5, 0.5), SamplePattern::Grid.sample(0, 1, (0.0, 0.0), &mut rng));
+  }
+
+  #[test]
+  fn implements_jittered() {
+    let mut rng = Rng::new(0);
+    for i in 0..9 {
+      let (u, v) = SamplePattern::Jittered.sample(i, 9, (0.0, 0.0), &mut rng);
+      let (col, row) = ((i % 3) as f32, (i / 3) as f32);
+      assert!(u >= col / 3.0 && u < (col + 1.0) / 3.0);
+      assert!(v >= row / 3.0 && v < (row + 1.0) / 3.0);
+    }
+  }
+
+  #[test]
+  fn implements_radical_inverse() {
+    assert_eq!(0.5, radical_inverse(2, 1));
+    assert_eq!(0.25, radical_inverse(2, 2));
+    assert_eq!(0.75, radical_inverse(2, 3));
+    assert!((radical_inverse(3, 1) - 1.0 / 3.0).abs() < 1e-6);
+    assert!((radical_inverse(3, 4) - 4.0 / 9.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn implements_halton() {
+    let mut rng = Rng::new(0);
+    assert_eq!((0.5, 1.0 / 3.0), SamplePattern::Halton.sample(0, 4, (0.0, 0.0), &mut rng));
+    let (u, v) = SamplePattern::Halton.sample(0, 4, (0.75, 0.75), &mut rng);
+    assert!((u - 0.25).abs() < 1e-6 && (v - 1.0 / 12.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn implements_orthonormal_basis() {
+    for n in [Tuple4::vector(0.0, 0.0, 1.0), Tuple4::vector(0.0, 0.0, -1.0), Tuple4::vector(1.0, 2.0, -3.0).normalized()] {
+      let (t, b) = orthonormal_basis(n);
+      assert!(Tuple4::dot(t, n).abs() < 1e-6);
+      assert!(Tuple4::dot(b, n).abs() < 1e-6);
+      assert!(Tuple4::dot(t, b).abs() < 1e-6);
+      assert!((t.length() - 1.0).abs() < 1e-6 && (b.length() - 1.0).abs() < 1e-6);
+    }
+  }
+
+  #[test]
+  fn implements_cosine_sample_hemisphere() {
+    let n = Tuple4::vector(0.0, 1.0, 0.0);
+    let mut rng = Rng::new(5);
+    let mut mean_cos = 0.0;
+    for _ in 0..4000 {
+      let d = cosine_sample_hemisphere(n, rng.next_f32(), rng.next_f32());
+      assert!((d.length() - 1.0).abs() < 1e-5);
+      assert!(Tuple4::dot(d, n) >= 0.0);
+      mean_cos += Tuple4::dot(d, n) / 4000.0;
+    }
+    // E[cos] under a cos/pi density is 2/3
+    assert!((mean_cos - 2.0 / 3.0).abs() < 0.02);
+  }
+
+  #[test]
+  fn implements_power_cosine_sample() {
+    let axis = Tuple4::vector(1.0, 1.0, 0.0).normalized();
+    let mut rng = Rng::new(6);
+    let mut mean_cos = 0.0;
+    for _ in 0..4000 {
+      let d = power_cosine_sample(axis, 20.0, rng.next_f32(), rng.next_f32());
+      assert!((d.length() - 1.0).abs() < 1e-5);
+      mean_cos += Tuple4::dot(d, axis) / 4000.0;
+    }
+    // E[cos] under a cos^n density is (n + 1) / (n + 2)
+    assert!((mean_cos - 21.0 / 22.0).abs() < 0.01);
+    assert_eq!(0.0, power_cosine_pdf(-0.1, 20.0));
+    assert!((power_cosine_pdf(1.0, 1.0) - 1.0 / std::f32::consts::PI).abs() < 1e-6);
+  }
+
+  #[test]
+  fn implements_uniform_sample_sphere() {
+    let mut rng = Rng::new(7);
+    let mut mean = Tuple4::vector(0.0, 0.0, 0.0);
+    for _ in 0..4000 {
+      let d = uniform_sample_sphere(rng.next_f32(), rng.next_f32());
+      assert!((d.length() - 1.0).abs() < 1e-5);
+      mean = mean + d * (1.0 / 4000.0);
+    }
+    assert!(mean.length() < 0.05);
+  }
+
+  #[test]
+  fn implements_distribution_1d() {
+    let d = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+    assert_eq!(2.0, d.integral());
+    assert_eq!((0.0, 0.5, 0), d.sample(0.0));
+    assert_eq!((0.25, 1.5, 1), d.sample(0.125));
+    let (x, _, _) = d.sample(0.3125);
+    assert!((x - 0.375).abs() < 1e-6);
+    // the empty cell is never picked
+    assert_eq!((0.75, 2.0, 3), d.sample(0.5));
+    assert_eq!(0.0, d.pdf(0.6));
+
+    let uniform = Distribution1D::new(vec![0.0, 0.0]);
+    assert_eq!((0.25, 1.0, 0), uniform.sample(0.25));
+  }
+
+  #[test]
+  fn implements_distribution_2d() {
+    let d = Distribution2D::new(&[1.0, 0.0, 0.0, 3.0], 2, 2);
+    let mut rng = Rng::new(8);
+    let mut lower_right = 0;
+    for _ in 0..1000 {
+      let ((x, y), pdf) = d.sample(rng.next_f32(), rng.next_f32());
+      assert_eq!(pdf, d.pdf(x, y));
+      assert!(pdf > 0.0);
+      if x >= 0.5 && y >= 0.5 {
+        lower_right += 1;
+      }
+    }
+    assert!((lower_right as f32 / 1000.0 - 0.75).abs() < 0.05);
+    assert_eq!(3.0, d.pdf(0.75, 0.75));
+  }
+}
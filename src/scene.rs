@@ -0,0 +1,493 @@
+extern crate rusty_math as rm;
+
+use crate::camera::{view_transform, Camera, MAX_PIXELS};
+use crate::color::Color;
+use crate::errors::SceneError;
+use crate::light::Light;
+use crate::material::Material;
+use crate::point_light::PointLight;
+use crate::shading::ShadingModel;
+use crate::sphere::Sphere;
+use crate::world::World;
+use crate::yaml::{self, Node};
+use rm::{Matrix4, Tuple4};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs;
+
+/// Camera and world described by a scene file.
+///
+/// A scene file is a list of `add` and `define` entries:
+///
+/// ```yaml
+/// - add: camera
+///   width: 320
+///   height: 240
+///   field-of-view: 1.0
+///   from: [0, 1.5, -5]
+///   to: [0, 1, 0]
+///   up: [0, 1, 0]
+///
+/// - add: light
+///   at: [-10, 10, -10]
+///   intensity: [1, 1, 1]
+///
+/// - define: matte
+///   value:
+///     color: [1, 0.9, 0.9]
+///     specular: 0
+///
+/// - define: red-matte
+///   extend: matte
+///   value:
+///     color: [0.8, 0.1, 0.1]
+///
+/// - add: sphere
+///   material: red-matte
+///   transform:
+///     - [scale, 0.5, 0.5, 0.5]
+///     - [translate, 1.5, 0.5, -0.5]
+/// ```
+///
+/// Definitions hold either a material (a mapping) or a transform list, and can
+/// be referred to by name afterwards. Transform lists apply their steps in
+/// order: `translate`, `scale`, `rotate-x`, `rotate-y`, `rotate-z` (radians)
+/// and `shear`.
+pub struct Scene {
+  pub camera: Camera,
+  pub world: World
+}
+
+impl Scene {
+  pub fn parse(source: &str) -> Result<Self, SceneError> {
+    let root = yaml::parse(source)?;
+    let entries = root.as_list().ok_or_else(|| root.error("expected a list of 'add' and 'define' entries"))?;
+    let mut definitions = HashMap::new();
+    let mut camera = None;
+    let mut objects = Vec::new();
+    let mut lights: Vec<Box<dyn Light>> = Vec::new();
+
+    for entry in entries {
+      if let Some(name) = entry.get("define") {
+        let (key, definition) = define(entry, &definitions)?;
+        if definitions.contains_key(&key) {
+          return Err(name.error(format!("'{}' is already defined", key)));
+        }
+        definitions.insert(key, definition);
+        continue;
+      }
+      let kind = entry.get("add").ok_or_else(|| entry.error("expected 'add' or 'define'"))?;
+      match scalar(kind)? {
+        "camera" => {
+          if camera.is_some() {
+            return Err(kind.error("only one camera is allowed"));
+          }
+          camera = Some(parse_camera(entry)?);
+        },
+        "light" => lights.push(Box::new(parse_light(entry)?)),
+        "sphere" => objects.push(parse_sphere(entry, &definitions)?),
+        other => return Err(kind.error(format!("unknown object '{}'", other)))
+      }
+    }
+
+    let camera = camera.ok_or_else(|| root.error("scene has no camera"))?;
+    Ok(Self { camera, world: World::new(objects, lights) })
+  }
+
+  pub fn load(file: &str) -> Result<Self, SceneError> {
+    Self::parse(&fs::read_to_string(file)?)
+  }
+}
+
+enum Definition {
+  Material(Material),
+  Transform(Matrix4)
+}
+
+fn define(entry: &Node, definitions: &HashMap<String, Definition>) -> Result<(String, Definition), SceneError> {
+  check_keys(entry, "definition", &["define", "extend", "value"])?;
+  let name = scalar(entry.get("define").unwrap())?.to_string();
+  let value = required(entry, "value", "definition")?;
+  let base = match entry.get("extend") {
+    Some(node) => Some((node, lookup(node, definitions)?)),
+    None => None
+  };
+  let definition = if value.as_list().is_some() {
+    let start = match base {
+      Some((_, Definition::Transform(m))) => *m,
+      Some((node, _)) => return Err(node.error("a transform can only extend a transform")),
+      None => Matrix4::identity()
+    };
+    Definition::Transform(transform(value, definitions, start)?)
+  } else {
+    let start = match base {
+      Some((_, Definition::Material(m))) => *m,
+      Some((node, _)) => return Err(node.error("a material can only extend a material")),
+      None => Material::default()
+    };
+    Definition::Material(material(value, start)?)
+  };
+  Ok((name, definition))
+}
+
+/// The camera's field of view, strictly between 0 and `max` radians.
+fn field_of_view(entry: &Node, max: f32) -> Result<f32, SceneError> {
+  let node = required(entry, "field-of-view", "camera")?;
+  let angle = number(node)?;
+  if angle <= 0.0 || angle >= max {
+    return Err(node.error(format!("camera 'field-of-view' must be between 0 and {:.4} radians", max)));
+  }
+  Ok(angle)
+}
+
+fn lookup<'a>(node: &Node, definitions: &'a HashMap<String, Definition>) -> Result<&'a Definition, SceneError> {
+  let name = scalar(node)?;
+  definitions.get(name).ok_or_else(|| node.error(format!("'{}' is not defined", name)))
+}
+
+fn parse_camera(entry: &Node) -> Result<Camera, SceneError> {
+  check_keys(entry, "camera", &[
+    "add", "width", "height", "field-of-view", "from", "to", "up", "projection", "view-width", "aperture",
+    "focal-distance"
+  ])?;
+  let width = size(required(entry, "width", "camera")?)?;
+  let height = size(required(entry, "height", "camera")?)?;
+  if width.checked_mul(height).filter(|pixels| *pixels <= MAX_PIXELS).is_none() {
+    return Err(entry.error(format!("a {}x{} image has more than {} pixels", width, height, MAX_PIXELS)));
+  }
+  let projection = match entry.get("projection") {
+    Some(node) => scalar(node).map(|name| (node, name))?,
+    None => (entry, "perspective")
+  };
+  let mut camera = match projection {
+    (_, "perspective") => Camera::new(width, height, field_of_view(entry, PI)?),
+    (_, "orthographic") => Camera::orthographic(width, height, number(required(entry, "view-width", "camera")?)?),
+    // a fisheye can see behind itself
+    (_, "fisheye") => Camera::fisheye(width, height, field_of_view(entry, 2.0 * PI)?),
+    (_, "equirectangular") => Camera::equirectangular(width, height),
+    (node, other) => return Err(node.error(format!("unknown projection '{}'", other)))
+  };
+  if let Some(node) = entry.get("aperture") {
+    camera.aperture = number(node)?;
+    if camera.aperture < 0.0 {
+      return Err(node.error("camera 'aperture' must not be negative"));
+    }
+  }
+  if let Some(node) = entry.get("focal-distance") {
+    camera.focal_distance = number(node)?;
+    if camera.focal_distance <= 0.0 {
+      return Err(node.error("camera 'focal-distance' must be positive"));
+    }
+  }
+
+  let from = point(required(entry, "from", "camera")?)?;
+  let to = point(required(entry, "to", "camera")?)?;
+  let up_node = required(entry, "up", "camera")?;
+  let up = vector(up_node)?;
+  let forward = to - from;
+  if forward.length() == 0.0 {
+    return Err(entry.get("to").unwrap().error("camera 'to' must differ from 'from'"));
+  }
+  if up.length() == 0.0 || Tuple4::cross(forward.normalized(), up.normalized()).unwrap().length() < 1e-6 {
+    return Err(up_node.error("camera 'up' must not be parallel to the view direction"));
+  }
+  camera.set_transform(view_transform(from, to, up)).map_err(|_| entry.error("camera view is not invertible"))?;
+  Ok(camera)
+}
+
+fn parse_light(entry: &Node) -> Result<PointLight, SceneError> {
+  check_keys(entry, "light", &["add", "at", "intensity"])?;
+  let position = point(required(entry, "at", "light")?)?;
+  let intensity = color(required(entry, "intensity", "light")?)?;
+  Ok(PointLight::new(position, intensity))
+}
+
+fn parse_sphere(entry: &Node, definitions: &HashMap<String, Definition>) -> Result<Sphere, SceneError> {
+  check_keys(entry, "sphere", &["add", "material", "transform"])?;
+  let mut sphere = Sphere::unit();
+  if let Some(node) = entry.get("material") {
+    let mat = match node.as_scalar() {
+      Some(_) => match lookup(node, definitions)? {
+        Definition::Material(m) => *m,
+        Definition::Transform(_) => return Err(node.error(format!("'{}' is not a material", scalar(node)?)))
+      },
+      None => material(node, Material::default())?
+    };
+    sphere.set_material(mat);
+  }
+  if let Some(node) = entry.get("transform") {
+    let t = transform(node, definitions, Matrix4::identity())?;
+    if t.inverse().is_err() {
+      return Err(node.error("transform is not invertible"));
+    }
+    sphere.set_transform(t);
+  }
+  Ok(sphere)
+}
+
+/// Applies the attributes of mapping `node` on top of `base`.
+fn material(node: &Node, base: Material) -> Result<Material, SceneError> {
+  let entries = node.as_map().ok_or_else(|| node.error("expected a material or a material name"))?;
+  let mut mat = base;
+  // the model goes first so that its parameters can follow in any order
+  if let Some(model) = node.get("model") {
+    mat.model = match (scalar(model)?, mat.model) {
+      ("phong", _) => ShadingModel::Phong,
+      ("blinn-phong", _) => ShadingModel::BlinnPhong,
+      ("ggx", ggx @ ShadingModel::Ggx { .. }) => ggx,
+      ("ggx", _) => ShadingModel::Ggx { roughness: 0.5, metallic: 0.0 },
+      (other, _) => return Err(model.error(format!("unknown shading model '{}'", other)))
+    };
+  }
+  for (key, value) in entries {
+    match (scalar(key)?, &mut mat.model) {
+      ("model", _) => {},
+      ("color", _) => mat.color = color(value)?,
+      ("emission", _) => mat.emission = color(value)?,
+      ("ambient", _) => mat.ambient = number(value)?,
+      ("diffuse", _) => mat.diffuse = number(value)?,
+      ("specular", _) => mat.specular = number(value)?,
+      ("shininess", _) => mat.shininess = number(value)?,
+      ("roughness", ShadingModel::Ggx { roughness, .. }) => *roughness = number(value)?,
+      ("metallic", ShadingModel::Ggx { metallic, .. }) => *metallic = number(value)?,
+      (name @ ("roughness" | "metallic"), _) => return Err(key.error(format!("'{}' needs 'model: ggx'", name))),
+      (other, _) => return Err(key.error(format!("unknown material attribute '{}'", other)))
+    }
+  }
+  Ok(mat)
+}
+
+/// Applies the steps of transform list `node` after `base`.
+fn transform(node: &Node, definitions: &HashMap<String, Definition>, base: Matrix4) -> Result<Matrix4, SceneError> {
+  let steps = node.as_list().ok_or_else(|| node.error("expected a list of transforms"))?;
+  let mut m = base;
+  for step in steps {
+    if step.as_scalar().is_some() {
+      m = match lookup(step, definitions)? {
+        Definition::Transform(t) => *t * m,
+        Definition::Material(_) => return Err(step.error(format!("'{}' is not a transform", scalar(step)?)))
+      };
+      continue;
+    }
+    let items = step.as_list().ok_or_else(|| step.error("expected [operation, arguments...]"))?;
+    let (operation, arguments) = match items.split_first() {
+      Some((operation, arguments)) => (operation, arguments),
+      None => return Err(step.error("expected [operation, arguments...]"))
+    };
+    let name = scalar(operation)?;
+    let expected = match name {
+      "translate" | "scale" => 3,
+      "rotate-x" | "rotate-y" | "rotate-z" => 1,
+      "shear" => 6,
+      other => return Err(operation.error(format!("unknown transform '{}'", other)))
+    };
+    if arguments.len() != expected {
+      return Err(step.error(format!("'{}' takes {} arguments, found {}", name, expected, arguments.len())));
+    }
+    let a = arguments.iter().map(number).collect::<Result<Vec<f32>, _>>()?;
+    m = match name {
+      "translate" => m.translate(a[0], a[1], a[2]),
+      "scale" => m.scale(a[0], a[1], a[2]),
+      "rotate-x" => m.rotate_x(a[0]),
+      "rotate-y" => m.rotate_y(a[0]),
+      "rotate-z" => m.rotate_z(a[0]),
+      _ => m.shear(a[0], a[1], a[2], a[3], a[4], a[5])
+    };
+  }
+  Ok(m)
+}
+
+fn check_keys(entry: &Node, kind: &str, allowed: &[&str]) -> Result<(), SceneError> {
+  for (key, _) in entry.as_map().ok_or_else(|| entry.error(format!("expected {} attributes", kind)))? {
+    if !allowed.contains(&scalar(key)?) {
+      return Err(key.error(format!("unknown {} attribute '{}'", kind, scalar(key)?)));
+    }
+  }
+  Ok(())
+}
+
+fn required<'a>(entry: &'a Node, key: &str, kind: &str) -> Result<&'a Node, SceneError> {
+  entry.get(key).ok_or_else(|| entry.error(format!("{} is missing '{}'", kind, key)))
+}
+
+fn scalar(node: &Node) -> Result<&str, SceneError> {
+  node.as_scalar().ok_or_else(|| node.error("expected a single value"))
+}
+
+fn number(node: &Node) -> Result<f32, SceneError> {
+  scalar(node)?
+    .parse::<f32>()
+    .ok()
+    .filter(|v| v.is_finite())
+    .ok_or_else(|| node.error("expected a number"))
+}
+
+fn size(node: &Node) -> Result<usize, SceneError> {
+  scalar(node)?.parse::<usize>().ok().filter(|v| *v > 0).ok_or_else(|| node.error("expected a positive integer"))
+}
+
+fn triple(node: &Node) -> Result<(f32, f32, f32), SceneError> {
+  match node.as_list() {
+    Some([x, y, z]) => Ok((number(x)?, number(y)?, number(z)?)),
+    _ => Err(node.error("expected [x, y, z]"))
+  }
+}
+
+fn point(node: &Node) -> Result<Tuple4, SceneError> {
+  triple(node).map(|(x, y, z)| Tuple4::point(x, y, z))
+}
+
+fn vector(node: &Node) -> Result<Tuple4, SceneError> {
+  triple(node).map(|(x, y, z)| Tuple4::vector(x, y, z))
+}
+
+fn color(node: &Node) -> Result<Color, SceneError> {
+  match node.as_list() {
+    Some([r, g, b]) => Ok(Color::new(number(r)?, number(g)?, number(b)?)),
+    _ => Err(node.error("expected [r, g, b]"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Color, Material, Matrix4, Scene, SceneError, ShadingModel, Tuple4};
+  use crate::camera::{view_transform, Projection};
+
+  fn error(source: &str) -> (usize, usize, String) {
+    match Scene::parse(source) {
+      Err(SceneError::Parse { line, column, message }) => (line, column, message),
+      Err(e) => panic!("unexpected error {}", e),
+      Ok(_) => panic!("expected an error")
+    }
+  }
+
+  const CAMERA: &str = "\
+- add: camera
+  width: 40
+  height: 20
+  field-of-view: 1.2
+  from: [0, 1, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+";
+
+  #[test]
+  fn implements_camera_and_lights() {
+    let source = format!("{}\n- add: light\n  at: [-10, 10, -10]\n  intensity: [1, 0.5, 0.25]\n", CAMERA);
+    let scene = Scene::parse(&source).unwrap();
+    assert_eq!((40, 20), (scene.camera.hsize, scene.camera.vsize));
+    assert_eq!(1.2, scene.camera.field_of_view);
+    assert_eq!(Projection::Perspective, scene.camera.projection);
+    let expected = view_transform(Tuple4::point(0.0, 1.0, -5.0), Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 1.0, 0.0));
+    assert_eq!(expected, scene.camera.get_transform());
+    assert_eq!(1, scene.world.lights.len());
+    assert_eq!(Color::new(1.0, 0.5, 0.25), scene.world.lights[0].intensity());
+
+    let source = CAMERA.replace("field-of-view: 1.2", "projection: orthographic\n  view-width: 4\n  aperture: 0.1");
+    let camera = Scene::parse(&source).unwrap().camera;
+    assert_eq!(Projection::Orthographic { view_width: 4.0 }, camera.projection);
+    assert_eq!(0.1, camera.aperture);
+  }
+
+  #[test]
+  fn implements_definitions() {
+    let source = format!("{}
+- define: white
+  value:
+    color: [1, 1, 1]
+    diffuse: 0.7
+    specular: 0
+- define: red
+  extend: white
+  value:
+    color: [1, 0, 0]
+- define: shrink
+  value:
+    - [scale, 0.5, 0.5, 0.5]
+- define: shrink-and-lift
+  extend: shrink
+  value:
+    - [translate, 0, 1, 0]
+- add: sphere
+  material: red
+  transform:
+    - shrink-and-lift
+    - [rotate-y, 1.5]
+- add: sphere
+  material:
+    model: ggx
+    roughness: 0.2
+", CAMERA);
+    let scene = Scene::parse(&source).unwrap();
+    let spheres = &scene.world.objects;
+    assert_eq!(2, spheres.len());
+    let red = Material { color: Color::new(1.0, 0.0, 0.0), diffuse: 0.7, specular: 0.0, ..Material::default() };
+    assert_eq!(red, spheres[0].get_material());
+    assert_eq!(Matrix4::scaling(0.5, 0.5, 0.5).translate(0.0, 1.0, 0.0).rotate_y(1.5), spheres[0].get_transform());
+    assert_eq!(ShadingModel::Ggx { roughness: 0.2, metallic: 0.0 }, spheres[1].get_material().model);
+    assert_eq!(Matrix4::identity(), spheres[1].get_transform());
+  }
+
+  #[test]
+  fn reports_error_positions() {
+    let sphere = |body: &str| format!("{}- add: sphere\n{}", CAMERA, body);
+    assert_eq!((9, 3, "unknown sphere attribute 'colour'".into()), error(&sphere("  colour: [1, 0, 0]\n")));
+    assert_eq!(
+      (10, 22, "expected a number".into()),
+      error(&sphere("  transform:\n    - [translate, 1, x, 0]\n"))
+    );
+    assert_eq!(
+      (10, 7, "'scale' takes 3 arguments, found 2".into()),
+      error(&sphere("  transform:\n    - [scale, 1, 2]\n"))
+    );
+    assert_eq!((9, 13, "'steel' is not defined".into()), error(&sphere("  material: steel\n")));
+    assert_eq!(
+      (10, 5, "'metallic' needs 'model: ggx'".into()),
+      error(&sphere("  material:\n    metallic: 1\n"))
+    );
+    assert_eq!((9, 14, "transform is not invertible".into()), error(&sphere("  transform: [[scale, 0, 1, 1]]\n")));
+    assert_eq!((1, 3, "camera is missing 'width'".into()), error(&CAMERA.replace("  width: 40\n", "")));
+    assert_eq!(
+      (1, 3, "a 100000000000x20 image has more than 67108864 pixels".into()),
+      error(&CAMERA.replace("width: 40", "width: 100000000000"))
+    );
+    assert_eq!(
+      (1, 3, "a 18446744073709551615x20 image has more than 67108864 pixels".into()),
+      error(&CAMERA.replace("width: 40", "width: 18446744073709551615"))
+    );
+    let camera_error = |from: &str, to: &str| error(&CAMERA.replace(from, to));
+    assert_eq!(
+      (4, 18, "camera 'field-of-view' must be between 0 and 3.1416 radians".into()),
+      camera_error("field-of-view: 1.2", "field-of-view: 0")
+    );
+    assert_eq!(
+      (4, 18, "camera 'field-of-view' must be between 0 and 3.1416 radians".into()),
+      camera_error("field-of-view: 1.2", "field-of-view: 3.5")
+    );
+    assert_eq!(
+      (5, 18, "camera 'field-of-view' must be between 0 and 6.2832 radians".into()),
+      camera_error("field-of-view: 1.2", "projection: fisheye\n  field-of-view: -1")
+    );
+    assert!(Scene::parse(&CAMERA.replace("field-of-view: 1.2", "projection: fisheye\n  field-of-view: 3.5")).is_ok());
+    assert_eq!(
+      (5, 13, "camera 'aperture' must not be negative".into()),
+      camera_error("field-of-view: 1.2", "field-of-view: 1.2\n  aperture: -0.1")
+    );
+    assert_eq!(
+      (5, 19, "camera 'focal-distance' must be positive".into()),
+      camera_error("field-of-view: 1.2", "field-of-view: 1.2\n  focal-distance: 0")
+    );
+    assert_eq!((7, 7, "camera 'up' must not be parallel to the view direction".into()), error(&CAMERA.replace("up: [0, 1, 0]", "up: [0, 1, -5]")));
+    assert_eq!((1, 8, "unknown object 'cube'".into()), error("- add: cube\n"));
+    assert_eq!((1, 1, "scene has no camera".into()), error("- add: light\n  at: [0, 0, 0]\n  intensity: [1, 1, 1]\n"));
+    assert_eq!((1, 3, "expected 'add' or 'define'".into()), error("- bogus: 1\n"));
+  }
+
+  #[test]
+  fn loads_example_scene() {
+    let scene = Scene::parse(include_str!("../scenes/spheres.yaml")).unwrap();
+    assert!(!scene.world.objects.is_empty());
+    assert!(!scene.world.lights.is_empty());
+  }
+}
@@ -0,0 +1,318 @@
+extern crate rusty_math as rm;
+
+use crate::color::{Color, BLACK};
+use crate::material::Material;
+use crate::rng::Rng;
+use crate::sampler::{cosine_sample_hemisphere, power_cosine_pdf, power_cosine_sample, to_world};
+use rm::Tuple4;
+
+use std::f32::consts::PI;
+
+/// Reflection model used to shade a `Material`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadingModel {
+  /// Classic Phong, using the material's `diffuse`, `specular` and `shininess`.
+  Phong,
+  /// Phong with the specular lobe around the half vector.
+  BlinnPhong,
+  /// Cook-Torrance microfacet BRDF with a GGX distribution, Smith geometry term
+  /// and Schlick Fresnel. The material's `color` is the base color; `diffuse`,
+  /// `specular` and `shininess` are ignored.
+  Ggx { roughness: f32, metallic: f32 }
+}
+
+impl ShadingModel {
+  /// Light reflected towards `eye_v` from a light sample of `intensity`
+  /// arriving along `light_v`. Intensities follow the renderer's convention: a
+  /// white Lambertian surface facing a light reflects the light's intensity.
+  pub fn reflect(&self, mat: &Material, light_v: Tuple4, eye_v: Tuple4, normal_v: Tuple4, intensity: Color) -> Color {
+    let light_dot_normal = Tuple4::dot(light_v, normal_v);
+    if light_dot_normal < 0.0 {
+      return BLACK;
+    }
+    match *self {
+      ShadingModel::Phong => {
+        let diffuse = mat.color * intensity * mat.diffuse * light_dot_normal;
+        let reflect_dot_eye = Tuple4::dot(Tuple4::reflect(-light_v, normal_v), eye_v);
+        if reflect_dot_eye > 0.0 {
+          diffuse + intensity * mat.specular * reflect_dot_eye.powf(mat.shininess)
+        } else {
+          diffuse
+        }
+      },
+      ShadingModel::BlinnPhong => {
+        let diffuse = mat.color * intensity * mat.diffuse * light_dot_normal;
+        let half_v = (light_v + eye_v).normalized();
+        let normal_dot_half = Tuple4::dot(normal_v, half_v);
+        if normal_dot_half > 0.0 && Tuple4::dot(normal_v, eye_v) > 0.0 {
+          diffuse + intensity * mat.specular * normal_dot_half.powf(mat.shininess)
+        } else {
+          diffuse
+        }
+      },
+      ShadingModel::Ggx { roughness, metallic } => {
+        let eye_dot_normal = Tuple4::dot(eye_v, normal_v);
+        if eye_dot_normal <= 0.0 {
+          return BLACK;
+        }
+        let half_v = (light_v + eye_v).normalized();
+        let alpha = (roughness * roughness).max(0.001);
+        let d = ggx_distribution(Tuple4::dot(normal_v, half_v), alpha);
+        let g = smith_g1(light_dot_normal, alpha) * smith_g1(eye_dot_normal, alpha);
+        let f0 = Color::new(0.04, 0.04, 0.04) * (1.0 - metallic) + mat.color * metallic;
+        let f = schlick_fresnel(f0, Tuple4::dot(eye_v, half_v));
+        let specular = f * (d * g / (4.0 * light_dot_normal * eye_dot_normal));
+        let kd = (Color::new(1.0, 1.0, 1.0) - f) * (1.0 - metallic);
+        // the Lambertian 1/pi cancels against the renderer's intensity convention
+        (kd * mat.color + specular * PI) * intensity * light_dot_normal
+      }
+    }
+  }
+
+  /// BRDF value for light arriving along `wi` and leaving along `wo`, consistent
+  /// with `reflect`: `reflect(..) == pi * brdf(..) * intensity * cos(theta_i)`.
+  pub fn brdf(&self, mat: &Material, wi: Tuple4, wo: Tuple4, normal_v: Tuple4) -> Color {
+    let cos_i = Tuple4::dot(wi, normal_v);
+    if cos_i <= 0.0 {
+      return BLACK;
+    }
+    self.reflect(mat, wi, wo, normal_v, Color::new(1.0, 1.0, 1.0)) * (1.0 / (PI * cos_i))
+  }
+
+  /// Incoming direction for light leaving along `wo`, importance sampled from
+  /// a mixture of a cosine weighted diffuse lobe and the model's specular lobe.
+  /// Its solid angle density is given by `pdf`.
+  pub fn sample(&self, mat: &Material, wo: Tuple4, normal_v: Tuple4, rng: &mut Rng) -> Option<Tuple4> {
+    let (choice, u, v) = (rng.next_f32(), rng.next_f32(), rng.next_f32());
+    let wi = if choice >= self.specular_probability(mat) {
+      cosine_sample_hemisphere(normal_v, u, v)
+    } else {
+      match *self {
+        ShadingModel::Phong => power_cosine_sample(Tuple4::reflect(-wo, normal_v), mat.shininess, u, v),
+        ShadingModel::BlinnPhong => {
+          Tuple4::reflect(-wo, power_cosine_sample(normal_v, mat.shininess, u, v))
+        },
+        ShadingModel::Ggx { roughness, .. } => {
+          let alpha = (roughness * roughness).max(0.001);
+          Tuple4::reflect(-wo, ggx_sample_half(normal_v, alpha, u, v))
+        }
+      }
+    };
+    if Tuple4::dot(wi, normal_v) > 0.0 { Some(wi) } else { None }
+  }
+
+  /// Solid angle density with which `sample` returns `wi`.
+  pub fn pdf(&self, mat: &Material, wo: Tuple4, wi: Tuple4, normal_v: Tuple4) -> f32 {
+    let cos_i = Tuple4::dot(wi, normal_v);
+    if cos_i <= 0.0 {
+      return 0.0;
+    }
+    let p_specular = self.specular_probability(mat);
+    let diffuse = cos_i / PI;
+    let half_v = (wi + wo).normalized();
+    let wo_dot_half = Tuple4::dot(wo, half_v);
+    let specular = match *self {
+      ShadingModel::Phong => power_cosine_pdf(Tuple4::dot(wi, Tuple4::reflect(-wo, normal_v)), mat.shininess),
+      _ if wo_dot_half <= 0.0 => 0.0,
+      ShadingModel::BlinnPhong => power_cosine_pdf(Tuple4::dot(normal_v, half_v), mat.shininess) / (4.0 * wo_dot_half),
+      ShadingModel::Ggx { roughness, .. } => {
+        let alpha = (roughness * roughness).max(0.001);
+        let normal_dot_half = Tuple4::dot(normal_v, half_v);
+        ggx_distribution(normal_dot_half, alpha) * normal_dot_half / (4.0 * wo_dot_half)
+      }
+    };
+    (1.0 - p_specular) * diffuse + p_specular * specular
+  }
+
+  /// Probability of sampling the specular lobe rather than the diffuse one.
+  fn specular_probability(&self, mat: &Material) -> f32 {
+    match *self {
+      ShadingModel::Phong | ShadingModel::BlinnPhong => {
+        let diffuse = mat.diffuse * mat.color.luminance().max(0.0);
+        if mat.specular + diffuse > 0.0 { mat.specular / (mat.specular + diffuse) } else { 0.0 }
+      },
+      ShadingModel::Ggx { metallic, .. } => 0.5 + 0.5 * metallic.clamp(0.0, 1.0)
+    }
+  }
+}
+
+/// Half vector around `normal_v` distributed as `D(h) * cos(theta_h)`.
+fn ggx_sample_half(normal_v: Tuple4, alpha: f32, u: f32, v: f32) -> Tuple4 {
+  let cos2_theta = (1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u);
+  let cos_theta = cos2_theta.max(0.0).sqrt();
+  let sin_theta = (1.0 - cos2_theta).max(0.0).sqrt();
+  let phi = 2.0 * PI * v;
+  to_world(Tuple4::vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta), normal_v)
+}
+
+/// GGX / Trowbridge-Reitz normal distribution.
+pub fn ggx_distribution(normal_dot_half: f32, alpha: f32) -> f32 {
+  if normal_dot_half <= 0.0 {
+    return 0.0;
+  }
+  let alpha2 = alpha * alpha;
+  let denominator = normal_dot_half * normal_dot_half * (alpha2 - 1.0) + 1.0;
+  alpha2 / (PI * denominator * denominator)
+}
+
+/// Smith masking term for a single direction under a GGX distribution.
+pub fn smith_g1(cos_theta: f32, alpha: f32) -> f32 {
+  if cos_theta <= 0.0 {
+    return 0.0;
+  }
+  let alpha2 = alpha * alpha;
+  2.0 * cos_theta / (cos_theta + (alpha2 + (1.0 - alpha2) * cos_theta * cos_theta).sqrt())
+}
+
+pub fn schlick_fresnel(f0: Color, cos_theta: f32) -> Color {
+  let factor = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+  f0 + (Color::new(1.0, 1.0, 1.0) - f0) * factor
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{ggx_distribution, schlick_fresnel, smith_g1, Color, Material, Rng, ShadingModel, Tuple4};
+  use crate::test_utils::cmp_color;
+  use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+  fn white() -> Color {
+    Color::new(1.0, 1.0, 1.0)
+  }
+
+  #[test]
+  fn implements_blinn_phong() {
+    let m = Material::default();
+    let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let light_v = Tuple4::vector(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
+    let eye_v = Tuple4::vector(0.0, -FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
+    let color = ShadingModel::BlinnPhong.reflect(&m, light_v, eye_v, normal_v, white());
+    assert!(cmp_color(Color::new(1.5364, 1.5364, 1.5364), color), "{}", color);
+
+    // highlight is broader than Phong's for the same shininess
+    let eye_v = Tuple4::vector(0.0, -0.1, -1.0).normalized();
+    let phong = ShadingModel::Phong.reflect(&m, light_v, eye_v, normal_v, white());
+    let blinn = ShadingModel::BlinnPhong.reflect(&m, light_v, eye_v, normal_v, white());
+    assert!(blinn.r() > phong.r());
+  }
+
+  #[test]
+  fn implements_light_behind_surface() {
+    let m = Material::default();
+    let normal_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let eye_v = Tuple4::vector(0.0, 0.0, -1.0);
+    let light_v = Tuple4::vector(0.0, 0.0, 1.0);
+    for model in [ShadingModel::Phong, ShadingModel::BlinnPhong, ShadingModel::Ggx { roughness: 0.5, metallic: 0.0 }] {
+      assert_eq!(Color::new(0.0, 0.0, 0.0), model.reflect(&m, light_v, eye_v, normal_v, white()));
+    }
+  }
+
+  #[test]
+  fn implements_brdf() {
+    // Lambertian albedo 0.5 under Phong without specular
+    let m = Material { color: Color::new(0.5, 0.5, 0.5), diffuse: 1.0, specular: 0.0, ..Material::default() };
+    let normal_v = Tuple4::vector(0.0, 1.0, 0.0);
+    let wi = Tuple4::vector(0.0, 1.0, 1.0).normalized();
+    let wo = Tuple4::vector(1.0, 1.0, 0.0).normalized();
+    let f = ShadingModel::Phong.brdf(&m, wi, wo, normal_v);
+    assert!(cmp_color(Color::new(0.5 / PI, 0.5 / PI, 0.5 / PI), f));
+    assert_eq!(Color::new(0.0, 0.0, 0.0), ShadingModel::Phong.brdf(&m, -wi, wo, normal_v));
+  }
+
+  #[test]
+  fn implements_ggx_terms() {
+    // D integrates to one over the projected hemisphere; check the peak value instead
+    assert!((ggx_distribution(1.0, 0.5) - 1.0 / (PI * 0.25)).abs() < 0.0001);
+    assert_eq!(0.0, ggx_distribution(-0.5, 0.5));
+    assert!((smith_g1(1.0, 0.5) - 1.0).abs() < 0.0001);
+    assert!(smith_g1(0.1, 0.5) < smith_g1(0.5, 0.5));
+    assert_eq!(Color::new(0.04, 0.04, 0.04), schlick_fresnel(Color::new(0.04, 0.04, 0.04), 1.0));
+    assert_eq!(white(), schlick_fresnel(Color::new(0.04, 0.04, 0.04), 0.0));
+  }
+
+  #[test]
+  fn implements_ggx() {
+    let m = Material { color: Color::new(0.5, 0.5, 0.5), ..Material::default() };
+    let normal_v = Tuple4::vector(0.0, 1.0, 0.0);
+    let light_v = Tuple4::vector(0.0, 1.0, 1.0).normalized();
+    let eye_v = Tuple4::vector(0.0, 1.0, -1.0).normalized();
+
+    // rougher surfaces spread the highlight and lower its peak
+    let smooth = ShadingModel::Ggx { roughness: 0.2, metallic: 0.0 }.reflect(&m, light_v, eye_v, normal_v, white());
+    let rough = ShadingModel::Ggx { roughness: 0.8, metallic: 0.0 }.reflect(&m, light_v, eye_v, normal_v, white());
+    assert!(smooth.r() > rough.r());
+
+    // metals have no diffuse lobe
+    let off_peak = Tuple4::vector(0.0, 1.0, 0.1).normalized();
+    let metal = ShadingModel::Ggx { roughness: 0.2, metallic: 1.0 }.reflect(&m, light_v, off_peak, normal_v, white());
+    let dielectric = ShadingModel::Ggx { roughness: 0.2, metallic: 0.0 }.reflect(&m, light_v, off_peak, normal_v, white());
+    assert!(metal.r() < 0.05 && dielectric.r() > 0.2, "{} {}", metal, dielectric);
+
+    // colored metals tint their reflections
+    let gold = Material { color: Color::new(1.0, 0.8, 0.3), ..m };
+    let tinted = ShadingModel::Ggx { roughness: 0.3, metallic: 1.0 }.reflect(&gold, light_v, eye_v, normal_v, white());
+    assert!(tinted.r() > tinted.g() && tinted.g() > tinted.b());
+  }
+
+  #[test]
+  fn implements_ggx_reciprocity() {
+    let m = Material::default();
+    let model = ShadingModel::Ggx { roughness: 0.4, metallic: 0.3 };
+    let normal_v = Tuple4::vector(0.0, 1.0, 0.0);
+    let a = Tuple4::vector(0.3, 1.0, 0.2).normalized();
+    let b = Tuple4::vector(-0.6, 0.7, -0.1).normalized();
+    let ab = model.reflect(&m, a, b, normal_v, white()) * (1.0 / Tuple4::dot(a, normal_v));
+    let ba = model.reflect(&m, b, a, normal_v, white()) * (1.0 / Tuple4::dot(b, normal_v));
+    assert!(cmp_color(ab, ba), "{} {}", ab, ba);
+  }
+
+  #[test]
+  fn implements_sample_and_pdf() {
+    let normal_v = Tuple4::vector(0.0, 1.0, 0.0);
+    let wo = Tuple4::vector(0.4, 1.0, 0.0).normalized();
+    let m = Material { color: Color::new(0.6, 0.6, 0.6), shininess: 30.0, ..Material::default() };
+    let models = [ShadingModel::Phong, ShadingModel::BlinnPhong, ShadingModel::Ggx { roughness: 0.3, metallic: 0.2 }];
+    for model in models {
+      let mut rng = Rng::new(2);
+      // the importance sampled estimate of the albedo matches a uniform estimate
+      let (mut sampled, mut uniform) = (0.0, 0.0);
+      let n = 20000;
+      for _ in 0..n {
+        if let Some(wi) = model.sample(&m, wo, normal_v, &mut rng) {
+          let pdf = model.pdf(&m, wo, wi, normal_v);
+          assert!(pdf > 0.0);
+          sampled += model.brdf(&m, wi, wo, normal_v).g() * Tuple4::dot(wi, normal_v) / pdf;
+        }
+        let z = rng.next_f32();
+        let r = (1.0 - z * z).sqrt();
+        let phi = 2.0 * PI * rng.next_f32();
+        let wi = Tuple4::vector(r * phi.cos(), z, r * phi.sin());
+        uniform += model.brdf(&m, wi, wo, normal_v).g() * z * 2.0 * PI;
+      }
+      let (sampled, uniform) = (sampled / n as f32, uniform / n as f32);
+      assert!((sampled - uniform).abs() < 0.05 * uniform, "{:?} {} {}", model, sampled, uniform);
+    }
+    assert_eq!(0.0, ShadingModel::Phong.pdf(&m, wo, -normal_v, normal_v));
+  }
+
+  #[test]
+  fn implements_pdf_normalization() {
+    // integrating the pdf over the hemisphere gives at most one
+    let normal_v = Tuple4::vector(0.0, 1.0, 0.0);
+    let wo = Tuple4::vector(0.2, 1.0, 0.1).normalized();
+    let m = Material { shininess: 10.0, ..Material::default() };
+    let models = [ShadingModel::Phong, ShadingModel::BlinnPhong, ShadingModel::Ggx { roughness: 0.5, metallic: 0.0 }];
+    for model in models {
+      let mut rng = Rng::new(3);
+      let n = 20000;
+      let mut total = 0.0;
+      for _ in 0..n {
+        let z = rng.next_f32();
+        let r = (1.0 - z * z).sqrt();
+        let phi = 2.0 * PI * rng.next_f32();
+        total += model.pdf(&m, wo, Tuple4::vector(r * phi.cos(), z, r * phi.sin()), normal_v) * 2.0 * PI;
+      }
+      let total = total / n as f32;
+      assert!(total > 0.9 && total < 1.03, "{:?} {}", model, total);
+    }
+  }
+}
@@ -0,0 +1,206 @@
+extern crate rusty_math as rm;
+
+use crate::color::Color;
+use crate::directional_light::DirectionalLight;
+use rm::Tuple4;
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Extraterrestrial solar illuminance in kilolux, matching the kcd/m^2 units of
+/// the sky model's luminance.
+const SUN_ILLUMINANCE: f32 = 128.0;
+
+/// Analytic clear-sky model after Preetham, Shirley and Smits (1999).
+///
+/// Angles are in radians: `sun_elevation` above the horizon and `sun_azimuth`
+/// around +y, starting at +z and turning towards +x. `turbidity` ranges from
+/// about 2 (very clear) to 10 (hazy). Luminance comes out in kcd/m^2 and is
+/// multiplied by `scale` to bring it into the renderer's range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhysicalSky {
+  pub sun_elevation: f32,
+  pub sun_azimuth: f32,
+  pub turbidity: f32,
+  pub scale: f32
+}
+
+impl PhysicalSky {
+  pub fn new(sun_elevation: f32, sun_azimuth: f32, turbidity: f32) -> Self {
+    Self { sun_elevation, sun_azimuth, turbidity, scale: 0.1 }
+  }
+
+  /// Unit vector pointing towards the sun.
+  pub fn sun_direction(&self) -> Tuple4 {
+    let (sin_el, cos_el) = self.sun_elevation.sin_cos();
+    let (sin_az, cos_az) = self.sun_azimuth.sin_cos();
+    Tuple4::vector(cos_el * sin_az, sin_el, cos_el * cos_az)
+  }
+
+  /// Directional light for the sun, dimmed and reddened by Rayleigh and aerosol
+  /// extinction along its path through the atmosphere.
+  pub fn sun_light(&self) -> DirectionalLight {
+    let zenith = FRAC_PI_2 - self.sun_elevation.clamp(0.0, FRAC_PI_2);
+    let zenith_degrees = zenith.to_degrees();
+    // Kasten's relative optical air mass
+    let air_mass = 1.0 / (zenith.cos() + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+    let turbidity = self.turbidity();
+    let beta = 0.04608 * turbidity - 0.04586;
+    // representative wavelengths in micrometers for the red, green and blue primaries
+    let transmittance = |lambda: f32| {
+      let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+      let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+      rayleigh * aerosol
+    };
+    // radiance of a white Lambertian surface is E / pi under the renderer's light convention
+    let intensity = SUN_ILLUMINANCE * self.scale / PI;
+    let color = Color::new(transmittance(0.680), transmittance(0.550), transmittance(0.440)) * intensity;
+    DirectionalLight::new(-self.sun_direction(), color)
+  }
+
+  /// Linear RGB radiance seen looking along `direction`. Directions below the
+  /// horizon see the horizon's color.
+  pub fn radiance(&self, direction: Tuple4) -> Color {
+    let mut d = direction.normalized();
+    if d.y() < 0.0 {
+      let horizontal = Tuple4::vector(d.x(), 0.0, d.z());
+      d = if horizontal.length() > 1e-6 { horizontal.normalized() } else { Tuple4::vector(0.0, 0.0, 1.0) };
+    }
+    let cos_theta = d.y().max(0.001);
+    let theta_sun = FRAC_PI_2 - self.sun_elevation.clamp(0.0, FRAC_PI_2);
+    let gamma = Tuple4::dot(d, self.sun_direction()).clamp(-1.0, 1.0).acos();
+    let t = self.turbidity();
+
+    let luminance_coefficients = [
+      0.1787 * t - 1.4630,
+      -0.3554 * t + 0.4275,
+      -0.0227 * t + 5.3251,
+      0.1206 * t - 2.5771,
+      -0.0670 * t + 0.3703
+    ];
+    let x_coefficients = [
+      -0.0193 * t - 0.2592,
+      -0.0665 * t + 0.0008,
+      -0.0004 * t + 0.2125,
+      -0.0641 * t - 0.8989,
+      -0.0033 * t + 0.0452
+    ];
+    let y_coefficients = [
+      -0.0167 * t - 0.2608,
+      -0.0950 * t + 0.0092,
+      -0.0079 * t + 0.2102,
+      -0.0441 * t - 1.6537,
+      -0.0109 * t + 0.0529
+    ];
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+    let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+    let (s, s2, s3) = (theta_sun, theta_sun * theta_sun, theta_sun * theta_sun * theta_sun);
+    let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s) +
+      t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394) +
+      (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+    let zenith_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s) +
+      t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516) +
+      (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+    let relative = |coefficients: &[f32; 5]| {
+      perez(coefficients, cos_theta, gamma) / perez(coefficients, 1.0, theta_sun)
+    };
+    let luminance = zenith_luminance * relative(&luminance_coefficients) * self.scale;
+    let x = zenith_x * relative(&x_coefficients);
+    let y = zenith_y * relative(&y_coefficients);
+    xyy_to_rgb(x, y, luminance)
+  }
+
+  fn turbidity(&self) -> f32 {
+    self.turbidity.clamp(1.7, 10.0)
+  }
+}
+
+/// Perez et al. sky luminance distribution.
+fn perez(coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+  let [a, b, c, d, e] = *coefficients;
+  let cos_gamma = gamma.cos();
+  (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+/// CIE xyY chromaticity and luminance to linear sRGB.
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Color {
+  if y <= 0.0 {
+    return Color::new(0.0, 0.0, 0.0);
+  }
+  let cx = x / y * luminance;
+  let cz = (1.0 - x - y) / y * luminance;
+  Color::new(
+    3.2406 * cx - 1.5372 * luminance - 0.4986 * cz,
+    -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz,
+    0.0557 * cx - 0.2040 * luminance + 1.0570 * cz
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{xyy_to_rgb, PhysicalSky, Tuple4};
+  use crate::light::Light;
+  use crate::rng::Rng;
+  use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+  #[test]
+  fn implements_sun_direction() {
+    let sky = PhysicalSky::new(0.0, FRAC_PI_2, 3.0);
+    let d = sky.sun_direction();
+    assert!((d - Tuple4::vector(1.0, 0.0, 0.0)).length() < 1e-6);
+    let sky = PhysicalSky::new(FRAC_PI_4, 0.0, 3.0);
+    assert!((sky.sun_direction() - Tuple4::vector(0.0, 1.0, 1.0).normalized()).length() < 1e-6);
+  }
+
+  #[test]
+  fn implements_xyy_to_rgb() {
+    // D65 white point
+    let white = xyy_to_rgb(0.3127, 0.3290, 1.0);
+    assert!((white.r() - 1.0).abs() < 0.01 && (white.g() - 1.0).abs() < 0.01 && (white.b() - 1.0).abs() < 0.01);
+    assert!((white.luminance() - 1.0).abs() < 0.01);
+  }
+
+  #[test]
+  fn implements_zenith_luminance() {
+    // Preetham's zenith luminance for T = 3 and the sun at 45 degrees is about 7.33 kcd/m^2
+    let sky = PhysicalSky::new(FRAC_PI_4, 0.0, 3.0);
+    let zenith = sky.radiance(Tuple4::vector(0.0, 1.0, 0.0));
+    assert!((zenith.luminance() - 0.733).abs() < 0.01, "{}", zenith);
+    // a clear sky is blue overhead
+    assert!(zenith.b() > zenith.g() && zenith.g() > zenith.r());
+  }
+
+  #[test]
+  fn implements_sky_distribution() {
+    let sky = PhysicalSky::new(0.3, 1.0, 3.0);
+    let sun = sky.sun_direction();
+    let near_sun = sky.radiance(Tuple4::vector(sun.x(), sun.y() + 0.1, sun.z()));
+    let away = sky.radiance(Tuple4::vector(-sun.x(), sun.y() + 0.1, -sun.z()));
+    assert!(near_sun.luminance() > 2.0 * away.luminance());
+    // hazier skies are brighter and less saturated overhead
+    let up = Tuple4::vector(0.0, 1.0, 0.0);
+    let hazy = PhysicalSky::new(0.3, 1.0, 8.0).radiance(up);
+    let clear = sky.radiance(up);
+    assert!(hazy.luminance() > clear.luminance());
+    assert!(hazy.r() / hazy.b() > clear.r() / clear.b());
+    // below the horizon the horizon color is seen
+    assert_eq!(sky.radiance(Tuple4::vector(1.0, 0.0, 0.0)), sky.radiance(Tuple4::vector(1.0, -0.5, 0.0)));
+    let down = sky.radiance(Tuple4::vector(0.0, -1.0, 0.0));
+    assert!(down.luminance().is_finite() && down.luminance() > 0.0);
+  }
+
+  #[test]
+  fn implements_sun_light() {
+    let high = PhysicalSky::new(1.2, 0.5, 3.0).sun_light();
+    let low = PhysicalSky::new(0.05, 0.5, 3.0).sun_light();
+    let sample = high.samples(Tuple4::point(0.0, 0.0, 0.0), &mut Rng::new(0))[0];
+    assert!((sample.direction - PhysicalSky::new(1.2, 0.5, 3.0).sun_direction()).length() < 1e-6);
+    // the sun dims and reddens towards the horizon
+    assert!(high.intensity.g() > low.intensity.g());
+    assert!(low.intensity.r() / low.intensity.b() > high.intensity.r() / high.intensity.b());
+    // overhead the sun lights a white surface far more than the sky does
+    let zenith = PhysicalSky::new(1.2, 0.5, 3.0).radiance(Tuple4::vector(0.0, 1.0, 0.0));
+    assert!(high.intensity.g() > 2.0 * zenith.g(), "{} {}", high.intensity, zenith);
+  }
+}
@@ -2,14 +2,19 @@ extern crate rusty_math as rm;
 
 use crate::intersection::{Intersect, IntersectionRecord};
 use crate::material::{Material};
+use crate::motion::AnimatedTransform;
 use crate::ray::Ray;
-use rm::{Matrix4, Tuple4};
+use crate::sampler::uniform_sample_sphere;
+use rm::{Matrix4, MatrixInversionError, Tuple4};
+
+use std::f32::consts::PI;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Sphere {
   origin: Tuple4,
   radius: f32,
   transform: Option<Matrix4>,
+  motion: Option<AnimatedTransform>,
   material: Option<Material>
 }
 
@@ -19,6 +24,7 @@ impl Sphere {
       origin: origin,
       radius: radius,
       transform: None,
+      motion: None,
       material: Some(material)
     }
   }
@@ -28,12 +34,29 @@ impl Sphere {
       origin: Tuple4::point(0.0, 0.0, 0.0),
       radius: 1.0,
       transform: None,
+      motion: None,
       material: None
     }
   }
 
   pub fn set_transform(&mut self, t: Matrix4) {
     self.transform = Some(t);
+    self.motion = None;
+  }
+
+  /// Moves the sphere from `start` at time 0 to `end` at time 1.
+  pub fn set_motion(&mut self, start: Matrix4, end: Matrix4) -> Result<(), MatrixInversionError> {
+    self.motion = Some(AnimatedTransform::new(start, end)?);
+    self.transform = Some(start);
+    Ok(())
+  }
+
+  /// Transform at `time`; static spheres always use `get_transform`.
+  pub fn transform_at(&self, time: f32) -> Matrix4 {
+    match self.motion {
+      Some(motion) => motion.at(time),
+      None => self.get_transform()
+    }
   }
 
   pub fn set_material(&mut self, mat: Material) {
@@ -41,7 +64,11 @@ impl Sphere {
   }
 
   pub fn normal_at(&self, p: Tuple4) -> Tuple4 {
-    let mut inv_transform = self.get_transform().inverse().unwrap();
+    self.normal_at_time(p, 0.0)
+  }
+
+  pub fn normal_at_time(&self, p: Tuple4, time: f32) -> Tuple4 {
+    let mut inv_transform = self.transform_at(time).inverse().unwrap();
     let obj_n = inv_transform * p - self.origin;
 
     inv_transform.transpose();
@@ -51,6 +78,15 @@ impl Sphere {
     world_n
   }
 
+  /// Spherical texture coordinates of `p` at `time`: `u` runs once around the
+  /// y axis of the untransformed sphere, `v` from its bottom pole (0) to its top (1).
+  pub fn uv_at(&self, p: Tuple4, time: f32) -> (f32, f32) {
+    let obj_p = self.transform_at(time).inverse().unwrap() * p - self.origin;
+    let theta = obj_p.x().atan2(obj_p.z());
+    let phi = (obj_p.y() / obj_p.length()).clamp(-1.0, 1.0).acos();
+    (1.0 - (theta / (2.0 * PI) + 0.5), 1.0 - phi / PI)
+  }
+
   pub fn get_transform(&self) -> Matrix4 {
     match self.transform {
       Some(t) => t,
@@ -64,11 +100,36 @@ impl Sphere {
       None => Material::default()
     }
   }
+
+  /// Point on the surface at `time` from uniform `u`, `v`, uniformly distributed
+  /// over the untransformed sphere, together with its density per unit of world area.
+  pub fn sample_surface(&self, u: f32, v: f32, time: f32) -> (Tuple4, f32) {
+    let obj_p = self.origin + uniform_sample_sphere(u, v) * self.radius;
+    let world_p = self.transform_at(time) * obj_p;
+    (world_p, self.area_pdf(world_p, time))
+  }
+
+  /// Density of `sample_surface` at `p` per unit of world area. The transform
+  /// scales surface elements by `|det M| * |M^-T n|` for object normal `n`.
+  pub fn area_pdf(&self, p: Tuple4, time: f32) -> f32 {
+    let transform = self.transform_at(time);
+    let inv_transform = match transform.inverse() {
+      Ok(inv) => inv,
+      Err(_) => return 0.0
+    };
+    let mut obj_n = inv_transform * p - self.origin;
+    obj_n.set_w(0.0);
+    obj_n.normalize();
+    let mut world_n = inv_transform.transposed() * obj_n;
+    world_n.set_w(0.0);
+    let scale = transform.determinant().abs() * world_n.length();
+    1.0 / (4.0 * PI * self.radius * self.radius * scale)
+  }
 }
 
 impl Intersect for Sphere {
   fn intersects(&self, r: Ray) -> Option<Vec<IntersectionRecord<Self>>> {
-    let transform = self.get_transform();
+    let transform = self.transform_at(r.time);
     let inv_transform = match transform.inverse() {
       Ok(inv) => inv,
       Err(_) => return None,
@@ -116,6 +177,7 @@ mod tests {
         origin: o,
         radius: r,
         transform: None,
+        motion: None,
         material: Some(mat)
       },
       Sphere::new(o, r, mat)
@@ -132,6 +194,41 @@ mod tests {
     assert_eq!(t, s.transform.unwrap());
   }
 
+  #[test]
+  fn implements_sample_surface() {
+    let mut s = Sphere::new(Tuple4::point(0.0, 0.0, 0.0), 2.0, Material::default());
+    s.set_transform(Matrix4::translation(1.0, 0.0, 0.0) * Matrix4::scaling(3.0, 3.0, 3.0));
+    let (p, pdf) = s.sample_surface(0.3, 0.7, 0.0);
+    assert!(((p - Tuple4::point(1.0, 0.0, 0.0)).length() - 6.0).abs() < 1e-4);
+    assert!((pdf - 1.0 / (4.0 * PI * 36.0)).abs() < 1e-6);
+
+    // stretched spheres have a denser distribution where they are squashed
+    let mut s = Sphere::unit();
+    s.set_transform(Matrix4::scaling(2.0, 1.0, 1.0));
+    let pole = s.area_pdf(Tuple4::point(2.0, 0.0, 0.0), 0.0);
+    let equator = s.area_pdf(Tuple4::point(0.0, 1.0, 0.0), 0.0);
+    assert!((pole - 1.0 / (4.0 * PI)).abs() < 1e-6);
+    assert!((equator - 1.0 / (8.0 * PI)).abs() < 1e-6);
+  }
+
+  #[test]
+  fn implements_motion() {
+    let mut s = Sphere::unit();
+    s.set_motion(Matrix4::identity(), Matrix4::translation(4.0, 0.0, 0.0)).unwrap();
+    assert_eq!(Matrix4::identity(), s.get_transform());
+    assert_eq!(Matrix4::translation(2.0, 0.0, 0.0), s.transform_at(0.5));
+
+    let r = Ray::new(Tuple4::point(2.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    assert_eq!(None, s.intersects(r));
+    let ts: Vec<f32> = s.intersects(Ray::with_time(r.origin, r.direction, 0.5)).unwrap().iter().map(|i| i.t).collect();
+    assert_eq!(vec![4.0, 6.0], ts);
+    assert_eq!(Tuple4::vector(-1.0, 0.0, 0.0), s.normal_at_time(Tuple4::point(1.0, 0.0, 0.0), 0.5));
+
+    s.set_transform(Matrix4::identity());
+    assert_eq!(Matrix4::identity(), s.transform_at(0.5));
+    assert!(s.set_motion(Matrix4::identity(), Matrix4::scaling(0.0, 1.0, 1.0)).is_err());
+  }
+
   #[test]
   fn implements_set_material() {
     let mut s = Sphere::unit();
@@ -250,4 +347,25 @@ mod tests {
     let coord = f32::sqrt(2.0) / 2.0;
     assert!(test_utils::cmp_tuple4(Tuple4::vector(0.0, 0.97014, -0.24254), s.normal_at(Tuple4::point(0.0, coord, coord))));
   }
+
+  #[test]
+  fn implements_uv_at() {
+    let mut s = Sphere::unit();
+    let cases = [
+      ((0.0, 0.0, -1.0), (0.0, 0.5)),
+      ((1.0, 0.0, 0.0), (0.25, 0.5)),
+      ((0.0, 0.0, 1.0), (0.5, 0.5)),
+      ((-1.0, 0.0, 0.0), (0.75, 0.5)),
+      ((0.0, 1.0, 0.0), (0.5, 1.0)),
+      ((0.0, -1.0, 0.0), (0.5, 0.0))
+    ];
+    for ((x, y, z), (u, v)) in cases {
+      let (su, sv) = s.uv_at(Tuple4::point(x, y, z), 0.0);
+      assert!((su - u).abs() < 0.0001 && (sv - v).abs() < 0.0001, "{:?}", (x, y, z));
+    }
+    // coordinates stick to the surface as it moves
+    s.set_transform(Matrix4::translation(0.0, 3.0, 0.0) * Matrix4::scaling(2.0, 2.0, 2.0));
+    let (u, v) = s.uv_at(Tuple4::point(2.0, 3.0, 0.0), 0.0);
+    assert!((u - 0.25).abs() < 0.0001 && (v - 0.5).abs() < 0.0001);
+  }
 }
@@ -0,0 +1,108 @@
+extern crate rusty_math as rm;
+
+use crate::Color;
+use crate::light::{Light, LightSample};
+use crate::rng::Rng;
+use rm::Tuple4;
+
+/// Point light restricted to a cone around `direction`. Intensity fades out
+/// smoothly over the outer `penumbra` radians of the cone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+  pub position: Tuple4,
+  pub direction: Tuple4,
+  /// Half angle of the cone, in radians.
+  pub cone_angle: f32,
+  pub penumbra: f32,
+  pub intensity: Color
+}
+
+impl SpotLight {
+  pub fn new(position: Tuple4, direction: Tuple4, cone_angle: f32, penumbra: f32, intensity: Color) -> Self {
+    Self {
+      position,
+      direction: direction.normalized(),
+      cone_angle,
+      penumbra: penumbra.clamp(0.0, cone_angle),
+      intensity
+    }
+  }
+
+  /// Cone attenuation in [0, 1] for light travelling along `to_point`.
+  pub fn falloff(&self, to_point: Tuple4) -> f32 {
+    let cos_theta = Tuple4::dot(self.direction, to_point.normalized());
+    let cos_outer = self.cone_angle.cos();
+    let cos_inner = (self.cone_angle - self.penumbra).cos();
+    if cos_theta >= cos_inner {
+      1.0
+    } else if cos_theta <= cos_outer {
+      0.0
+    } else {
+      let t = (cos_theta - cos_outer) / (cos_inner - cos_outer);
+      t * t * (3.0 - 2.0 * t)
+    }
+  }
+}
+
+impl Light for SpotLight {
+  fn samples(&self, point: Tuple4, _rng: &mut Rng) -> Vec<LightSample> {
+    let v = self.position - point;
+    vec![LightSample {
+      direction: v.normalized(),
+      distance: v.length(),
+      intensity: self.intensity * self.falloff(-v)
+    }]
+  }
+
+  fn intensity(&self) -> Color {
+    self.intensity
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Color, Light, Rng, SpotLight, Tuple4};
+  use std::f32::consts::FRAC_PI_4;
+
+  fn spot() -> SpotLight {
+    SpotLight::new(
+      Tuple4::point(0.0, 10.0, 0.0),
+      Tuple4::vector(0.0, -1.0, 0.0),
+      FRAC_PI_4,
+      FRAC_PI_4 / 2.0,
+      Color::new(1.0, 1.0, 1.0)
+    )
+  }
+
+  #[test]
+  fn implements_constructor() {
+    let light = SpotLight::new(Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 0.0, 3.0), 0.5, 1.0, Color::new(1.0, 1.0, 1.0));
+    assert_eq!(Tuple4::vector(0.0, 0.0, 1.0), light.direction);
+    assert_eq!(0.5, light.penumbra);
+  }
+
+  #[test]
+  fn implements_falloff() {
+    let light = spot();
+    assert_eq!(1.0, light.falloff(Tuple4::vector(0.0, -1.0, 0.0)));
+    assert_eq!(1.0, light.falloff(Tuple4::vector(0.3, -1.0, 0.0)));
+    assert_eq!(0.0, light.falloff(Tuple4::vector(1.0, -0.9, 0.0)));
+    assert_eq!(0.0, light.falloff(Tuple4::vector(0.0, 1.0, 0.0)));
+    let partial = light.falloff(Tuple4::vector(0.8, -1.0, 0.0));
+    assert!(partial > 0.0 && partial < 1.0);
+    assert!(light.falloff(Tuple4::vector(0.7, -1.0, 0.0)) > partial);
+  }
+
+  #[test]
+  fn implements_samples() {
+    let light = spot();
+    let mut rng = Rng::new(0);
+    let samples = light.samples(Tuple4::point(0.0, 0.0, 0.0), &mut rng);
+    assert_eq!(Tuple4::vector(0.0, 1.0, 0.0), samples[0].direction);
+    assert_eq!(10.0, samples[0].distance);
+    assert_eq!(Color::new(1.0, 1.0, 1.0), samples[0].intensity);
+
+    let samples = light.samples(Tuple4::point(20.0, 0.0, 0.0), &mut rng);
+    assert_eq!(Color::new(0.0, 0.0, 0.0), samples[0].intensity);
+  }
+}
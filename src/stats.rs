@@ -0,0 +1,154 @@
+use std::fmt;
+use std::ops::Add;
+use std::time::Duration;
+
+#[cfg(feature = "stats")]
+use std::cell::Cell;
+
+/// Ray and intersection counts. They are only collected when the crate is
+/// built with the `stats` feature; otherwise `record` compiles to nothing and
+/// every count stays zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Counters {
+  pub camera_rays: u64,
+  pub shadow_rays: u64,
+  /// Rays continuing a path off a surface.
+  pub reflection_rays: u64,
+  /// Ray-object intersection tests.
+  pub intersection_tests: u64
+}
+
+impl Counters {
+  pub const ENABLED: bool = cfg!(feature = "stats");
+}
+
+impl Add for Counters {
+  type Output = Self;
+
+  fn add(self, rhs: Self) -> Self::Output {
+    Self {
+      camera_rays: self.camera_rays + rhs.camera_rays,
+      shadow_rays: self.shadow_rays + rhs.shadow_rays,
+      reflection_rays: self.reflection_rays + rhs.reflection_rays,
+      intersection_tests: self.intersection_tests + rhs.intersection_tests
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Counter {
+  CameraRay,
+  ShadowRay,
+  ReflectionRay,
+  IntersectionTest
+}
+
+#[cfg(feature = "stats")]
+thread_local! {
+  static COUNTERS: Cell<Counters> = Cell::new(Counters::default());
+}
+
+/// Adds `n` to `counter` for the calling thread.
+#[inline(always)]
+pub fn record(counter: Counter, n: u64) {
+  #[cfg(feature = "stats")]
+  COUNTERS.with(|cell| {
+    let mut counters = cell.get();
+    match counter {
+      Counter::CameraRay => counters.camera_rays += n,
+      Counter::ShadowRay => counters.shadow_rays += n,
+      Counter::ReflectionRay => counters.reflection_rays += n,
+      Counter::IntersectionTest => counters.intersection_tests += n
+    }
+    cell.set(counters);
+  });
+  #[cfg(not(feature = "stats"))]
+  let _ = (counter, n);
+}
+
+/// Counts recorded on the calling thread since the last call.
+pub fn take() -> Counters {
+  #[cfg(feature = "stats")]
+  return COUNTERS.with(Cell::take);
+  #[cfg(not(feature = "stats"))]
+  Counters::default()
+}
+
+/// CPU time consumed by the whole process so far, where the platform reports it.
+pub fn cpu_time() -> Option<Duration> {
+  // fields 14 and 15 of /proc/self/stat, after the parenthesised command name,
+  // are user and system time in ticks of 1/100 s
+  let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+  let mut fields = stat.get(stat.rfind(')')? + 2..)?.split(' ').skip(11);
+  let user: u64 = fields.next()?.parse().ok()?;
+  let system: u64 = fields.next()?.parse().ok()?;
+  Some(Duration::from_millis((user + system) * 10))
+}
+
+/// Summary of a finished render.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+  pub counters: Counters,
+  pub wall_time: Duration,
+  /// Process CPU time spent while rendering, if known.
+  pub cpu_time: Option<Duration>
+}
+
+impl fmt::Display for RenderStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "Render statistics")?;
+    writeln!(f, "  wall time           {:.2} s", self.wall_time.as_secs_f64())?;
+    match self.cpu_time {
+      Some(cpu_time) => writeln!(f, "  CPU time            {:.2} s", cpu_time.as_secs_f64())?,
+      None => writeln!(f, "  CPU time            unavailable on this platform")?
+    }
+    if Counters::ENABLED {
+      let c = &self.counters;
+      writeln!(f, "  camera rays         {}", c.camera_rays)?;
+      writeln!(f, "  shadow rays         {}", c.shadow_rays)?;
+      writeln!(f, "  reflection rays     {}", c.reflection_rays)?;
+      writeln!(f, "  refraction rays     none, no material refracts")?;
+      writeln!(f, "  intersection tests  {}", c.intersection_tests)?;
+    } else {
+      writeln!(f, "  ray counters        not collected; build with --features stats")?;
+    }
+    write!(f, "  BVH node visits     none, objects are tested without a BVH")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{cpu_time, record, take, Counter, Counters, RenderStats};
+  use std::time::Duration;
+
+  #[test]
+  fn implements_counters() {
+    take();
+    record(Counter::CameraRay, 1);
+    record(Counter::ShadowRay, 2);
+    record(Counter::IntersectionTest, 5);
+    record(Counter::IntersectionTest, 1);
+    let counters = take();
+    if Counters::ENABLED {
+      assert_eq!(Counters { camera_rays: 1, shadow_rays: 2, reflection_rays: 0, intersection_tests: 6 }, counters);
+    } else {
+      assert_eq!(Counters::default(), counters);
+    }
+    assert_eq!(Counters::default(), take());
+    // other threads keep their own counts
+    std::thread::spawn(|| record(Counter::ReflectionRay, 3)).join().unwrap();
+    assert_eq!(Counters::default(), take());
+  }
+
+  #[test]
+  fn implements_report() {
+    let stats = RenderStats { wall_time: Duration::from_millis(1500), cpu_time: None, ..RenderStats::default() };
+    let report = stats.to_string();
+    assert!(report.contains("wall time           1.50 s"));
+    assert!(report.contains("BVH node visits"));
+    assert!(report.contains("refraction rays") == Counters::ENABLED);
+    if cfg!(target_os = "linux") {
+      assert!(cpu_time().is_some());
+    }
+  }
+}
@@ -0,0 +1,24 @@
+extern crate rusty_math as rm;
+
+use crate::color::Color;
+use crate::material::Material;
+use crate::point_light::PointLight;
+use crate::sphere::Sphere;
+use crate::world::World;
+use rm::{Matrix4, Tuple4};
+
+pub fn cmp_color(a: Color, b: Color) -> bool {
+  (a.r() - b.r()).abs() < 0.0001 &&
+  (a.g() - b.g()).abs() < 0.0001 &&
+  (a.b() - b.b()).abs() < 0.0001
+}
+
+/// Two concentric spheres lit from the top left front, as used throughout the tests.
+pub fn default_world() -> World {
+  let light = PointLight::new(Tuple4::point(-10.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
+  let mut s1 = Sphere::unit();
+  s1.set_material(Material { color: Color::new(0.8, 1.0, 0.6), diffuse: 0.7, specular: 0.2, ..Material::default() });
+  let mut s2 = Sphere::unit();
+  s2.set_transform(Matrix4::scaling(0.5, 0.5, 0.5));
+  World::new(vec![s1, s2], vec![Box::new(light)])
+}
@@ -0,0 +1,326 @@
+extern crate rusty_math as rm;
+
+use crate::color::{Color, BLACK};
+use crate::environment::Background;
+use crate::intersection::{hit, Intersect, IntersectionRecord};
+use crate::light::{Light, LightSample, RadianceSample};
+use crate::lighting::{ambient, direct_shadowed};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rng::Rng;
+use crate::sphere::Sphere;
+use crate::stats::{self, Counter};
+use rm::Tuple4;
+
+use std::cmp::Ordering;
+
+/// Offset applied along the surface normal before casting secondary rays, to
+/// keep them from re-intersecting the surface they start on.
+pub const EPSILON: f32 = 0.001;
+
+/// Shading information at the closest hit of a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SurfaceHit {
+  /// Index of the hit object in `World::objects`.
+  pub object: usize,
+  pub t: f32,
+  pub point: Tuple4,
+  /// `point` nudged along the normal, used as origin for secondary rays.
+  pub over_point: Tuple4,
+  pub eye: Tuple4,
+  /// Surface normal, flipped to face the eye.
+  pub normal: Tuple4,
+  pub inside: bool,
+  pub material: Material,
+  /// Time of the ray that found the hit.
+  pub time: f32
+}
+
+#[derive(Debug)]
+pub struct World {
+  pub objects: Vec<Sphere>,
+  pub lights: Vec<Box<dyn Light>>,
+  /// Intensity of the ambient light, applied once regardless of light count.
+  pub ambient: Color,
+  /// Seen by rays that miss every object.
+  pub background: Background
+}
+
+impl World {
+  pub fn new(objects: Vec<Sphere>, lights: Vec<Box<dyn Light>>) -> Self {
+    Self { objects, lights, ambient: Color::new(1.0, 1.0, 1.0), background: Background::default() }
+  }
+
+  /// All intersections of `r` with the world's objects, sorted by `t`.
+  pub fn intersect(&self, r: Ray) -> Vec<IntersectionRecord<'_, Sphere>> {
+    stats::record(Counter::IntersectionTest, self.objects.len() as u64);
+    let mut intersections: Vec<IntersectionRecord<Sphere>> = self.objects.iter()
+      .filter_map(|o| o.intersects(r))
+      .flatten()
+      .collect();
+    intersections.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(Ordering::Equal));
+    intersections
+  }
+
+  /// Whether anything blocks the segment between `point` and `light_position`.
+  pub fn is_shadowed(&self, light_position: Tuple4, point: Tuple4) -> bool {
+    let v = light_position - point;
+    self.is_occluded(point, v.normalized(), v.length(), 0.0)
+  }
+
+  /// Whether anything lies within `distance` of `point` along `direction` at `time`.
+  pub fn is_occluded(&self, point: Tuple4, direction: Tuple4, distance: f32, time: f32) -> bool {
+    stats::record(Counter::ShadowRay, 1);
+    let intersections = self.intersect(Ray::with_time(point, direction, time));
+    match hit(&intersections) {
+      Some(h) => h.t < distance,
+      None => false
+    }
+  }
+
+  /// Closest hit of `r` in front of its origin.
+  pub fn hit(&self, r: Ray) -> Option<SurfaceHit> {
+    let intersections = self.intersect(r);
+    let hit_record = hit(&intersections)?;
+    let object = self.objects.iter().position(|o| std::ptr::eq(o, hit_record.o))?;
+    let point = r.point_at(hit_record.t);
+    let eye = -r.direction;
+    let mut normal = hit_record.o.normal_at_time(point, r.time);
+    let inside = Tuple4::dot(normal, eye) < 0.0;
+    if inside {
+      normal = -normal;
+    }
+    Some(SurfaceHit {
+      object,
+      t: hit_record.t,
+      point,
+      over_point: point + normal * EPSILON,
+      eye,
+      normal,
+      inside,
+      material: hit_record.o.get_material(),
+      time: r.time
+    })
+  }
+
+  pub fn color_at(&self, r: Ray, rng: &mut Rng) -> Color {
+    match self.hit(r) {
+      Some(surface) => self.shade_hit(&surface, rng),
+      None => self.background.radiance(r.direction)
+    }
+  }
+
+  /// Emission, ambient and direct lighting from every light, with shadows.
+  pub fn shade_hit(&self, surface: &SurfaceHit, rng: &mut Rng) -> Color {
+    let material = &surface.material;
+    let base = material.emission + ambient(material, self.ambient);
+    self.lights.iter().fold(base, |color, light| {
+      let visible = |s: &LightSample| !self.is_occluded(surface.over_point, s.direction, s.distance, surface.time);
+      color + direct_shadowed(material, light.as_ref(), surface.over_point, surface.eye, surface.normal, visible, rng)
+    })
+  }
+
+  /// Indices of the objects with an emissive material, which integrators can
+  /// sample like lights.
+  pub fn emitters(&self) -> Vec<usize> {
+    (0..self.objects.len()).filter(|&i| self.objects[i].get_material().emission != BLACK).collect()
+  }
+
+  /// Point on the surface of emitter `object` at `time` for next-event
+  /// estimation from `point`. Emissive surfaces radiate from both sides.
+  pub fn sample_emitter(&self, object: usize, point: Tuple4, time: f32, rng: &mut Rng) -> Option<RadianceSample> {
+    let emitter = &self.objects[object];
+    let (light_point, area_pdf) = emitter.sample_surface(rng.next_f32(), rng.next_f32(), time);
+    let v = light_point - point;
+    let distance = v.length();
+    let direction = v.normalized();
+    let pdf = solid_angle_pdf(area_pdf, distance, Tuple4::dot(emitter.normal_at_time(light_point, time), direction));
+    if pdf <= 0.0 || !pdf.is_finite() {
+      return None;
+    }
+    Some(RadianceSample {
+      direction,
+      // stop short of the emitter so it does not shadow itself
+      distance: distance - EPSILON,
+      radiance: emitter.get_material().emission,
+      pdf: Some(pdf)
+    })
+  }
+
+  /// Solid angle density with which `sample_emitter` from `origin` picks the
+  /// point of `surface`.
+  pub fn emitter_pdf(&self, origin: Tuple4, surface: &SurfaceHit) -> f32 {
+    let v = surface.point - origin;
+    let distance = v.length();
+    let area_pdf = self.objects[surface.object].area_pdf(surface.point, surface.time);
+    solid_angle_pdf(area_pdf, distance, Tuple4::dot(surface.normal, v.normalized()))
+  }
+}
+
+fn solid_angle_pdf(area_pdf: f32, distance: f32, cos_light: f32) -> f32 {
+  let cos_light = cos_light.abs();
+  if cos_light <= f32::EPSILON {
+    return 0.0;
+  }
+  area_pdf * distance * distance / cos_light
+}
+
+impl Default for World {
+  fn default() -> Self {
+    Self::new(vec![], vec![])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Background, Color, Ray, Rng, World, BLACK};
+  use crate::light::Attenuation;
+  use crate::material::Material;
+  use crate::point_light::PointLight;
+  use crate::sphere::Sphere;
+  use crate::test_utils::{cmp_color, default_world};
+  use rm::{Matrix4, Tuple4};
+
+  #[test]
+  fn implements_intersect() {
+    let w = default_world();
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    let ts: Vec<f32> = w.intersect(r).iter().map(|i| i.t).collect();
+    assert_eq!(vec![4.0, 4.5, 5.5, 6.0], ts);
+  }
+
+  #[test]
+  fn implements_color_at() {
+    let w = default_world();
+    let mut rng = Rng::new(0);
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 1.0, 0.0));
+    assert_eq!(BLACK, w.color_at(r, &mut rng));
+
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    assert!(cmp_color(Color::new(0.38066, 0.47583, 0.2855), w.color_at(r, &mut rng)));
+
+    // without lights only ambient remains, counted once as with any number of lights
+    let w = World::new(w.objects, vec![]);
+    assert!(cmp_color(Color::new(0.08, 0.1, 0.06), w.color_at(r, &mut rng)));
+  }
+
+  #[test]
+  fn implements_hit() {
+    let w = default_world();
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    let surface = w.hit(r).unwrap();
+    assert_eq!(4.0, surface.t);
+    assert_eq!(Tuple4::point(0.0, 0.0, -1.0), surface.point);
+    assert_eq!(Tuple4::vector(0.0, 0.0, -1.0), surface.normal);
+    assert_eq!(Tuple4::vector(0.0, 0.0, -1.0), surface.eye);
+    assert!(!surface.inside);
+    assert!(surface.over_point.z() < surface.point.z());
+
+    let r = Ray::new(Tuple4::point(0.0, 0.0, 0.0), Tuple4::vector(0.0, 0.0, 1.0));
+    let surface = w.hit(r).unwrap();
+    assert_eq!(0.5, surface.t);
+    assert!(surface.inside);
+    assert_eq!(Tuple4::vector(0.0, 0.0, -1.0), surface.normal);
+
+    assert_eq!(None, w.hit(Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 1.0, 0.0))));
+  }
+
+  #[test]
+  fn implements_is_shadowed() {
+    let w = default_world();
+    let light_position = Tuple4::point(-10.0, 10.0, -10.0);
+    assert!(!w.is_shadowed(light_position, Tuple4::point(0.0, 10.0, 0.0)));
+    assert!(w.is_shadowed(light_position, Tuple4::point(10.0, -10.0, 10.0)));
+    assert!(!w.is_shadowed(light_position, Tuple4::point(-20.0, 20.0, -20.0)));
+    assert!(!w.is_shadowed(light_position, Tuple4::point(-2.0, 2.0, -2.0)));
+  }
+
+  #[test]
+  fn test_color_at_background() {
+    let mut w = default_world();
+    w.background = Background::Gradient { bottom: BLACK, top: Color::new(0.2, 0.4, 1.0) };
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 2.0, 0.0));
+    assert_eq!(Color::new(0.2, 0.4, 1.0), w.color_at(r, &mut Rng::new(0)));
+  }
+
+  #[test]
+  fn test_color_at_in_shadow() {
+    let light = PointLight::new(Tuple4::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+    let s1 = Sphere::unit();
+    let mut s2 = Sphere::unit();
+    s2.set_transform(Matrix4::translation(0.0, 0.0, 10.0));
+    let w = World::new(vec![s1, s2], vec![Box::new(light)]);
+    let r = Ray::new(Tuple4::point(0.0, 0.0, 5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    assert!(cmp_color(Color::new(0.1, 0.1, 0.1), w.color_at(r, &mut Rng::new(0))));
+  }
+
+  #[test]
+  fn test_color_at_with_multiple_lights() {
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    let single = default_world().color_at(r, &mut Rng::new(0));
+
+    let mut w = default_world();
+    w.lights.push(Box::new(PointLight::new(Tuple4::point(10.0, -10.0, -10.0), Color::new(1.0, 1.0, 1.0))));
+    let double = w.color_at(r, &mut Rng::new(0));
+    // ambient (0.08, 0.1, 0.06) is only counted once
+    let ambient = Color::new(0.08, 0.1, 0.06);
+    assert!(cmp_color((single - ambient) * 2.0 + ambient, double));
+
+    w.ambient = Color::new(0.0, 0.0, 0.0);
+    assert!(cmp_color((single - ambient) * 2.0, w.color_at(r, &mut Rng::new(0))));
+  }
+
+  #[test]
+  fn test_color_at_with_attenuated_light() {
+    let mut w = default_world();
+    w.ambient = Color::new(0.0, 0.0, 0.0);
+    let r = Ray::new(Tuple4::point(0.0, 0.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    let unattenuated = w.color_at(r, &mut Rng::new(0));
+
+    let mut light = PointLight::new(Tuple4::point(-10.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
+    light.set_attenuation(Attenuation::InverseSquare);
+    w.lights = vec![Box::new(light)];
+    let distance = (Tuple4::point(-10.0, 10.0, -10.0) - Tuple4::point(0.0, 0.0, -1.0)).length();
+    let attenuated = w.color_at(r, &mut Rng::new(0));
+    assert!((attenuated.g() - unattenuated.g() / (distance * distance)).abs() < 0.0001);
+  }
+
+  fn emissive_world() -> World {
+    let mut lamp = Sphere::unit();
+    lamp.set_transform(Matrix4::translation(0.0, 3.0, 0.0) * Matrix4::scaling(0.5, 0.5, 0.5));
+    lamp.set_material(Material::emissive(Color::new(4.0, 2.0, 1.0)));
+    World::new(vec![Sphere::unit(), lamp], vec![])
+  }
+
+  #[test]
+  fn test_color_at_emissive() {
+    let w = emissive_world();
+    let r = Ray::new(Tuple4::point(0.0, 3.0, -5.0), Tuple4::vector(0.0, 0.0, 1.0));
+    assert_eq!(Color::new(4.0, 2.0, 1.0), w.color_at(r, &mut Rng::new(0)));
+
+    let mut w = w;
+    w.lights.push(Box::new(PointLight::new(Tuple4::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0))));
+    assert!(cmp_color(Color::new(4.0, 2.0, 1.0), w.color_at(r, &mut Rng::new(0))));
+  }
+
+  #[test]
+  fn implements_emitters() {
+    let w = emissive_world();
+    assert_eq!(vec![1], w.emitters());
+    assert_eq!(Vec::<usize>::new(), default_world().emitters());
+
+    let point = Tuple4::point(0.0, 1.5, 0.0);
+    let mut rng = Rng::new(1);
+    for _ in 0..32 {
+      let sample = w.sample_emitter(1, point, 0.0, &mut rng).unwrap();
+      assert_eq!(Color::new(4.0, 2.0, 1.0), sample.radiance);
+      let surface = w.hit(Ray::new(point, sample.direction)).unwrap();
+      assert_eq!(1, surface.object);
+      // samples on the far side are hidden behind the near one
+      if (surface.t - sample.distance).abs() < 0.01 {
+        let pdf = sample.pdf.unwrap();
+        assert!((w.emitter_pdf(point, &surface) - pdf).abs() < 0.001 * pdf);
+      }
+    }
+  }
+}
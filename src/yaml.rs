@@ -0,0 +1,333 @@
+use crate::errors::SceneError;
+
+/// Parsed value together with the 1-based line and column where it starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+  pub value: Value,
+  pub line: usize,
+  pub column: usize
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  Scalar(String),
+  List(Vec<Node>),
+  /// Entries in file order, each key with its own node for error positions.
+  Map(Vec<(Node, Node)>)
+}
+
+impl Node {
+  pub fn error(&self, message: impl Into<String>) -> SceneError {
+    SceneError::Parse { line: self.line, column: self.column, message: message.into() }
+  }
+
+  pub fn as_scalar(&self) -> Option<&str> {
+    match &self.value {
+      Value::Scalar(s) => Some(s),
+      _ => None
+    }
+  }
+
+  pub fn as_list(&self) -> Option<&[Node]> {
+    match &self.value {
+      Value::List(items) => Some(items),
+      _ => None
+    }
+  }
+
+  pub fn as_map(&self) -> Option<&[(Node, Node)]> {
+    match &self.value {
+      Value::Map(entries) => Some(entries),
+      _ => None
+    }
+  }
+
+  /// Value stored under `key`, if this is a mapping containing it.
+  pub fn get(&self, key: &str) -> Option<&Node> {
+    self.as_map()?.iter().find(|(k, _)| k.as_scalar() == Some(key)).map(|(_, v)| v)
+  }
+}
+
+#[derive(Clone, Debug)]
+struct Line {
+  number: usize,
+  indent: usize,
+  text: String
+}
+
+/// Parses the YAML subset used by scene files: block sequences (`- item`),
+/// block mappings (`key: value`), flow sequences (`[a, [b, c]]`), plain
+/// scalars and `#` comments. Indentation must use spaces.
+pub fn parse(source: &str) -> Result<Node, SceneError> {
+  let mut lines = Vec::new();
+  for (index, raw) in source.lines().enumerate() {
+    let number = index + 1;
+    let content = match raw.find('#') {
+      Some(i) => &raw[..i],
+      None => raw
+    };
+    let indent = content.len() - content.trim_start_matches(' ').len();
+    if content[indent..].starts_with('\t') {
+      return Err(SceneError::Parse { line: number, column: indent + 1, message: "tabs are not allowed in indentation".into() });
+    }
+    let text = content.trim_end();
+    if text.len() > indent {
+      lines.push(Line { number, indent, text: text[indent..].to_string() });
+    }
+  }
+  let mut parser = Parser { lines, position: 0 };
+  let indent = match parser.lines.first() {
+    Some(line) => line.indent,
+    None => return Ok(Node { value: Value::List(Vec::new()), line: 1, column: 1 })
+  };
+  let root = parser.block(indent)?;
+  match parser.lines.get(parser.position) {
+    Some(line) => Err(parser.error_at(line, 0, "unexpected indentation")),
+    None => Ok(root)
+  }
+}
+
+struct Parser {
+  lines: Vec<Line>,
+  position: usize
+}
+
+impl Parser {
+  fn error_at(&self, line: &Line, offset: usize, message: &str) -> SceneError {
+    SceneError::Parse { line: line.number, column: line.indent + offset + 1, message: message.into() }
+  }
+
+  fn current(&self) -> Option<&Line> {
+    self.lines.get(self.position)
+  }
+
+  fn block(&mut self, indent: usize) -> Result<Node, SceneError> {
+    let line = self.current().unwrap();
+    if is_sequence_item(&line.text) {
+      self.sequence(indent)
+    } else {
+      self.mapping(indent)
+    }
+  }
+
+  fn sequence(&mut self, indent: usize) -> Result<Node, SceneError> {
+    let first = self.current().unwrap();
+    let (line, column) = (first.number, first.indent + 1);
+    let mut items = Vec::new();
+    while let Some(current) = self.current() {
+      if current.indent != indent || !is_sequence_item(&current.text) {
+        break;
+      }
+      let current = current.clone();
+      let rest = current.text[1..].trim_start();
+      let offset = current.text.len() - rest.len();
+      if rest.is_empty() {
+        self.position += 1;
+        items.push(self.nested(&current, indent)?);
+      } else if split_key(rest).is_some() {
+        // a mapping starting on the item's line continues at the key's column
+        let item_indent = current.indent + offset;
+        self.lines[self.position] = Line { number: current.number, indent: item_indent, text: rest.to_string() };
+        items.push(self.mapping(item_indent)?);
+      } else {
+        self.position += 1;
+        items.push(inline(rest, current.number, current.indent + offset + 1)?);
+      }
+    }
+    Ok(Node { value: Value::List(items), line, column })
+  }
+
+  fn mapping(&mut self, indent: usize) -> Result<Node, SceneError> {
+    let first = self.current().unwrap();
+    let (line, column) = (first.number, first.indent + 1);
+    let mut entries: Vec<(Node, Node)> = Vec::new();
+    while let Some(current) = self.current() {
+      if current.indent < indent || (current.indent == indent && is_sequence_item(&current.text)) {
+        break;
+      }
+      let current = current.clone();
+      if current.indent > indent {
+        return Err(self.error_at(&current, 0, "unexpected indentation"));
+      }
+      let (key, rest) = match split_key(&current.text) {
+        Some(split) => split,
+        None => return Err(self.error_at(&current, 0, "expected 'key: value'"))
+      };
+      let key_node = Node { value: Value::Scalar(key.to_string()), line: current.number, column: current.indent + 1 };
+      if entries.iter().any(|(k, _)| k.as_scalar() == Some(key)) {
+        return Err(key_node.error(format!("duplicate key '{}'", key)));
+      }
+      self.position += 1;
+      let value = if rest.trim().is_empty() {
+        self.nested(&current, indent)?
+      } else {
+        let offset = current.text.len() - rest.trim_start().len();
+        inline(rest.trim(), current.number, current.indent + offset + 1)?
+      };
+      entries.push((key_node, value));
+    }
+    Ok(Node { value: Value::Map(entries), line, column })
+  }
+
+  /// Block value following a line that ends in `key:` or `-`.
+  fn nested(&mut self, parent: &Line, indent: usize) -> Result<Node, SceneError> {
+    match self.current() {
+      Some(next) if next.indent > indent => {
+        let next_indent = next.indent;
+        self.block(next_indent)
+      },
+      // like YAML, a mapping's sequence value may sit at the key's indentation
+      Some(next) if next.indent == indent && is_sequence_item(&next.text) && !is_sequence_item(&parent.text) => {
+        self.sequence(indent)
+      },
+      _ => Err(self.error_at(parent, parent.text.len(), "expected a value"))
+    }
+  }
+}
+
+fn is_sequence_item(text: &str) -> bool {
+  text == "-" || text.starts_with("- ")
+}
+
+/// Splits `key: value` at the first colon followed by a space or the end of the
+/// line. Flow sequences are never keys.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+  if text.starts_with('[') {
+    return None;
+  }
+  let bytes = text.as_bytes();
+  (0..bytes.len())
+    .find(|&i| bytes[i] == b':' && (i + 1 == bytes.len() || bytes[i + 1] == b' '))
+    .map(|i| (text[..i].trim_end(), &text[i + 1..]))
+    .filter(|(key, _)| !key.is_empty())
+}
+
+/// Value written on a single line, starting at `column`.
+fn inline(text: &str, line: usize, column: usize) -> Result<Node, SceneError> {
+  let chars: Vec<char> = text.chars().collect();
+  let mut position = 0;
+  let node = flow(&chars, &mut position, line, column)?;
+  skip_spaces(&chars, &mut position);
+  if position < chars.len() {
+    return Err(SceneError::Parse { line, column: column + position, message: "unexpected characters after value".into() });
+  }
+  Ok(node)
+}
+
+fn skip_spaces(chars: &[char], position: &mut usize) {
+  while *position < chars.len() && chars[*position] == ' ' {
+    *position += 1;
+  }
+}
+
+fn flow(chars: &[char], position: &mut usize, line: usize, column: usize) -> Result<Node, SceneError> {
+  skip_spaces(chars, position);
+  let start = *position;
+  let error = |at: usize, message: &str| SceneError::Parse { line, column: column + at, message: message.into() };
+  if chars.get(start) != Some(&'[') {
+    while *position < chars.len() && !matches!(chars[*position], ',' | '[' | ']') {
+      *position += 1;
+    }
+    let scalar: String = chars[start..*position].iter().collect();
+    if scalar.trim().is_empty() {
+      return Err(error(start, "expected a value"));
+    }
+    return Ok(Node { value: Value::Scalar(scalar.trim().to_string()), line, column: column + start });
+  }
+
+  *position += 1;
+  let mut items = Vec::new();
+  skip_spaces(chars, position);
+  if chars.get(*position) == Some(&']') {
+    *position += 1;
+    return Ok(Node { value: Value::List(items), line, column: column + start });
+  }
+  loop {
+    items.push(flow(chars, position, line, column)?);
+    skip_spaces(chars, position);
+    match chars.get(*position) {
+      Some(',') => *position += 1,
+      Some(']') => {
+        *position += 1;
+        return Ok(Node { value: Value::List(items), line, column: column + start });
+      },
+      Some(_) => return Err(error(*position, "expected ',' or ']'")),
+      None => return Err(error(start, "unclosed '['"))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{parse, Node, Value};
+  use crate::errors::SceneError;
+
+  fn scalar(node: &Node) -> &str {
+    node.as_scalar().unwrap()
+  }
+
+  fn error_position(source: &str) -> (usize, usize, String) {
+    match parse(source) {
+      Err(SceneError::Parse { line, column, message }) => (line, column, message),
+      other => panic!("expected a parse error, got {:?}", other)
+    }
+  }
+
+  #[test]
+  fn implements_block_structure() {
+    let source = "\
+# a comment
+- add: camera   # trailing comment
+  width: 100
+  from: [ -6, 6.5, -10 ]
+
+- define: base
+  value:
+    color: [1, 1, 1]
+  transform:
+  - [ scale, 2, 2, 2 ]
+  -
+    - translate
+    - 1
+";
+    let root = parse(source).unwrap();
+    let items = root.as_list().unwrap();
+    assert_eq!(2, items.len());
+    assert_eq!("camera", scalar(items[0].get("add").unwrap()));
+    assert_eq!("100", scalar(items[0].get("width").unwrap()));
+    let from = items[0].get("from").unwrap();
+    assert_eq!((4, 9), (from.line, from.column));
+    let coordinates: Vec<&str> = from.as_list().unwrap().iter().map(scalar).collect();
+    assert_eq!(vec!["-6", "6.5", "-10"], coordinates);
+    assert_eq!((4, 11), (from.as_list().unwrap()[0].line, from.as_list().unwrap()[0].column));
+
+    let value = items[1].get("value").unwrap();
+    assert_eq!("1", scalar(&value.get("color").unwrap().as_list().unwrap()[2]));
+    let transform = items[1].get("transform").unwrap().as_list().unwrap();
+    assert_eq!("scale", scalar(&transform[0].as_list().unwrap()[0]));
+    assert_eq!(vec!["translate", "1"], transform[1].as_list().unwrap().iter().map(scalar).collect::<Vec<_>>());
+    assert_eq!(None, items[1].get("missing"));
+  }
+
+  #[test]
+  fn implements_flow_sequences() {
+    let root = parse("a: [[1, 2], [], x y]").unwrap();
+    let list = root.get("a").unwrap().as_list().unwrap();
+    assert_eq!(3, list.len());
+    assert_eq!(Value::List(Vec::new()), list[1].value);
+    assert_eq!("x y", scalar(&list[2]));
+    assert_eq!(Value::List(Vec::new()), parse("").unwrap().value);
+  }
+
+  #[test]
+  fn reports_error_positions() {
+    assert_eq!((2, 12, "expected ',' or ']'".into()), error_position("a: 1\nb: [1, [2] 3]"));
+    assert_eq!((1, 4, "unclosed '['".into()), error_position("a: [1, [2]"));
+    assert_eq!((2, 3, "unexpected indentation".into()), error_position("a: 1\n  b: 2"));
+    assert_eq!((3, 3, "duplicate key 'b'".into()), error_position("- a: 1\n  b: 2\n  b: 3"));
+    assert_eq!((1, 3, "expected a value".into()), error_position("a:\nb: 1"));
+    assert_eq!((1, 1, "expected 'key: value'".into()), error_position("just text"));
+    assert_eq!((1, 11, "unexpected characters after value".into()), error_position("a: [1, 2] 3"));
+    assert_eq!((2, 1, "tabs are not allowed in indentation".into()), error_position("a:\n\tb: 1"));
+  }
+}
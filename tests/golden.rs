@@ -0,0 +1,107 @@
+//! Golden-image regression tests: renders small reference scenes and compares
+//! them to the PPMs checked in under `tests/golden`. Renders depend only on
+//! the scene and settings and are quantised like the references before the
+//! comparison, so the tolerance only has to absorb floating point differences
+//! between platforms.
+//!
+//! After an intended change to the images, rewrite the references with
+//!
+//!     UPDATE_GOLDEN=1 cargo test --test golden
+//!
+//! and look over the new files before committing them. On a mismatch the
+//! render and a difference heatmap are written next to the build's temporary
+//! files, named in the failure message.
+
+extern crate rusty_math as rm;
+
+use rusty_cage::canvas::Canvas;
+use rusty_cage::color::{Color, Transfer};
+use rusty_cage::environment::Background;
+use rusty_cage::integrator::Integrator;
+use rusty_cage::post::{post_process, Effect};
+use rusty_cage::render::{render, RenderSettings};
+use rusty_cage::sampler::SamplePattern;
+use rusty_cage::scene::Scene;
+use rusty_cage::sky::PhysicalSky;
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Lowest PSNR in dB and highest mean FLIP error a render may show against its reference.
+const MIN_PSNR: f32 = 40.0;
+const MAX_FLIP: f32 = 0.01;
+
+fn spheres() -> Scene {
+  let mut scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/spheres.yaml")).unwrap();
+  scene.camera.set_size(80, 40);
+  scene
+}
+
+fn settings(integrator: Integrator) -> RenderSettings {
+  RenderSettings { samples_per_pixel: 8, pattern: SamplePattern::Jittered, seed: 1, integrator, ..RenderSettings::default() }
+}
+
+/// `image` as it reads back from an sRGB PPM: clipped to the display range
+/// and rounded to 8 bits.
+fn quantised(image: &Canvas) -> Canvas {
+  let channel = |v: f32| Transfer::Srgb.decode((Transfer::Srgb.encode(v) * 255.0).round().clamp(0.0, 255.0) / 255.0);
+  image.map(|c| Color::new(channel(c.r()), channel(c.g()), channel(c.b())))
+}
+
+fn check(name: &str, image: &Canvas) {
+  let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.ppm", name));
+  if env::var_os("UPDATE_GOLDEN").is_some() {
+    image.save(golden.to_str().unwrap()).unwrap();
+    return;
+  }
+  let reference = Canvas::import_ppm(golden.to_str().unwrap(), Transfer::Srgb)
+    .unwrap_or_else(|e| panic!("cannot read {}: {}; run with UPDATE_GOLDEN=1 to create it", golden.display(), e));
+  assert_eq!(
+    (reference.width(), reference.height()), (image.width(), image.height()),
+    "{} is not the size of the '{}' render", golden.display(), name
+  );
+  let comparison = quantised(image).compare(&reference);
+  if comparison.psnr < MIN_PSNR || comparison.flip > MAX_FLIP {
+    let output = |suffix: &str| -> PathBuf { Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.{}.ppm", name, suffix)) };
+    image.save(output("actual").to_str().unwrap()).unwrap();
+    image.difference_heatmap(&reference).save(output("diff").to_str().unwrap()).unwrap();
+    panic!(
+      "'{}' differs from {}: {}; see {} and {}",
+      name, golden.display(), comparison, output("actual").display(), output("diff").display()
+    );
+  }
+}
+
+#[test]
+fn matches_whitted_spheres() {
+  let scene = spheres();
+  check("spheres-whitted", &render(&scene.camera, &scene.world, &settings(Integrator::Whitted)));
+}
+
+#[test]
+fn matches_path_traced_spheres() {
+  let scene = spheres();
+  check("spheres-path", &render(&scene.camera, &scene.world, &settings(Integrator::path_tracer())));
+}
+
+#[test]
+fn matches_sky() {
+  let mut scene = spheres();
+  let sky = PhysicalSky::new(0.35, 2.4, 3.0);
+  scene.world.lights = vec![Box::new(sky.sun_light())];
+  scene.world.background = Background::Sky(sky);
+  scene.world.objects[0].set_transform(rm::Matrix4::scaling(100.0, 0.01, 100.0));
+  check("spheres-sky", &render(&scene.camera, &scene.world, &settings(Integrator::path_tracer())));
+}
+
+#[test]
+fn matches_post_processing() {
+  let scene = spheres();
+  let image = render(&scene.camera, &scene.world, &settings(Integrator::Whitted));
+  let effects = [
+    Effect::Bloom { threshold: 0.8, radius: 0.02, intensity: 0.5 },
+    Effect::Vignette { strength: 0.5, falloff: 2.0 },
+    Effect::Grain { strength: 0.05, seed: 1 }
+  ];
+  check("spheres-post", &post_process(&image, &effects));
+}